@@ -0,0 +1,851 @@
+//! Read-only access to APFS containers: the object map of the latest checkpoint, and the file
+//! system trees of its unencrypted volumes.
+
+use std::collections::HashMap;
+use std::io;
+
+use super::disk_image::{malformed, Device, Entry, EntryKind, Volume};
+
+const CONTAINER_MAGIC: &[u8] = b"NXSB";
+const VOLUME_MAGIC: &[u8] = b"APSB";
+const OBJECT_TYPE_MASK: u32 = 0x0000_ffff;
+const OBJECT_TYPE_CONTAINER: u32 = 0x1;
+/// Set on the checkpoint descriptor block count if the area is not contiguous.
+const CHECKPOINT_NON_CONTIGUOUS: u32 = 0x8000_0000;
+const MAX_VOLUMES: usize = 100;
+
+const NODE_ROOT: u16 = 0x1;
+const NODE_LEAF: u16 = 0x2;
+const NODE_FIXED_KV_SIZE: u16 = 0x4;
+const NODE_HEADER_LEN: usize = 56;
+/// Trailing `btree_info_t` of root nodes.
+const TREE_INFO_LEN: usize = 40;
+/// Marks a value slot without a value.
+const NO_VALUE: u16 = 0xffff;
+const MAX_TREE_DEPTH: u16 = 16;
+
+const OMAP_VALUE_DELETED: u32 = 0x1;
+
+const INCOMPAT_CASE_INSENSITIVE: u64 = 0x1;
+const INCOMPAT_NORMALIZATION_INSENSITIVE: u64 = 0x8;
+const FS_UNENCRYPTED: u64 = 0x1;
+
+const OBJECT_ID_MASK: u64 = 0x0fff_ffff_ffff_ffff;
+const RECORD_TYPE_SHIFT: u32 = 60;
+const RECORD_INODE: u64 = 3;
+const RECORD_XATTR: u64 = 4;
+const RECORD_FILE_EXTENT: u64 = 8;
+const RECORD_DIR: u64 = 9;
+
+const ROOT_DIR_ID: u64 = 2;
+const DIR_TYPE_MASK: u16 = 0x000f;
+const DT_DIR: u16 = 4;
+const DT_REG: u16 = 8;
+const DT_LNK: u16 = 10;
+const INODE_XFIELD_DSTREAM: u8 = 8;
+const EXTENT_LEN_MASK: u64 = 0x00ff_ffff_ffff_ffff;
+const XATTR_DATA_EMBEDDED: u16 = 0x2;
+const SYMLINK_XATTR: &[u8] = b"com.apple.fs.symlink";
+/// BSD flag of files whose contents are stored compressed in an extended attribute.
+const UF_COMPRESSED: u32 = 0x20;
+
+pub(super) fn is_apfs(device: &Device) -> bool {
+    device
+        .read_at(32, 4)
+        .map(|magic| magic == CONTAINER_MAGIC)
+        .unwrap_or(false)
+}
+
+/// Opens every volume of the container on `device`.
+pub(super) fn volumes(device: &Device) -> io::Result<Vec<Box<dyn Volume + '_>>> {
+    let container = Container::open(device)?;
+    let superblock = &container.superblock;
+
+    let omap = container.object_map(le64(superblock, 160))?;
+    let max_volumes = (le32(superblock, 180) as usize).min(MAX_VOLUMES);
+
+    let mut volumes: Vec<Box<dyn Volume>> = vec![];
+    for i in 0..max_volumes {
+        let oid = le64(superblock, 184 + 8 * i);
+        if oid == 0 {
+            continue;
+        }
+
+        let address = *omap
+            .get(&oid)
+            .ok_or_else(|| malformed("volume missing from object map"))?;
+        volumes.push(Box::new(ApfsVolume::open(&container, address)?));
+    }
+
+    Ok(volumes)
+}
+
+/// Callback receiving the key and value of a B-tree record.
+type RecordVisitor<'a> = dyn FnMut(&[u8], &[u8]) -> io::Result<()> + 'a;
+
+struct Container<'a> {
+    device: &'a Device,
+    block_size: usize,
+    /// Container superblock of the latest checkpoint.
+    superblock: Vec<u8>,
+}
+
+impl<'a> Container<'a> {
+    fn open(device: &'a Device) -> io::Result<Self> {
+        let block_size = le32(&device.read_at(0, 40)?, 36) as usize;
+        if !(4096..=65536).contains(&block_size) || !block_size.is_power_of_two() {
+            return Err(malformed("invalid block size"));
+        }
+
+        let mut container = Container {
+            device,
+            block_size,
+            superblock: vec![],
+        };
+
+        // Block zero holds a copy of the superblock, which may be older than the one in the
+        // checkpoint descriptor area.
+        let mut superblock = container.block(0)?;
+        let descriptor_blocks = le32(&superblock, 104);
+        let descriptor_base = le64(&superblock, 112);
+
+        if descriptor_blocks & CHECKPOINT_NON_CONTIGUOUS == 0 {
+            for i in 0..descriptor_blocks as u64 {
+                let block = container.block(descriptor_base + i)?;
+                if &block[32..36] == CONTAINER_MAGIC
+                    && le32(&block, 24) & OBJECT_TYPE_MASK == OBJECT_TYPE_CONTAINER
+                    && has_valid_checksum(&block)
+                    && le64(&block, 16) > le64(&superblock, 16)
+                {
+                    superblock = block;
+                }
+            }
+        }
+
+        container.superblock = superblock;
+        Ok(container)
+    }
+
+    fn block(&self, address: u64) -> io::Result<Vec<u8>> {
+        self.device.read_at(
+            address
+                .checked_mul(self.block_size as u64)
+                .ok_or_else(|| malformed("invalid block address"))?,
+            self.block_size,
+        )
+    }
+
+    /// Reads the object map at `address`, mapping each virtual object identifier to the
+    /// address of its latest version.
+    fn object_map(&self, address: u64) -> io::Result<HashMap<u64, u64>> {
+        let omap = self.block(address)?;
+        let max_xid = le64(&self.superblock, 16);
+
+        let mut versions = HashMap::new();
+        self.scan_tree(le64(&omap, 48), None, &mut |key, value| {
+            if key.len() < 16 || value.len() < 16 {
+                return Err(malformed("invalid object map record"));
+            }
+
+            let (oid, xid) = (le64(key, 0), le64(key, 8));
+            if xid > max_xid || le32(value, 0) & OMAP_VALUE_DELETED != 0 {
+                return Ok(());
+            }
+            match versions.get(&oid) {
+                Some(&(latest, _)) if latest >= xid => {}
+                _ => {
+                    versions.insert(oid, (xid, le64(value, 8)));
+                }
+            }
+            Ok(())
+        })?;
+
+        Ok(versions
+            .into_iter()
+            .map(|(oid, (_, address))| (oid, address))
+            .collect())
+    }
+
+    /// Calls `visit` with the key and value of every record in the B-tree whose root node is
+    /// at `address`. Child nodes of a virtual tree are located through `omap`.
+    fn scan_tree(
+        &self,
+        address: u64,
+        omap: Option<&HashMap<u64, u64>>,
+        visit: &mut RecordVisitor,
+    ) -> io::Result<()> {
+        let root = self.block(address)?;
+        // Fixed key and value sizes apply to leaf records; index records hold child addresses.
+        let info = &root[self.block_size - TREE_INFO_LEN..];
+        let fixed_sizes = (le32(info, 8) as usize, le32(info, 12) as usize);
+        self.scan_node(&root, fixed_sizes, omap, MAX_TREE_DEPTH, visit)
+    }
+
+    fn scan_node(
+        &self,
+        node: &[u8],
+        fixed_sizes: (usize, usize),
+        omap: Option<&HashMap<u64, u64>>,
+        depth: u16,
+        visit: &mut RecordVisitor,
+    ) -> io::Result<()> {
+        let flags = le16(node, 32);
+        let level = le16(node, 34);
+        let key_count = le32(node, 36) as usize;
+        let toc_start = NODE_HEADER_LEN + le16(node, 40) as usize;
+        let keys_start = toc_start + le16(node, 42) as usize;
+        let values_end = if flags & NODE_ROOT != 0 {
+            self.block_size - TREE_INFO_LEN
+        } else {
+            self.block_size
+        };
+        let is_leaf = flags & NODE_LEAF != 0;
+        if is_leaf != (level == 0) || depth == 0 {
+            return Err(malformed("invalid B-tree node"));
+        }
+
+        let slice = |start: usize, len: usize| {
+            node.get(start..start + len)
+                .ok_or_else(|| malformed("invalid B-tree record"))
+        };
+
+        for i in 0..key_count {
+            let (key, value_offset, value_len) = if flags & NODE_FIXED_KV_SIZE != 0 {
+                let entry = slice(toc_start + 4 * i, 4)?;
+                let value_len = if is_leaf { fixed_sizes.1 } else { 8 };
+                (
+                    slice(keys_start + le16(entry, 0) as usize, fixed_sizes.0)?,
+                    le16(entry, 2),
+                    value_len,
+                )
+            } else {
+                let entry = slice(toc_start + 8 * i, 8)?;
+                (
+                    slice(
+                        keys_start + le16(entry, 0) as usize,
+                        le16(entry, 2) as usize,
+                    )?,
+                    le16(entry, 4),
+                    le16(entry, 6) as usize,
+                )
+            };
+
+            if value_offset == NO_VALUE {
+                continue;
+            }
+            let value = values_end
+                .checked_sub(value_offset as usize)
+                .ok_or_else(|| malformed("invalid B-tree record"))
+                .and_then(|start| slice(start, value_len))?;
+
+            if is_leaf {
+                visit(key, value)?;
+            } else {
+                if value.len() < 8 {
+                    return Err(malformed("invalid B-tree record"));
+                }
+                let child = le64(value, 0);
+                let address = match omap {
+                    Some(omap) => *omap
+                        .get(&child)
+                        .ok_or_else(|| malformed("B-tree node missing from object map"))?,
+                    None => child,
+                };
+                self.scan_node(&self.block(address)?, fixed_sizes, omap, depth - 1, visit)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct Inode {
+    mode: u16,
+    bsd_flags: u32,
+    /// Identifier of the data stream the file's extents are keyed by.
+    stream_id: u64,
+    size: u64,
+}
+
+struct Extent {
+    offset: u64,
+    len: u64,
+    block: u64,
+}
+
+/// Unencrypted APFS volume, with its file system tree read into memory.
+struct ApfsVolume<'a> {
+    device: &'a Device,
+    block_size: usize,
+    inodes: HashMap<u64, Inode>,
+    /// Names, identifiers and types of the entries of each directory.
+    children: HashMap<u64, Vec<(String, u64, u16)>>,
+    extents: HashMap<u64, Vec<Extent>>,
+    symlinks: HashMap<u64, Vec<u8>>,
+}
+
+impl<'a> ApfsVolume<'a> {
+    fn open(container: &Container<'a>, address: u64) -> io::Result<Self> {
+        let superblock = container.block(address)?;
+        if &superblock[32..36] != VOLUME_MAGIC {
+            return Err(malformed("invalid volume superblock"));
+        }
+        if le64(&superblock, 264) & FS_UNENCRYPTED == 0 {
+            return Err(malformed("encrypted volumes are not supported"));
+        }
+
+        let incompatible_features = le64(&superblock, 56);
+        let hashed_names = incompatible_features
+            & (INCOMPAT_CASE_INSENSITIVE | INCOMPAT_NORMALIZATION_INSENSITIVE)
+            != 0;
+
+        let omap = container.object_map(le64(&superblock, 128))?;
+        let root = *omap
+            .get(&le64(&superblock, 136))
+            .ok_or_else(|| malformed("file system tree missing from object map"))?;
+
+        let mut volume = ApfsVolume {
+            device: container.device,
+            block_size: container.block_size,
+            inodes: HashMap::new(),
+            children: HashMap::new(),
+            extents: HashMap::new(),
+            symlinks: HashMap::new(),
+        };
+
+        container.scan_tree(root, Some(&omap), &mut |key, value| {
+            volume.add_record(key, value, hashed_names)
+        })?;
+
+        Ok(volume)
+    }
+
+    fn add_record(&mut self, key: &[u8], value: &[u8], hashed_names: bool) -> io::Result<()> {
+        if key.len() < 8 {
+            return Err(malformed("invalid file system record"));
+        }
+        let id = le64(key, 0) & OBJECT_ID_MASK;
+        let invalid = || malformed("invalid file system record");
+
+        match le64(key, 0) >> RECORD_TYPE_SHIFT {
+            RECORD_INODE => {
+                if value.len() < 92 {
+                    return Err(invalid());
+                }
+                let size = inode_stream_size(&value[92..]).ok_or_else(invalid)?;
+                self.inodes.insert(
+                    id,
+                    Inode {
+                        mode: le16(value, 80),
+                        bsd_flags: le32(value, 68),
+                        stream_id: le64(value, 8),
+                        size,
+                    },
+                );
+            }
+            RECORD_DIR => {
+                let name = if hashed_names {
+                    let len = le32(key.get(8..12).ok_or_else(invalid)?, 0) & 0x3ff;
+                    key.get(12..12 + len as usize)
+                } else {
+                    let len = le16(key.get(8..10).ok_or_else(invalid)?, 0);
+                    key.get(10..10 + len as usize)
+                }
+                .ok_or_else(invalid)?;
+                if value.len() < 18 {
+                    return Err(invalid());
+                }
+
+                let name = name.strip_suffix(&[0]).unwrap_or(name);
+                self.children.entry(id).or_default().push((
+                    String::from_utf8_lossy(name).into_owned(),
+                    le64(value, 0),
+                    le16(value, 16) & DIR_TYPE_MASK,
+                ));
+            }
+            RECORD_FILE_EXTENT => {
+                if key.len() < 16 || value.len() < 16 {
+                    return Err(invalid());
+                }
+                self.extents.entry(id).or_default().push(Extent {
+                    offset: le64(key, 8),
+                    len: le64(value, 0) & EXTENT_LEN_MASK,
+                    block: le64(value, 8),
+                });
+            }
+            RECORD_XATTR => {
+                let len = le16(key.get(8..10).ok_or_else(invalid)?, 0);
+                let name = key.get(10..10 + len as usize).ok_or_else(invalid)?;
+                if name.strip_suffix(&[0]).unwrap_or(name) != SYMLINK_XATTR {
+                    return Ok(());
+                }
+                if value.len() < 4 || le16(value, 0) & XATTR_DATA_EMBEDDED == 0 {
+                    return Err(malformed("unsupported symlink target"));
+                }
+
+                let target = value
+                    .get(4..4 + le16(value, 2) as usize)
+                    .ok_or_else(invalid)?;
+                self.symlinks
+                    .insert(id, target.strip_suffix(&[0]).unwrap_or(target).to_vec());
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+impl Volume for ApfsVolume<'_> {
+    fn list(&self, dir: Option<&Entry>) -> io::Result<Vec<Entry>> {
+        let dir = dir.map_or(ROOT_DIR_ID, |dir| dir.id);
+
+        let mut entries = vec![];
+        for (name, id, dir_type) in self.children.get(&dir).into_iter().flatten() {
+            let kind = match *dir_type {
+                DT_DIR => EntryKind::Directory,
+                DT_REG => EntryKind::File,
+                DT_LNK => EntryKind::Symlink,
+                _ => continue,
+            };
+            let inode = self
+                .inodes
+                .get(id)
+                .ok_or_else(|| malformed("directory entry without inode"))?;
+
+            entries.push(Entry {
+                name: name.clone(),
+                kind,
+                mode: inode.mode as u32,
+                id: *id,
+            });
+        }
+
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+
+    fn read(&self, entry: &Entry) -> io::Result<Vec<u8>> {
+        if entry.kind == EntryKind::Symlink {
+            return self
+                .symlinks
+                .get(&entry.id)
+                .cloned()
+                .ok_or_else(|| malformed("symlink without target"));
+        }
+
+        let inode = self
+            .inodes
+            .get(&entry.id)
+            .ok_or_else(|| malformed("directory entry without inode"))?;
+        if inode.bsd_flags & UF_COMPRESSED != 0 {
+            return Err(malformed(&format!(
+                "{} is compressed, which is not supported",
+                entry.name
+            )));
+        }
+        if inode.size > self.device.len() {
+            return Err(malformed("file size exceeds volume size"));
+        }
+
+        // Ranges without an extent, or with a zero block address, are holes.
+        let mut data = vec![0; inode.size as usize];
+        for extent in self.extents.get(&inode.stream_id).into_iter().flatten() {
+            if extent.block == 0 || extent.offset >= inode.size {
+                continue;
+            }
+
+            let len = extent.len.min(inode.size - extent.offset) as usize;
+            let offset = extent.offset as usize;
+            data[offset..offset + len].copy_from_slice(
+                &self.device.read_at(
+                    extent
+                        .block
+                        .checked_mul(self.block_size as u64)
+                        .ok_or_else(|| malformed("invalid block address"))?,
+                    len,
+                )?,
+            );
+        }
+
+        Ok(data)
+    }
+}
+
+/// Size of the inode's data stream, from its extended fields. Zero if it has none, e.g. for
+/// directories and empty files.
+fn inode_stream_size(xfields: &[u8]) -> Option<u64> {
+    if xfields.len() < 4 {
+        return Some(0);
+    }
+
+    let count = le16(xfields, 0) as usize;
+    let mut data_offset = 4 + 4 * count;
+
+    for i in 0..count {
+        let header = xfields.get(4 + 4 * i..8 + 4 * i)?;
+        let len = le16(header, 2) as usize;
+        if header[0] == INODE_XFIELD_DSTREAM {
+            return xfields
+                .get(data_offset..data_offset + 8)
+                .map(|size| le64(size, 0));
+        }
+        // Field data is padded to a multiple of eight bytes.
+        data_offset += (len + 7) & !7;
+    }
+
+    Some(0)
+}
+
+/// Verifies the Fletcher-64 checksum in the first eight bytes of an object.
+fn has_valid_checksum(block: &[u8]) -> bool {
+    const MODULUS: u64 = 0xffff_ffff;
+
+    let (mut sum1, mut sum2) = (0u64, 0u64);
+    for word in block[8..].chunks_exact(4) {
+        sum1 = (sum1 + le32(word, 0) as u64) % MODULUS;
+        sum2 = (sum2 + sum1) % MODULUS;
+    }
+
+    let check1 = MODULUS - (sum1 + sum2) % MODULUS;
+    let check2 = MODULUS - (sum1 + check1) % MODULUS;
+    le64(block, 0) == check2 << 32 | check1
+}
+
+fn le16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn le32(data: &[u8], offset: usize) -> u32 {
+    let mut value = [0; 4];
+    value.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_le_bytes(value)
+}
+
+fn le64(data: &[u8], offset: usize) -> u64 {
+    let mut value = [0; 8];
+    value.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(value)
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use std::collections::HashMap;
+    use std::io::Write;
+
+    use super::*;
+    use crate::archive::disk_image::Device;
+
+    const BLOCK: usize = 4096;
+    const VOLUME_OID: u64 = 1026;
+    const TREE_OID: u64 = 1028;
+    const LEAF_OID: u64 = 1029;
+
+    type Record = (Vec<u8>, Vec<u8>);
+
+    fn put16(block: &mut [u8], offset: usize, value: u16) {
+        block[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn put32(block: &mut [u8], offset: usize, value: u32) {
+        block[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn put64(block: &mut [u8], offset: usize, value: u64) {
+        block[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// Stores the Fletcher-64 checksum of the block in its first eight bytes.
+    fn set_checksum(block: &mut [u8]) {
+        const MODULUS: u64 = 0xffff_ffff;
+
+        let (mut sum1, mut sum2) = (0u64, 0u64);
+        for word in block[8..].chunks_exact(4) {
+            sum1 = (sum1 + le32(word, 0) as u64) % MODULUS;
+            sum2 = (sum2 + sum1) % MODULUS;
+        }
+        let check1 = MODULUS - (sum1 + sum2) % MODULUS;
+        let check2 = MODULUS - (sum1 + check1) % MODULUS;
+        put64(block, 0, check2 << 32 | check1);
+    }
+
+    /// B-tree node holding `records`, with variable-size keys and values unless `fixed` gives
+    /// the sizes of leaf records.
+    fn node(flags: u16, level: u16, records: &[Record], fixed: Option<(usize, usize)>) -> Vec<u8> {
+        let mut block = vec![0; BLOCK];
+        let values_end = if flags & NODE_ROOT != 0 {
+            BLOCK - TREE_INFO_LEN
+        } else {
+            BLOCK
+        };
+        let toc_entry_len = if fixed.is_some() { 4 } else { 8 };
+        let keys_start = NODE_HEADER_LEN + toc_entry_len * records.len();
+
+        let flags = flags | fixed.map_or(0, |_| NODE_FIXED_KV_SIZE);
+        put16(&mut block, 32, flags);
+        put16(&mut block, 34, level);
+        put32(&mut block, 36, records.len() as u32);
+        put16(&mut block, 42, (toc_entry_len * records.len()) as u16);
+
+        let (mut key_offset, mut value_offset) = (0, 0);
+        for (i, (key, value)) in records.iter().enumerate() {
+            let key_start = keys_start + key_offset;
+            block[key_start..key_start + key.len()].copy_from_slice(key);
+            value_offset += value.len();
+            let value_start = values_end - value_offset;
+            block[value_start..value_start + value.len()].copy_from_slice(value);
+
+            let toc = NODE_HEADER_LEN + toc_entry_len * i;
+            put16(&mut block, toc, key_offset as u16);
+            if fixed.is_some() {
+                put16(&mut block, toc + 2, value_offset as u16);
+            } else {
+                put16(&mut block, toc + 2, key.len() as u16);
+                put16(&mut block, toc + 4, value_offset as u16);
+                put16(&mut block, toc + 6, value.len() as u16);
+            }
+            key_offset += key.len();
+        }
+
+        if let (true, Some((key_len, value_len))) = (flags & NODE_ROOT != 0, fixed) {
+            put32(&mut block, BLOCK - TREE_INFO_LEN + 8, key_len as u32);
+            put32(&mut block, BLOCK - TREE_INFO_LEN + 12, value_len as u32);
+        }
+        block
+    }
+
+    /// Object map at `address`, whose single-node tree is in the following block.
+    fn object_map(address: u64, mappings: &[(u64, u64, u32, u64)]) -> Vec<Vec<u8>> {
+        let mut omap = vec![0; BLOCK];
+        put64(&mut omap, 48, address + 1);
+
+        let records: Vec<Record> = mappings
+            .iter()
+            .map(|&(oid, xid, flags, address)| {
+                let key = [oid.to_le_bytes(), xid.to_le_bytes()].concat();
+                let mut value = vec![0; 16];
+                put32(&mut value, 0, flags);
+                put64(&mut value, 8, address);
+                (key, value)
+            })
+            .collect();
+
+        vec![
+            omap,
+            node(NODE_ROOT | NODE_LEAF, 0, &records, Some((16, 16))),
+        ]
+    }
+
+    fn record_key(kind: u64, id: u64) -> Vec<u8> {
+        (id | kind << RECORD_TYPE_SHIFT).to_le_bytes().to_vec()
+    }
+
+    fn inode(id: u64, mode: u16, size: Option<u64>) -> Record {
+        let mut value = vec![0; 92];
+        put64(&mut value, 8, id);
+        put16(&mut value, 80, mode);
+        if let Some(size) = size {
+            // One data stream extended field, whose data starts with the size.
+            let mut xfields = vec![0; 48];
+            put16(&mut xfields, 0, 1);
+            put16(&mut xfields, 2, 48);
+            xfields[4] = INODE_XFIELD_DSTREAM;
+            put16(&mut xfields, 6, 40);
+            put64(&mut xfields, 8, size);
+            value.extend(xfields);
+        }
+        (record_key(RECORD_INODE, id), value)
+    }
+
+    fn dir_entry(parent: u64, name: &str, id: u64, dir_type: u16) -> Record {
+        let mut key = record_key(RECORD_DIR, parent);
+        key.extend(&(name.len() as u16 + 1).to_le_bytes());
+        key.extend(name.as_bytes());
+        key.push(0);
+
+        let mut value = vec![0; 18];
+        put64(&mut value, 0, id);
+        put16(&mut value, 16, dir_type);
+        (key, value)
+    }
+
+    fn extent(stream: u64, len: u64, block: u64) -> Record {
+        let mut key = record_key(RECORD_FILE_EXTENT, stream);
+        key.extend(&0u64.to_le_bytes());
+        let value = [len.to_le_bytes(), block.to_le_bytes()].concat();
+        (key, value)
+    }
+
+    fn symlink_target(id: u64, target: &str) -> Record {
+        let mut key = record_key(RECORD_XATTR, id);
+        key.extend(&(SYMLINK_XATTR.len() as u16 + 1).to_le_bytes());
+        key.extend(SYMLINK_XATTR);
+        key.push(0);
+
+        let mut value = vec![0; 4];
+        put16(&mut value, 0, XATTR_DATA_EMBEDDED);
+        put16(&mut value, 2, target.len() as u16 + 1);
+        value.extend(target.as_bytes());
+        value.push(0);
+        (key, value)
+    }
+
+    /// Container holding one volume with `Foo.app/Contents/Info.plist` and a symlink to it. The
+    /// latest superblock is in the checkpoint area, and the file system tree has an index node
+    /// above its leaf.
+    pub(in crate::archive) fn build(encrypted: bool) -> Vec<u8> {
+        let mut blocks = vec![vec![0; BLOCK]; 2];
+
+        // Blocks 2-3: container object map, which also lists a version of the volume beyond
+        // the latest checkpoint.
+        blocks.extend(object_map(
+            2,
+            &[(VOLUME_OID, 2, 0, 4), (VOLUME_OID, 3, 0, 999)],
+        ));
+
+        // Block 4: volume superblock.
+        let mut volume = vec![0; BLOCK];
+        volume[32..36].copy_from_slice(VOLUME_MAGIC);
+        put64(&mut volume, 128, 5);
+        put64(&mut volume, 136, TREE_OID);
+        put64(&mut volume, 264, if encrypted { 0 } else { FS_UNENCRYPTED });
+        blocks.push(volume);
+
+        // Blocks 5-6: volume object map, with a deleted newer version of the leaf.
+        blocks.extend(object_map(
+            5,
+            &[
+                (TREE_OID, 1, 0, 7),
+                (LEAF_OID, 1, 0, 8),
+                (LEAF_OID, 2, OMAP_VALUE_DELETED, 999),
+            ],
+        ));
+
+        // Block 7: root index node; block 8: leaf node; block 9: file contents.
+        blocks.push(node(
+            NODE_ROOT,
+            1,
+            &[(record_key(RECORD_INODE, 1), LEAF_OID.to_le_bytes().to_vec())],
+            None,
+        ));
+        blocks.push(node(
+            NODE_LEAF,
+            0,
+            &[
+                inode(ROOT_DIR_ID, 0o040755, None),
+                dir_entry(ROOT_DIR_ID, "Foo.app", 16, DT_DIR),
+                inode(16, 0o040755, None),
+                dir_entry(16, "Contents", 17, DT_DIR),
+                inode(17, 0o040755, None),
+                dir_entry(17, "Info.plist", 18, DT_REG),
+                dir_entry(17, "link", 19, DT_LNK),
+                inode(18, 0o100644, Some(5)),
+                extent(18, BLOCK as u64, 9),
+                inode(19, 0o120755, None),
+                symlink_target(19, "Info.plist"),
+            ],
+            None,
+        ));
+        let mut data = vec![0; BLOCK];
+        data[..5].copy_from_slice(b"hello");
+        blocks.push(data);
+
+        // Block 0 lists no volumes; the checkpoint superblock in block 1 supersedes it.
+        for (i, (xid, volume_count)) in [(1, 0), (2, 1)].iter().enumerate() {
+            let superblock = &mut blocks[i];
+            put64(superblock, 16, *xid);
+            put32(superblock, 24, OBJECT_TYPE_CONTAINER);
+            superblock[32..36].copy_from_slice(CONTAINER_MAGIC);
+            put32(superblock, 36, BLOCK as u32);
+            put32(superblock, 104, 1);
+            put64(superblock, 112, 1);
+            put64(superblock, 160, 2);
+            put32(superblock, 180, 4);
+            if *volume_count > 0 {
+                put64(superblock, 184, VOLUME_OID);
+            }
+            set_checksum(superblock);
+        }
+
+        blocks.concat()
+    }
+
+    fn device(image: &[u8]) -> Device {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(image).unwrap();
+        Device::new(file).unwrap()
+    }
+
+    #[test]
+    fn test_volume() {
+        let device = device(&build(false));
+        assert!(is_apfs(&device));
+
+        let volumes = volumes(&device).unwrap();
+        assert_eq!(volumes.len(), 1);
+        let volume = &volumes[0];
+
+        let root = volume.list(None).unwrap();
+        assert_eq!(root.len(), 1);
+        assert_eq!(root[0].name, "Foo.app");
+        assert_eq!(root[0].kind, EntryKind::Directory);
+
+        let contents = volume.list(Some(&volume.list(Some(&root[0])).unwrap()[0]));
+        let contents = contents.unwrap();
+        let names: Vec<_> = contents.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["Info.plist", "link"]);
+
+        assert_eq!(contents[0].kind, EntryKind::File);
+        assert_eq!(contents[0].mode & 0o7777, 0o644);
+        assert_eq!(volume.read(&contents[0]).unwrap(), b"hello");
+        assert_eq!(contents[1].kind, EntryKind::Symlink);
+        assert_eq!(volume.read(&contents[1]).unwrap(), b"Info.plist");
+    }
+
+    #[test]
+    fn test_checkpoint_checksum() {
+        // With a corrupt checkpoint superblock, the copy in block zero is used.
+        let mut image = build(false);
+        image[BLOCK + 200] ^= 0xff;
+        assert!(volumes(&device(&image)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_object_map_versions() {
+        let image = build(false);
+        let device = device(&image);
+        let container = Container::open(&device).unwrap();
+
+        let omap = container.object_map(5).unwrap();
+        let expected: HashMap<u64, u64> = [(TREE_OID, 7), (LEAF_OID, 8)].iter().cloned().collect();
+        assert_eq!(omap, expected);
+    }
+
+    #[test]
+    fn test_encrypted_volume() {
+        let err = volumes(&device(&build(true))).err().unwrap();
+        assert!(err.to_string().contains("encrypted"));
+    }
+
+    #[test]
+    fn test_invalid_node() {
+        // A node marked as a leaf at a non-zero level.
+        let mut image = build(false);
+        put16(&mut image[7 * BLOCK..8 * BLOCK], 32, NODE_ROOT | NODE_LEAF);
+        assert!(volumes(&device(&image)).is_err());
+
+        // An index record too short to hold a child identifier.
+        let mut image = build(false);
+        put16(&mut image[7 * BLOCK..8 * BLOCK], NODE_HEADER_LEN + 6, 4);
+        assert!(volumes(&device(&image)).is_err());
+    }
+
+    #[test]
+    fn test_stream_size() {
+        assert_eq!(inode_stream_size(&[]), Some(0));
+        let (_, value) = inode(18, 0o100644, Some(1234));
+        assert_eq!(inode_stream_size(&value[92..]), Some(1234));
+        // Header claiming a field beyond the end.
+        assert_eq!(inode_stream_size(&[2, 0, 0, 0, 8, 0, 40, 0]), None);
+    }
+}
@@ -0,0 +1,219 @@
+//! AppleDouble encoding of extended attributes, as stored by `ditto` in `__MACOSX/._<name>`
+//! archive entries.
+
+use crate::util::OperationError;
+
+const MAGIC: u32 = 0x0005_1607;
+const VERSION: u32 = 0x0002_0000;
+const FILLER: &[u8; 16] = b"Mac OS X        ";
+
+const ENTRY_RESOURCE_FORK: u32 = 2;
+const ENTRY_FINDER_INFO: u32 = 9;
+
+const ATTR_MAGIC: &[u8; 4] = b"ATTR";
+
+/// Header and the two entry descriptors.
+const FINDER_INFO_OFFSET: usize = 50;
+const FINDER_INFO_LEN: usize = 32;
+/// Finder info is followed by two bytes of padding before the attribute header.
+const ATTR_HEADER_OFFSET: usize = FINDER_INFO_OFFSET + FINDER_INFO_LEN + 2;
+const ATTR_HEADER_LEN: usize = 36;
+
+/// Stored in the Finder info entry rather than as a named attribute.
+pub(crate) const FINDER_INFO_XATTR: &str = "com.apple.FinderInfo";
+/// Stored in the resource fork entry rather than as a named attribute.
+pub(crate) const RESOURCE_FORK_XATTR: &str = "com.apple.ResourceFork";
+
+/// Encodes extended attributes in the layout written by macOS `copyfile(3)`.
+pub(crate) fn encode(attributes: &[(String, Vec<u8>)]) -> Vec<u8> {
+    let mut finder_info = vec![0; FINDER_INFO_LEN];
+    let mut resource_fork: &[u8] = &[];
+    let mut named = vec![];
+
+    for (name, value) in attributes {
+        match name.as_str() {
+            FINDER_INFO_XATTR => {
+                let len = value.len().min(FINDER_INFO_LEN);
+                finder_info[..len].copy_from_slice(&value[..len]);
+            }
+            RESOURCE_FORK_XATTR => resource_fork = value,
+            _ => named.push((name, value)),
+        }
+    }
+
+    // Attribute entries: offset, length, flags, name length, NUL-terminated name,
+    // each padded to a 4-byte boundary.
+    let entries_len: usize = named
+        .iter()
+        .map(|(name, _)| align4(11 + name.len() + 1))
+        .sum();
+    let data_start = ATTR_HEADER_OFFSET + ATTR_HEADER_LEN + entries_len;
+    let data_len: usize = named.iter().map(|(_, value)| value.len()).sum();
+    let total_len = data_start + data_len;
+
+    let mut out = Vec::with_capacity(total_len + resource_fork.len());
+    out.extend(&MAGIC.to_be_bytes());
+    out.extend(&VERSION.to_be_bytes());
+    out.extend(FILLER);
+    out.extend(&2u16.to_be_bytes());
+
+    out.extend(&ENTRY_FINDER_INFO.to_be_bytes());
+    out.extend(&(FINDER_INFO_OFFSET as u32).to_be_bytes());
+    out.extend(&((total_len - FINDER_INFO_OFFSET) as u32).to_be_bytes());
+    out.extend(&ENTRY_RESOURCE_FORK.to_be_bytes());
+    out.extend(&(total_len as u32).to_be_bytes());
+    out.extend(&(resource_fork.len() as u32).to_be_bytes());
+
+    out.extend(&finder_info);
+    out.extend(&[0, 0]);
+
+    out.extend(ATTR_MAGIC);
+    out.extend(&0u32.to_be_bytes());
+    out.extend(&(total_len as u32).to_be_bytes());
+    out.extend(&(data_start as u32).to_be_bytes());
+    out.extend(&(data_len as u32).to_be_bytes());
+    out.extend(&[0; 12]);
+    out.extend(&0u16.to_be_bytes());
+    out.extend(&(named.len() as u16).to_be_bytes());
+
+    let mut offset = data_start;
+    for (name, value) in &named {
+        let start = out.len();
+        out.extend(&(offset as u32).to_be_bytes());
+        out.extend(&(value.len() as u32).to_be_bytes());
+        out.extend(&0u16.to_be_bytes());
+        out.push((name.len() + 1) as u8);
+        out.extend(name.as_bytes());
+        out.push(0);
+        out.resize(start + align4(out.len() - start), 0);
+        offset += value.len();
+    }
+
+    for (_, value) in &named {
+        out.extend(value.iter());
+    }
+    out.extend(resource_fork);
+
+    out
+}
+
+/// Decodes extended attributes from an AppleDouble file.
+pub(crate) fn decode(data: &[u8]) -> Result<Vec<(String, Vec<u8>)>, OperationError> {
+    if be32(data, 0) != Some(MAGIC) {
+        return Err(malformed());
+    }
+
+    let mut attributes = vec![];
+    let entry_count = be16(data, 24).ok_or_else(malformed)? as usize;
+
+    for i in 0..entry_count {
+        let descriptor = 26 + i * 12;
+        let id = be32(data, descriptor).ok_or_else(malformed)?;
+        let offset = be32(data, descriptor + 4).ok_or_else(malformed)? as usize;
+        let len = be32(data, descriptor + 8).ok_or_else(malformed)? as usize;
+        let entry = data.get(offset..offset + len).ok_or_else(malformed)?;
+
+        match id {
+            ENTRY_FINDER_INFO => {
+                let finder_info = entry.get(..FINDER_INFO_LEN).unwrap_or(entry);
+                if finder_info.iter().any(|b| *b != 0) {
+                    attributes.push((FINDER_INFO_XATTR.into(), finder_info.to_vec()));
+                }
+
+                let header = offset + FINDER_INFO_LEN + 2;
+                if data.get(header..header + 4) == Some(&ATTR_MAGIC[..]) {
+                    attributes.extend(named_attributes(data, header)?);
+                }
+            }
+            ENTRY_RESOURCE_FORK if len > 0 => {
+                attributes.push((RESOURCE_FORK_XATTR.into(), entry.to_vec()))
+            }
+            _ => {}
+        }
+    }
+
+    Ok(attributes)
+}
+
+fn named_attributes(data: &[u8], header: usize) -> Result<Vec<(String, Vec<u8>)>, OperationError> {
+    let count = be16(data, header + 34).ok_or_else(malformed)? as usize;
+
+    let mut attributes = vec![];
+    let mut entry = header + ATTR_HEADER_LEN;
+    for _ in 0..count {
+        let offset = be32(data, entry).ok_or_else(malformed)? as usize;
+        let len = be32(data, entry + 4).ok_or_else(malformed)? as usize;
+        let name_len = *data.get(entry + 10).ok_or_else(malformed)? as usize;
+
+        let name = data
+            .get(entry + 11..entry + 11 + name_len)
+            .ok_or_else(malformed)?;
+        let name = String::from_utf8_lossy(name.strip_suffix(&[0]).unwrap_or(name)).into();
+        let value = data.get(offset..offset + len).ok_or_else(malformed)?;
+
+        attributes.push((name, value.to_vec()));
+        entry += align4(11 + name_len);
+    }
+
+    Ok(attributes)
+}
+
+fn align4(n: usize) -> usize {
+    (n + 3) & !3
+}
+
+fn be16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn be32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn malformed() -> OperationError {
+    OperationError::new("Malformed AppleDouble extended attribute data")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut finder_info = vec![0; 32];
+        finder_info[..8].copy_from_slice(b"APPLMACS");
+
+        let attributes = vec![
+            (FINDER_INFO_XATTR.to_string(), finder_info),
+            (
+                "com.apple.quarantine".to_string(),
+                b"0081;5e5e0000;;".to_vec(),
+            ),
+            ("com.example.a".to_string(), vec![1, 2, 3]),
+            (RESOURCE_FORK_XATTR.to_string(), vec![0xaa; 10]),
+        ];
+
+        let encoded = encode(&attributes);
+        assert_eq!(&encoded[..4], &[0x00, 0x05, 0x16, 0x07]);
+        assert_eq!(&encoded[84..88], b"ATTR");
+
+        let mut decoded = decode(&encoded).unwrap();
+        decoded.sort();
+        let mut expected = attributes;
+        expected.sort();
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn test_no_finder_info() {
+        let attributes = vec![("user.test".to_string(), b"value".to_vec())];
+        assert_eq!(decode(&encode(&attributes)).unwrap(), attributes);
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert!(decode(b"not apple double").is_err());
+    }
+}
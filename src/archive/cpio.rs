@@ -0,0 +1,201 @@
+//! Extraction of the portable ("odc") cpio archives used for installer payloads.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read};
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
+
+use super::{create_dir_within, unsafe_path};
+
+const MAGIC: &[u8] = b"070707";
+const HEADER_LEN: usize = 76;
+const TRAILER: &str = "TRAILER!!!";
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+
+pub(super) fn is_cpio(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Extracts regular files, directories and symlinks into `dest`. Other entry types, e.g.
+/// device nodes, are skipped.
+pub(super) fn extract<R: Read>(mut reader: R, dest: &Path) -> io::Result<()> {
+    fs::create_dir_all(dest)?;
+    let root = dest.canonicalize()?;
+    let mut directories = vec![];
+
+    loop {
+        let mut header = [0; HEADER_LEN];
+        reader.read_exact(&mut header)?;
+        if !header.starts_with(MAGIC) {
+            return Err(malformed("unsupported cpio format"));
+        }
+
+        let mode = octal(&header[18..24])? as u32;
+        let name_len = octal(&header[59..65])? as usize;
+        let file_len = octal(&header[65..76])?;
+
+        let mut name = vec![0; name_len];
+        reader.read_exact(&mut name)?;
+        let name = String::from_utf8_lossy(name.strip_suffix(&[0]).unwrap_or(&name)).into_owned();
+
+        if name == TRAILER {
+            break;
+        }
+
+        let mut data = (&mut reader).take(file_len);
+        let relative = relative_path(&name)?;
+
+        if relative.as_os_str().is_empty() {
+            // The payload root, "."
+        } else if mode & S_IFMT == S_IFDIR {
+            let path = root.join(&relative);
+            create_dir_within(&root, &path)?;
+            directories.push((path, mode));
+        } else if mode & S_IFMT == S_IFREG || mode & S_IFMT == S_IFLNK {
+            let path = root.join(&relative);
+            create_dir_within(&root, path.parent().unwrap_or(&root))?;
+
+            if mode & S_IFMT == S_IFLNK {
+                let mut target = String::new();
+                data.read_to_string(&mut target)?;
+                symlink(target, &path)?;
+            } else {
+                // Refuse to write through an existing entry, which may be a symlink.
+                let mut file = OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&path)?;
+                io::copy(&mut data, &mut file)?;
+                fs::set_permissions(&path, fs::Permissions::from_mode(mode & 0o7777))?;
+            }
+        }
+
+        // Skip whatever the entry's handling did not consume.
+        io::copy(&mut data, &mut io::sink())?;
+    }
+
+    // Applied last so that read-only directories can still be populated.
+    for (path, mode) in directories.into_iter().rev() {
+        fs::set_permissions(&path, fs::Permissions::from_mode(mode & 0o7777))?;
+    }
+
+    Ok(())
+}
+
+/// Entry name relative to the payload root, rejecting names that could escape it.
+fn relative_path(name: &str) -> io::Result<PathBuf> {
+    let mut path = PathBuf::new();
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            _ => return Err(unsafe_path(name)),
+        }
+    }
+    Ok(path)
+}
+
+fn octal(field: &[u8]) -> io::Result<u64> {
+    std::str::from_utf8(field)
+        .ok()
+        .and_then(|field| u64::from_str_radix(field, 8).ok())
+        .ok_or_else(|| malformed("invalid header field"))
+}
+
+fn malformed(detail: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, detail)
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    /// Builds an archive from (name, mode, data) entries.
+    pub(in crate::archive) fn build(entries: &[(&str, u32, &[u8])]) -> Vec<u8> {
+        let mut out = vec![];
+        let trailer = [(super::TRAILER, 0, &[][..])];
+        for (i, (name, mode, data)) in entries.iter().chain(trailer.iter()).enumerate() {
+            out.extend(
+                format!(
+                    "070707{:06o}{:06o}{:06o}{:06o}{:06o}{:06o}{:06o}{:011o}{:06o}{:011o}",
+                    0,
+                    i,
+                    mode,
+                    0,
+                    0,
+                    1,
+                    0,
+                    0,
+                    name.len() + 1,
+                    data.len()
+                )
+                .as_bytes(),
+            );
+            out.extend(name.as_bytes());
+            out.push(0);
+            out.extend(*data);
+        }
+        out
+    }
+
+    #[test]
+    fn test_extract() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let archive = build(&[
+            (".", 0o040755, b""),
+            ("./Foo.app", 0o040755, b""),
+            ("./Foo.app/Contents/MacOS/Foo", 0o100755, b"executable"),
+            ("./Foo.app/Contents/Info.plist", 0o100644, b"<plist/>"),
+            ("./Foo.app/Contents/Current", 0o120755, b"MacOS"),
+            ("./dev", 0o020644, b""),
+        ]);
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        super::extract(&archive[..], temp_dir.path()).unwrap();
+
+        let bundle = temp_dir.path().join("Foo.app");
+        assert_eq!(
+            std::fs::read(bundle.join("Contents/MacOS/Foo")).unwrap(),
+            b"executable"
+        );
+        assert_eq!(
+            std::fs::metadata(bundle.join("Contents/MacOS/Foo"))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777,
+            0o755
+        );
+        assert_eq!(
+            std::fs::read_link(bundle.join("Contents/Current")).unwrap(),
+            std::path::Path::new("MacOS")
+        );
+        assert!(!temp_dir.path().join("dev").exists());
+    }
+
+    #[test]
+    fn test_extract_unsafe() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dest = temp_dir.path().join("out");
+
+        let archive = build(&[("../escaped", 0o100644, b"")]);
+        assert!(super::extract(&archive[..], &dest).is_err());
+
+        let archive = build(&[
+            ("./link", 0o120755, b".."),
+            ("./link/escaped", 0o100644, b""),
+        ]);
+        assert!(super::extract(&archive[..], &dest).is_err());
+        assert!(!temp_dir.path().join("escaped").exists());
+    }
+
+    #[test]
+    fn test_truncated() {
+        let archive = build(&[("./Foo", 0o100644, b"contents")]);
+        let temp_dir = tempfile::tempdir().unwrap();
+        assert!(super::extract(&archive[..100], temp_dir.path()).is_err());
+    }
+}
@@ -0,0 +1,352 @@
+//! Extraction of the application bundles in a disk image, reading its HFS+ or APFS volumes
+//! directly instead of attaching the image with `hdiutil`.
+
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{symlink, FileExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use tempfile::{Builder as TempFileBuilder, TempDir};
+
+use super::udif::UdifImage;
+use super::{apfs, hfs, unsafe_path};
+use crate::util::OperationError;
+
+/// Disk image whose application bundles were extracted to a temporary directory, removed on
+/// drop.
+pub(crate) struct ExtractedImage {
+    _dir: TempDir,
+    /// Application bundles in the image, sorted by path. May be empty, e.g. for an image
+    /// containing an installer package.
+    pub(crate) bundles: Vec<PathBuf>,
+}
+
+/// Extracts every application bundle on the volumes of the disk image at `dmg_path`. Fails if
+/// the image does not contain an HFS+ or APFS volume.
+pub(crate) fn extract_bundles(dmg_path: &Path) -> Result<ExtractedImage, OperationError> {
+    let error = |err: &dyn std::fmt::Display| {
+        OperationError::detail(
+            &format!("Could not read disk image {}", dmg_path.display()),
+            &err.to_string(),
+        )
+    };
+
+    let image = UdifImage::open(dmg_path)?;
+
+    let dir = TempFileBuilder::new().tempdir().map_err(|err| {
+        OperationError::detail("Could not create temporary directory", &err.to_string())
+    })?;
+
+    let mut bundles = vec![];
+    let mut volume_count = 0;
+
+    for partition in &image.partitions {
+        let file = tempfile::tempfile().map_err(|err| error(&err))?;
+        image.decode(partition, &file)?;
+        let device = Device::new(file).map_err(|err| error(&err))?;
+
+        let volumes: Vec<Box<dyn Volume>> = if apfs::is_apfs(&device) {
+            apfs::volumes(&device).map_err(|err| error(&err))?
+        } else if hfs::is_hfs(&device) {
+            vec![Box::new(
+                hfs::HfsVolume::open(&device).map_err(|err| error(&err))?,
+            )]
+        } else {
+            continue;
+        };
+
+        for volume in volumes {
+            volume_count += 1;
+            let root = dir.path().join(volume_count.to_string());
+            find_bundles(volume.as_ref(), &root, &mut bundles).map_err(|err| error(&err))?;
+        }
+    }
+
+    if volume_count == 0 {
+        return Err(error(&"No HFS+ or APFS volume found"));
+    }
+    bundles.sort();
+
+    Ok(ExtractedImage { _dir: dir, bundles })
+}
+
+/// Decompressed partition of a disk image, backed by a temporary file.
+pub(super) struct Device {
+    file: File,
+    len: u64,
+}
+
+impl Device {
+    pub(super) fn new(file: File) -> io::Result<Self> {
+        let len = file.metadata()?.len();
+        Ok(Device { file, len })
+    }
+
+    pub(super) fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub(super) fn read_at(&self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        if offset.saturating_add(len as u64) > self.len {
+            return Err(malformed("read beyond end of volume"));
+        }
+
+        let mut data = vec![0; len];
+        self.file.read_exact_at(&mut data, offset)?;
+        Ok(data)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum EntryKind {
+    Directory,
+    File,
+    Symlink,
+}
+
+/// Directory entry on a volume.
+#[derive(Clone)]
+pub(super) struct Entry {
+    pub(super) name: String,
+    pub(super) kind: EntryKind,
+    /// Unix permission bits.
+    pub(super) mode: u32,
+    /// Identifier of the entry's file or directory, specific to the volume format.
+    pub(super) id: u64,
+}
+
+/// Read-only view of a filesystem on a disk image.
+pub(super) trait Volume {
+    /// Entries of the directory `dir`, or of the root directory if `None`. Entries of other
+    /// types, e.g. device nodes, are omitted.
+    fn list(&self, dir: Option<&Entry>) -> io::Result<Vec<Entry>>;
+    /// Contents of a file, or target of a symlink.
+    fn read(&self, entry: &Entry) -> io::Result<Vec<u8>>;
+}
+
+/// Deepest directory nesting followed, well beyond that of real volumes.
+const MAX_DEPTH: usize = 128;
+
+/// Traversal of a volume's directories. A corrupt or crafted catalog may list a directory
+/// inside itself, so each directory is entered at most once and nesting is limited.
+struct Walk<'a> {
+    volume: &'a dyn Volume,
+    visited: HashSet<u64>,
+}
+
+impl<'a> Walk<'a> {
+    fn new(volume: &'a dyn Volume) -> Self {
+        Walk {
+            volume,
+            visited: HashSet::new(),
+        }
+    }
+
+    /// Lists `dir`, found `depth` levels below the root directory.
+    fn enter(&mut self, dir: &Entry, depth: usize) -> io::Result<Vec<Entry>> {
+        if depth > MAX_DEPTH {
+            return Err(malformed("directories nested too deeply"));
+        }
+        if !self.visited.insert(dir.id) {
+            return Err(malformed("directory listed more than once"));
+        }
+
+        self.volume.list(Some(dir))
+    }
+
+    /// Searches the entries of a directory for application bundles, extracting each to the
+    /// corresponding path below `dest`.
+    fn find_bundles(
+        &mut self,
+        entries: Vec<Entry>,
+        depth: usize,
+        dest: &Path,
+        found: &mut Vec<PathBuf>,
+    ) -> io::Result<()> {
+        for entry in entries {
+            if entry.kind != EntryKind::Directory {
+                continue;
+            }
+
+            let path = dest.join(entry_name(&entry)?);
+            let children = self.enter(&entry, depth + 1)?;
+            if path.extension().is_some_and(|extension| extension == "app") {
+                fs::create_dir_all(&path)?;
+                self.extract_directory(&entry, children, depth + 1, &path)?;
+                found.push(path);
+            } else {
+                self.find_bundles(children, depth + 1, &path, found)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn extract_directory(
+        &mut self,
+        dir: &Entry,
+        entries: Vec<Entry>,
+        depth: usize,
+        dest: &Path,
+    ) -> io::Result<()> {
+        for entry in entries {
+            let path = dest.join(entry_name(&entry)?);
+
+            match entry.kind {
+                EntryKind::Directory => {
+                    let children = self.enter(&entry, depth + 1)?;
+                    fs::create_dir(&path)?;
+                    self.extract_directory(&entry, children, depth + 1, &path)?;
+                }
+                EntryKind::Symlink => {
+                    let target = self.volume.read(&entry)?;
+                    symlink(String::from_utf8_lossy(&target).as_ref(), &path)?;
+                }
+                EntryKind::File => {
+                    let mut file = OpenOptions::new()
+                        .write(true)
+                        .create_new(true)
+                        .open(&path)?;
+                    file.write_all(&self.volume.read(&entry)?)?;
+                    fs::set_permissions(&path, fs::Permissions::from_mode(entry.mode & 0o7777))?;
+                }
+            }
+        }
+
+        // Applied last so that a read-only directory can still be populated.
+        fs::set_permissions(dest, fs::Permissions::from_mode(dir.mode & 0o7777 | 0o700))
+    }
+}
+
+/// Searches `volume` for application bundles, extracting each to the corresponding path below
+/// `dest`.
+fn find_bundles(volume: &dyn Volume, dest: &Path, found: &mut Vec<PathBuf>) -> io::Result<()> {
+    let root = volume.list(None)?;
+    Walk::new(volume).find_bundles(root, 0, dest, found)
+}
+
+/// Name of `entry` as a single path component, rejecting names that could escape the
+/// destination.
+fn entry_name(entry: &Entry) -> io::Result<&str> {
+    match entry.name.as_str() {
+        "" | "." | ".." => Err(unsafe_path(&entry.name)),
+        name if name.contains(['/', '\0']) => Err(unsafe_path(name)),
+        name => Ok(name),
+    }
+}
+
+pub(super) fn malformed(detail: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, detail)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::{Entry, EntryKind, Volume};
+
+    /// Volume in which every directory contains a directory with the next ID, up to `depth`,
+    /// after which the chain loops back to the first directory.
+    struct LoopingVolume {
+        depth: u64,
+    }
+
+    impl Volume for LoopingVolume {
+        fn list(&self, dir: Option<&Entry>) -> io::Result<Vec<Entry>> {
+            let id = dir.map_or(1, |dir| dir.id + 1);
+            Ok(vec![Entry {
+                name: format!("dir{}", id),
+                kind: EntryKind::Directory,
+                mode: 0o755,
+                id: if id > self.depth { 1 } else { id },
+            }])
+        }
+
+        fn read(&self, _entry: &Entry) -> io::Result<Vec<u8>> {
+            Ok(vec![])
+        }
+    }
+
+    #[test]
+    fn test_extract_bundles() {
+        let mut path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests/generated_artifacts/dmg/signed_with_correctly_signed_app.dmg");
+
+        let extracted = super::extract_bundles(&path).unwrap();
+        assert_eq!(extracted.bundles.len(), 1);
+
+        let bundle = &extracted.bundles[0];
+        assert_eq!(bundle.file_name().unwrap(), "correctly_signed.app");
+        assert!(
+            bundle
+                .join("Contents/MacOS/correctly_signed")
+                .metadata()
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o111
+                != 0
+        );
+        assert!(crate::codesign::is_macho(
+            bundle.join("Contents/MacOS/correctly_signed")
+        ));
+    }
+
+    #[test]
+    fn test_no_volume() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("empty.dmg");
+        std::fs::write(
+            &path,
+            super::super::udif::tests::build(&[("disk image", &[0; 4096])]),
+        )
+        .unwrap();
+
+        assert!(super::extract_bundles(&path).is_err());
+    }
+
+    #[test]
+    fn test_extract_apfs_bundles() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("apfs.dmg");
+        let volume = super::super::apfs::tests::build(false);
+        std::fs::write(
+            &path,
+            super::super::udif::tests::build(&[("APFS", &volume)]),
+        )
+        .unwrap();
+
+        let extracted = super::extract_bundles(&path).unwrap();
+        assert_eq!(extracted.bundles.len(), 1);
+
+        let bundle = &extracted.bundles[0];
+        assert_eq!(bundle.file_name().unwrap(), "Foo.app");
+        assert_eq!(
+            std::fs::read(bundle.join("Contents/Info.plist")).unwrap(),
+            b"hello"
+        );
+        assert_eq!(
+            std::fs::read_link(bundle.join("Contents/link")).unwrap(),
+            std::path::Path::new("Info.plist")
+        );
+    }
+
+    #[test]
+    fn test_directory_cycle() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut found = vec![];
+
+        let err = super::find_bundles(&LoopingVolume { depth: 3 }, temp_dir.path(), &mut found)
+            .unwrap_err();
+        assert!(err.to_string().contains("more than once"));
+
+        let err = super::find_bundles(
+            &LoopingVolume { depth: u64::MAX },
+            temp_dir.path(),
+            &mut found,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("nested too deeply"));
+    }
+}
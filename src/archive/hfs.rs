@@ -0,0 +1,511 @@
+//! Read-only access to HFS+ and HFSX volumes: the catalog B-tree, and the data forks of files
+//! including extents recorded in the extents overflow file.
+
+use std::collections::HashMap;
+use std::io;
+
+use super::disk_image::{malformed, Device, Entry, EntryKind, Volume};
+
+const VOLUME_HEADER_OFFSET: u64 = 1024;
+const VOLUME_HEADER_LEN: usize = 512;
+const SIGNATURE_HFS_PLUS: &[u8] = b"H+";
+const SIGNATURE_HFSX: &[u8] = b"HX";
+
+const ROOT_FOLDER_ID: u32 = 2;
+const CATALOG_FILE_ID: u32 = 4;
+const DATA_FORK: u8 = 0x00;
+
+const NODE_DESCRIPTOR_LEN: usize = 14;
+const LEAF_NODE: u8 = 0xff;
+
+const FOLDER_RECORD: u16 = 1;
+const FILE_RECORD: u16 = 2;
+
+const S_IFMT: u16 = 0o170000;
+const S_IFREG: u16 = 0o100000;
+const S_IFLNK: u16 = 0o120000;
+/// BSD flag of files whose contents are stored compressed in an extended attribute.
+const UF_COMPRESSED: u8 = 0x20;
+
+/// File type and creator of hard links, which refer to an `iNode<n>` file in the metadata
+/// directory.
+const HARD_LINK_TYPE: &[u8] = b"hlnkhfs+";
+const METADATA_DIR: &str = "\0\0\0\0HFS+ Private Data";
+
+pub(super) fn is_hfs(device: &Device) -> bool {
+    device
+        .read_at(VOLUME_HEADER_OFFSET, 2)
+        .map(|signature| signature == SIGNATURE_HFS_PLUS || signature == SIGNATURE_HFSX)
+        .unwrap_or(false)
+}
+
+#[derive(Clone, Copy)]
+struct Extent {
+    start_block: u32,
+    block_count: u32,
+}
+
+struct Fork {
+    size: u64,
+    /// Extents in file order, including any from the extents overflow file.
+    extents: Vec<Extent>,
+}
+
+struct CatalogRecord {
+    parent: u32,
+    name: String,
+    id: u32,
+    kind: EntryKind,
+    mode: u16,
+    /// Number of the `iNode` file a hard link refers to.
+    link_inode: Option<u32>,
+    /// Data fork of files.
+    fork: Option<Fork>,
+    /// Whether the file's contents are stored compressed in an extended attribute.
+    compressed: bool,
+}
+
+pub(super) struct HfsVolume<'a> {
+    device: &'a Device,
+    block_size: u64,
+    children: HashMap<u32, Vec<Entry>>,
+    forks: HashMap<u32, Fork>,
+    compressed: Vec<u32>,
+}
+
+impl<'a> HfsVolume<'a> {
+    pub(super) fn open(device: &'a Device) -> io::Result<Self> {
+        let header = device.read_at(VOLUME_HEADER_OFFSET, VOLUME_HEADER_LEN)?;
+        let block_size = be32(&header, 40) as u64;
+        if block_size < 512 || !block_size.is_power_of_two() {
+            return Err(malformed("invalid block size"));
+        }
+
+        let mut volume = HfsVolume {
+            device,
+            block_size,
+            children: HashMap::new(),
+            forks: HashMap::new(),
+            compressed: vec![],
+        };
+
+        // The extents overflow file itself never overflows.
+        let extents_file = volume.read_fork(&parse_fork(&header[192..272]))?;
+        let mut overflow: HashMap<u32, Vec<(u32, Vec<Extent>)>> = HashMap::new();
+        for record in leaf_records(&extents_file)? {
+            if record.len() < 76 || be16(&record, 0) != 10 || record[2] != DATA_FORK {
+                continue;
+            }
+            overflow
+                .entry(be32(&record, 4))
+                .or_default()
+                .push((be32(&record, 8), parse_extents(&record[12..76])));
+        }
+        let with_overflow = |id: u32, mut fork: Fork| {
+            let mut records = overflow.get(&id).cloned().unwrap_or_default();
+            records.sort_by_key(|(start, _)| *start);
+            for (_, extents) in records {
+                fork.extents.extend(extents);
+            }
+            fork
+        };
+
+        let catalog_fork = with_overflow(CATALOG_FILE_ID, parse_fork(&header[272..352]));
+        let catalog = volume.read_fork(&catalog_fork)?;
+
+        let mut records = vec![];
+        for record in leaf_records(&catalog)? {
+            if let Some(mut record) = parse_catalog_record(&record)? {
+                if let Some(fork) = record.fork.take() {
+                    volume
+                        .forks
+                        .insert(record.id, with_overflow(record.id, fork));
+                }
+                if record.compressed {
+                    volume.compressed.push(record.id);
+                }
+                records.push(record);
+            }
+        }
+
+        // Resolve hard links to the file they refer to.
+        let metadata_dir = records
+            .iter()
+            .find(|record| record.parent == ROOT_FOLDER_ID && record.name == METADATA_DIR)
+            .map(|record| record.id);
+        let inodes: HashMap<String, (u32, u16)> = records
+            .iter()
+            .filter(|record| Some(record.parent) == metadata_dir)
+            .map(|record| (record.name.clone(), (record.id, record.mode)))
+            .collect();
+
+        for record in records {
+            let (id, mode) = match record.link_inode {
+                Some(inode) => *inodes
+                    .get(&format!("iNode{}", inode))
+                    .ok_or_else(|| malformed("hard link without target"))?,
+                None => (record.id, record.mode),
+            };
+
+            volume
+                .children
+                .entry(record.parent)
+                .or_default()
+                .push(Entry {
+                    name: record.name,
+                    kind: record.kind,
+                    mode: mode as u32,
+                    id: id as u64,
+                });
+        }
+        for entries in volume.children.values_mut() {
+            entries.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+
+        Ok(volume)
+    }
+
+    fn read_fork(&self, fork: &Fork) -> io::Result<Vec<u8>> {
+        if fork.size > self.device.len() {
+            return Err(malformed("fork size exceeds volume size"));
+        }
+
+        let mut data = Vec::with_capacity(fork.size as usize);
+        for extent in &fork.extents {
+            if data.len() as u64 >= fork.size {
+                break;
+            }
+            let len =
+                (extent.block_count as u64 * self.block_size).min(fork.size - data.len() as u64);
+            data.extend(
+                self.device
+                    .read_at(extent.start_block as u64 * self.block_size, len as usize)?,
+            );
+        }
+
+        if (data.len() as u64) < fork.size {
+            return Err(malformed("fork extents shorter than its size"));
+        }
+        Ok(data)
+    }
+}
+
+impl Volume for HfsVolume<'_> {
+    fn list(&self, dir: Option<&Entry>) -> io::Result<Vec<Entry>> {
+        let dir = dir.map_or(ROOT_FOLDER_ID, |dir| dir.id as u32);
+
+        Ok(self.children.get(&dir).cloned().unwrap_or_default())
+    }
+
+    fn read(&self, entry: &Entry) -> io::Result<Vec<u8>> {
+        let id = entry.id as u32;
+        if self.compressed.contains(&id) {
+            return Err(malformed(&format!(
+                "{} is compressed, which is not supported",
+                entry.name
+            )));
+        }
+
+        let fork = self
+            .forks
+            .get(&id)
+            .ok_or_else(|| malformed("file record not found"))?;
+        self.read_fork(fork)
+    }
+}
+
+/// Parses a catalog leaf record, returning `None` for thread records and entries other than
+/// files, folders and symlinks.
+fn parse_catalog_record(record: &[u8]) -> io::Result<Option<CatalogRecord>> {
+    let invalid = || malformed("invalid catalog record");
+
+    let key_len = be16(record.get(0..2).ok_or_else(invalid)?, 0) as usize;
+    let key = record.get(2..2 + key_len).ok_or_else(invalid)?;
+    if key.len() < 6 {
+        return Err(invalid());
+    }
+    let name_len = be16(key, 4) as usize;
+    let name = key.get(6..6 + 2 * name_len).ok_or_else(invalid)?;
+
+    // Records start at an even offset.
+    let data = record.get((2 + key_len + 1) & !1..).ok_or_else(invalid)?;
+    if data.len() < 2 {
+        return Err(invalid());
+    }
+    let record_type = be16(data, 0);
+    if record_type != FOLDER_RECORD && record_type != FILE_RECORD {
+        return Ok(None);
+    }
+    let min_len = if record_type == FILE_RECORD { 248 } else { 88 };
+    if data.len() < min_len {
+        return Err(invalid());
+    }
+
+    let name = name
+        .chunks_exact(2)
+        .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+        .collect::<Vec<_>>();
+    // Names are stored as in the Carbon API, where ':' is the separator rather than '/'.
+    let name = String::from_utf16_lossy(&name).replace('/', ":");
+
+    let mode = be16(data, 42);
+    let id = be32(data, 8);
+
+    if record_type == FOLDER_RECORD {
+        return Ok(Some(CatalogRecord {
+            parent: be32(key, 0),
+            name,
+            id,
+            kind: EntryKind::Directory,
+            mode,
+            link_inode: None,
+            fork: None,
+            compressed: false,
+        }));
+    }
+
+    let is_hard_link = &data[48..56] == HARD_LINK_TYPE;
+    let kind = match mode & S_IFMT {
+        S_IFLNK => EntryKind::Symlink,
+        // Files created without Unix permissions, e.g. by older Carbon applications.
+        S_IFREG | 0 => EntryKind::File,
+        _ => return Ok(None),
+    };
+
+    Ok(Some(CatalogRecord {
+        parent: be32(key, 0),
+        name,
+        id,
+        kind,
+        mode: if mode == 0 { 0o644 } else { mode },
+        link_inode: if is_hard_link {
+            Some(be32(data, 44))
+        } else {
+            None
+        },
+        fork: Some(parse_fork(&data[88..168])),
+        compressed: data[41] & UF_COMPRESSED != 0,
+    }))
+}
+
+/// Records of every leaf node of a B-tree file, in key order.
+fn leaf_records(tree: &[u8]) -> io::Result<Vec<Vec<u8>>> {
+    let invalid = || malformed("invalid B-tree");
+
+    let header = tree
+        .get(NODE_DESCRIPTOR_LEN..NODE_DESCRIPTOR_LEN + 106)
+        .ok_or_else(invalid)?;
+    let node_size = be16(header, 18) as usize;
+    if node_size < 512 {
+        return Err(invalid());
+    }
+    let node_count = tree.len() / node_size;
+
+    let mut records = vec![];
+    let mut node_number = be32(header, 10) as usize;
+    let mut visited = 0;
+
+    while node_number != 0 {
+        visited += 1;
+        if visited > node_count {
+            return Err(invalid());
+        }
+
+        let node = tree
+            .get(node_number * node_size..(node_number + 1) * node_size)
+            .ok_or_else(invalid)?;
+        if node[8] != LEAF_NODE {
+            return Err(invalid());
+        }
+
+        // Record offsets are stored in reverse order at the end of the node, followed by the
+        // offset of free space.
+        let record_count = be16(node, 10) as usize;
+        let offset = |i: usize| {
+            node_size
+                .checked_sub(2 * (i + 1))
+                .map(|pos| be16(node, pos) as usize)
+                .ok_or_else(invalid)
+        };
+        for i in 0..record_count {
+            let record = node.get(offset(i)?..offset(i + 1)?).ok_or_else(invalid)?;
+            records.push(record.to_vec());
+        }
+
+        node_number = be32(node, 0) as usize;
+    }
+
+    Ok(records)
+}
+
+fn parse_fork(data: &[u8]) -> Fork {
+    Fork {
+        size: be64(data, 0),
+        extents: parse_extents(&data[16..80]),
+    }
+}
+
+fn parse_extents(data: &[u8]) -> Vec<Extent> {
+    data.chunks_exact(8)
+        .map(|extent| Extent {
+            start_block: be32(extent, 0),
+            block_count: be32(extent, 4),
+        })
+        .filter(|extent| extent.block_count != 0)
+        .collect()
+}
+
+fn be16(data: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([data[offset], data[offset + 1]])
+}
+
+fn be32(data: &[u8], offset: usize) -> u32 {
+    let mut value = [0; 4];
+    value.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_be_bytes(value)
+}
+
+fn be64(data: &[u8], offset: usize) -> u64 {
+    let mut value = [0; 8];
+    value.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_be_bytes(value)
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    const BLOCK_SIZE: usize = 4096;
+
+    pub(crate) enum Node<'a> {
+        Folder,
+        File(u16, &'a [u8]),
+        Symlink(&'a str),
+        /// Hard link to the `iNode` file with the given number.
+        HardLink(u32),
+    }
+
+    /// Builds an HFS+ volume holding `entries`, each given as parent folder ID, name, ID and
+    /// contents. The root folder has ID 2.
+    pub(crate) fn build(entries: &[(u32, &str, u32, Node)]) -> Vec<u8> {
+        let mut data_blocks = vec![];
+        let mut records = vec![];
+
+        for (parent, name, id, node) in entries {
+            let mut record = vec![];
+            let name = name.encode_utf16().collect::<Vec<_>>();
+            record.extend(&(6 + 2 * name.len() as u16).to_be_bytes());
+            record.extend(&parent.to_be_bytes());
+            record.extend(&(name.len() as u16).to_be_bytes());
+            for unit in name {
+                record.extend(&unit.to_be_bytes());
+            }
+
+            let mut data = vec![0; if let Node::Folder = node { 88 } else { 248 }];
+            data[8..12].copy_from_slice(&id.to_be_bytes());
+            let (mode, contents): (u16, &[u8]) = match node {
+                Node::Folder => {
+                    data[0..2].copy_from_slice(&1u16.to_be_bytes());
+                    (0o040755, b"")
+                }
+                Node::File(mode, contents) => (0o100000 | mode, contents),
+                Node::Symlink(target) => (0o120755, target.as_bytes()),
+                Node::HardLink(inode) => {
+                    data[44..48].copy_from_slice(&inode.to_be_bytes());
+                    data[48..56].copy_from_slice(super::HARD_LINK_TYPE);
+                    (0o100444, b"")
+                }
+            };
+            data[42..44].copy_from_slice(&mode.to_be_bytes());
+
+            if let Node::Folder = node {
+            } else {
+                data[0..2].copy_from_slice(&2u16.to_be_bytes());
+                let block_count = contents.len().div_ceil(BLOCK_SIZE);
+                data[88..96].copy_from_slice(&(contents.len() as u64).to_be_bytes());
+                data[104..108].copy_from_slice(&(4 + data_blocks.len() as u32).to_be_bytes());
+                data[108..112].copy_from_slice(&(block_count as u32).to_be_bytes());
+
+                let mut contents = contents.to_vec();
+                contents.resize(block_count * BLOCK_SIZE, 0);
+                data_blocks.extend(contents.chunks(BLOCK_SIZE).map(<[u8]>::to_vec));
+            }
+
+            record.extend(data);
+            records.push(record);
+        }
+
+        // Block 1 holds an empty extents overflow B-tree, blocks 2 and 3 the catalog's header
+        // and leaf node, followed by file contents.
+        let mut volume = vec![0; 4 * BLOCK_SIZE];
+        volume[1024..1026].copy_from_slice(b"H+");
+        volume[1024 + 40..1024 + 44].copy_from_slice(&(BLOCK_SIZE as u32).to_be_bytes());
+        write_fork(&mut volume[1024 + 192..], 1, 1);
+        write_fork(&mut volume[1024 + 272..], 2, 2);
+
+        header_node(&mut volume[BLOCK_SIZE..2 * BLOCK_SIZE], 0);
+        header_node(&mut volume[2 * BLOCK_SIZE..3 * BLOCK_SIZE], 1);
+
+        let leaf = &mut volume[3 * BLOCK_SIZE..4 * BLOCK_SIZE];
+        leaf[8] = super::LEAF_NODE;
+        leaf[10..12].copy_from_slice(&(records.len() as u16).to_be_bytes());
+        let mut offset = 14;
+        for (i, record) in records.iter().enumerate() {
+            leaf[offset..offset + record.len()].copy_from_slice(record);
+            leaf[BLOCK_SIZE - 2 * (i + 1)..BLOCK_SIZE - 2 * i]
+                .copy_from_slice(&(offset as u16).to_be_bytes());
+            offset += record.len();
+        }
+        let i = records.len();
+        leaf[BLOCK_SIZE - 2 * (i + 1)..BLOCK_SIZE - 2 * i]
+            .copy_from_slice(&(offset as u16).to_be_bytes());
+
+        volume.extend(data_blocks.concat());
+        volume
+    }
+
+    fn write_fork(fork: &mut [u8], start_block: u32, block_count: u32) {
+        let size = block_count as u64 * BLOCK_SIZE as u64;
+        fork[0..8].copy_from_slice(&size.to_be_bytes());
+        fork[16..20].copy_from_slice(&start_block.to_be_bytes());
+        fork[20..24].copy_from_slice(&block_count.to_be_bytes());
+    }
+
+    fn header_node(node: &mut [u8], first_leaf: u32) {
+        node[8] = 1;
+        let header = &mut node[14..];
+        header[10..14].copy_from_slice(&first_leaf.to_be_bytes());
+        header[18..20].copy_from_slice(&(BLOCK_SIZE as u16).to_be_bytes());
+    }
+
+    #[test]
+    fn test_read_volume() {
+        use super::super::disk_image::{Device, EntryKind, Volume};
+
+        let image = build(&[
+            (2, "Tool", 16, Node::File(0o755, b"tool")),
+            (2, "Link", 17, Node::Symlink("Tool")),
+            (2, "a/b", 18, Node::HardLink(20)),
+            (2, super::METADATA_DIR, 19, Node::Folder),
+            (19, "iNode20", 20, Node::File(0o644, &[7; 5000])),
+        ]);
+        let mut file = tempfile::tempfile().unwrap();
+        std::io::Write::write_all(&mut file, &image).unwrap();
+        let device = Device::new(file).unwrap();
+
+        assert!(super::is_hfs(&device));
+        let volume = super::HfsVolume::open(&device).unwrap();
+
+        let entries = volume.list(None).unwrap();
+        let names = entries
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec![super::METADATA_DIR, "Link", "Tool", "a:b"]);
+
+        assert_eq!(entries[1].kind, EntryKind::Symlink);
+        assert_eq!(volume.read(&entries[1]).unwrap(), b"Tool");
+        assert_eq!(entries[2].mode & 0o777, 0o755);
+        assert_eq!(volume.read(&entries[2]).unwrap(), b"tool");
+        assert_eq!(entries[3].mode & 0o777, 0o644);
+        assert_eq!(volume.read(&entries[3]).unwrap(), vec![7; 5000]);
+    }
+}
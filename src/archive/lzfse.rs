@@ -0,0 +1,620 @@
+//! Decoder for LZFSE streams, as found in compressed disk image chunks. A stream consists of
+//! blocks that are either stored, LZVN-compressed, or LZ77-compressed with literals and
+//! literal/match/distance triples entropy coded using finite state entropy (FSE).
+
+use crate::util::OperationError;
+
+const MAGIC_END: u32 = u32::from_le_bytes(*b"bvx$");
+const MAGIC_RAW: u32 = u32::from_le_bytes(*b"bvx-");
+const MAGIC_V1: u32 = u32::from_le_bytes(*b"bvx1");
+const MAGIC_V2: u32 = u32::from_le_bytes(*b"bvx2");
+const MAGIC_LZVN: u32 = u32::from_le_bytes(*b"bvxn");
+
+const V2_HEADER_LEN: usize = 32;
+
+const LITERAL_STATES: usize = 1024;
+const L_STATES: usize = 64;
+const M_STATES: usize = 64;
+const D_STATES: usize = 256;
+
+const LITERAL_SYMBOLS: usize = 256;
+const L_SYMBOLS: usize = 20;
+const M_SYMBOLS: usize = 20;
+const D_SYMBOLS: usize = 64;
+
+/// Number of extra bits following each literal length, match length and distance symbol.
+const L_EXTRA_BITS: [u8; L_SYMBOLS] = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 3, 5, 8];
+const M_EXTRA_BITS: [u8; M_SYMBOLS] = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3, 5, 8, 11];
+const D_EXTRA_BITS: [u8; D_SYMBOLS] = [
+    0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 6, 6, 6, 6, 7, 7, 7, 7,
+    8, 8, 8, 8, 9, 9, 9, 9, 10, 10, 10, 10, 11, 11, 11, 11, 12, 12, 12, 12, 13, 13, 13, 13, 14, 14,
+    14, 14, 15, 15, 15, 15,
+];
+
+/// Decompresses an LZFSE stream, up to its end-of-stream block.
+pub(crate) fn decompress(data: &[u8]) -> Result<Vec<u8>, OperationError> {
+    let mut out = vec![];
+    let mut pos = 0;
+
+    loop {
+        let block = &data[pos.min(data.len())..];
+        let start = out.len();
+
+        let (block_len, raw_len) = match le32(block, 0)? {
+            MAGIC_END => return Ok(out),
+            MAGIC_RAW => {
+                let len = le32(block, 4)? as usize;
+                let block_len = len
+                    .checked_add(8)
+                    .ok_or_else(|| malformed("invalid block length"))?;
+                out.extend_from_slice(
+                    block
+                        .get(8..block_len)
+                        .ok_or_else(|| malformed("truncated block"))?,
+                );
+                (block_len, len)
+            }
+            MAGIC_LZVN => {
+                let raw_len = le32(block, 4)? as usize;
+                let payload_len = le32(block, 8)? as usize;
+                let block_len = payload_len
+                    .checked_add(12)
+                    .ok_or_else(|| malformed("invalid block length"))?;
+                let payload = block
+                    .get(12..block_len)
+                    .ok_or_else(|| malformed("truncated block"))?;
+                lzvn_decode(payload, &mut out, raw_len)?;
+                (block_len, raw_len)
+            }
+            MAGIC_V2 => (decode_v2(block, &mut out)?, le32(block, 4)? as usize),
+            MAGIC_V1 => return Err(malformed("uncompressed block headers are not supported")),
+            _ => return Err(malformed("invalid block magic")),
+        };
+
+        if out.len() - start != raw_len {
+            return Err(malformed("block size mismatch"));
+        }
+        pos += block_len;
+    }
+}
+
+/// Decodes a compressed block with a packed (v2) header, returning the block's length.
+fn decode_v2(block: &[u8], out: &mut Vec<u8>) -> Result<usize, OperationError> {
+    let fields = [le64(block, 8)?, le64(block, 16)?, le64(block, 24)?];
+
+    let n_literals = field(fields[0], 0, 20);
+    let literal_payload_len = field(fields[0], 20, 20);
+    let n_matches = field(fields[0], 40, 20);
+    let literal_bits = field(fields[0], 60, 3) as i32 - 7;
+    let literal_states = [
+        field(fields[1], 0, 10),
+        field(fields[1], 10, 10),
+        field(fields[1], 20, 10),
+        field(fields[1], 30, 10),
+    ];
+    let lmd_payload_len = field(fields[1], 40, 20);
+    let lmd_bits = field(fields[1], 60, 3) as i32 - 7;
+    let header_len = field(fields[2], 0, 32);
+    let lmd_states = [
+        field(fields[2], 32, 10),
+        field(fields[2], 42, 10),
+        field(fields[2], 52, 10),
+    ];
+
+    let freqs = decode_frequencies(
+        block
+            .get(V2_HEADER_LEN..header_len)
+            .ok_or_else(|| malformed("truncated block header"))?,
+    )?;
+    let (l_freqs, freqs) = freqs.split_at(L_SYMBOLS);
+    let (m_freqs, freqs) = freqs.split_at(M_SYMBOLS);
+    let (d_freqs, literal_freqs) = freqs.split_at(D_SYMBOLS);
+
+    let payload_end = header_len + literal_payload_len + lmd_payload_len;
+    let literal_payload = block
+        .get(header_len..header_len + literal_payload_len)
+        .ok_or_else(|| malformed("truncated block"))?;
+    let lmd_payload = block
+        .get(header_len + literal_payload_len..payload_end)
+        .ok_or_else(|| malformed("truncated block"))?;
+
+    let literal_table = symbol_table(LITERAL_STATES, literal_freqs)?;
+    let mut reader = BitReader::new(literal_payload, literal_bits)?;
+    let mut states = literal_states;
+    let mut literals = Vec::with_capacity(n_literals + 3);
+    while literals.len() < n_literals {
+        reader.refill()?;
+        for state in &mut states {
+            literals.push(decode_symbol(state, &literal_table, &mut reader)?);
+        }
+    }
+    literals.truncate(n_literals);
+
+    let l_table = value_table(L_STATES, l_freqs, &L_EXTRA_BITS)?;
+    let m_table = value_table(M_STATES, m_freqs, &M_EXTRA_BITS)?;
+    let d_table = value_table(D_STATES, d_freqs, &D_EXTRA_BITS)?;
+    let [mut l_state, mut m_state, mut d_state] = lmd_states;
+    let mut reader = BitReader::new(lmd_payload, lmd_bits)?;
+    let mut literals = &literals[..];
+    let mut distance = 0;
+
+    for _ in 0..n_matches {
+        reader.refill()?;
+        let literal_len = decode_value(&mut l_state, &l_table, &mut reader)?;
+        let match_len = decode_value(&mut m_state, &m_table, &mut reader)?;
+        // A zero distance repeats the previous one.
+        match decode_value(&mut d_state, &d_table, &mut reader)? {
+            0 => {}
+            d => distance = d,
+        }
+
+        if literal_len > literals.len() {
+            return Err(malformed("literal length out of range"));
+        }
+        out.extend_from_slice(&literals[..literal_len]);
+        literals = &literals[literal_len..];
+        copy_match(out, distance, match_len)?;
+    }
+
+    Ok(payload_end)
+}
+
+/// Decodes the frequency tables of the literal length, match length, distance and literal
+/// symbols, stored using a variable-length code.
+fn decode_frequencies(data: &[u8]) -> Result<Vec<usize>, OperationError> {
+    let mut freqs = vec![0; L_SYMBOLS + M_SYMBOLS + D_SYMBOLS + LITERAL_SYMBOLS];
+    let mut bytes = data.iter();
+    let mut accum = 0u32;
+    let mut accum_bits = 0;
+
+    for freq in &mut freqs {
+        while accum_bits <= 24 {
+            match bytes.next() {
+                Some(&byte) => {
+                    accum |= (byte as u32) << accum_bits;
+                    accum_bits += 8;
+                }
+                None => break,
+            }
+        }
+
+        let (bits, value) = match accum & 0x1f {
+            b if b & 0b11 == 0b00 => (2, 0),
+            b if b & 0b11 == 0b10 => (2, 1),
+            b if b & 0b111 == 0b001 => (3, 2),
+            b if b & 0b111 == 0b101 => (3, 3),
+            0b00011 => (5, 4),
+            0b01011 => (5, 5),
+            0b10011 => (5, 6),
+            0b11011 => (5, 7),
+            b if b & 0b1111 == 0b0111 => (8, 8 + (accum >> 4 & 0xf)),
+            _ => (14, 24 + (accum >> 4 & 0x3ff)),
+        };
+        if bits > accum_bits {
+            return Err(malformed("truncated frequency table"));
+        }
+
+        *freq = value as usize;
+        accum >>= bits;
+        accum_bits -= bits;
+    }
+
+    if accum_bits >= 8 || bytes.next().is_some() {
+        return Err(malformed("invalid frequency table"));
+    }
+
+    Ok(freqs)
+}
+
+/// Entry of an FSE decoding table, giving the symbol for a state and how to reach the next.
+#[derive(Clone, Copy)]
+struct SymbolEntry {
+    symbol: u8,
+    bits: u32,
+    delta: usize,
+}
+
+/// Entry of an FSE decoding table whose symbols stand for a base value plus extra bits.
+#[derive(Clone, Copy)]
+struct ValueEntry {
+    /// State bits followed by value bits.
+    total_bits: u32,
+    value_bits: u32,
+    base: usize,
+    delta: usize,
+}
+
+/// Distributes `states` decoder states over the symbols in proportion to `freqs`, returning
+/// the number of state bits and next-state offset of each state, grouped by symbol.
+fn spread_states(
+    states: usize,
+    freqs: &[usize],
+) -> Result<Vec<(usize, u32, usize)>, OperationError> {
+    if freqs.iter().sum::<usize>() > states {
+        return Err(malformed("invalid frequency table"));
+    }
+
+    let states_clz = (states as u32).leading_zeros();
+    let mut table = Vec::with_capacity(states);
+
+    for (symbol, &freq) in freqs.iter().enumerate().filter(|(_, &freq)| freq != 0) {
+        // Shift such that states <= (freq << k) < 2 * states.
+        let k = (freq as u32).leading_zeros() - states_clz;
+        let j0 = ((2 * states) >> k) - freq;
+
+        for j in 0..freq {
+            table.push(if j < j0 {
+                (symbol, k, ((freq + j) << k) - states)
+            } else {
+                (symbol, k - 1, (j - j0) << (k - 1))
+            });
+        }
+    }
+
+    Ok(table)
+}
+
+fn symbol_table(states: usize, freqs: &[usize]) -> Result<Vec<SymbolEntry>, OperationError> {
+    Ok(spread_states(states, freqs)?
+        .into_iter()
+        .map(|(symbol, bits, delta)| SymbolEntry {
+            symbol: symbol as u8,
+            bits,
+            delta,
+        })
+        .collect())
+}
+
+fn value_table(
+    states: usize,
+    freqs: &[usize],
+    extra_bits: &[u8],
+) -> Result<Vec<ValueEntry>, OperationError> {
+    // Each symbol's base value follows the range covered by the previous one.
+    let mut bases = vec![0; extra_bits.len()];
+    for i in 1..extra_bits.len() {
+        bases[i] = bases[i - 1] + (1 << extra_bits[i - 1]);
+    }
+
+    Ok(spread_states(states, freqs)?
+        .into_iter()
+        .map(|(symbol, bits, delta)| ValueEntry {
+            total_bits: bits + extra_bits[symbol] as u32,
+            value_bits: extra_bits[symbol] as u32,
+            base: bases[symbol],
+            delta,
+        })
+        .collect())
+}
+
+fn decode_symbol(
+    state: &mut usize,
+    table: &[SymbolEntry],
+    reader: &mut BitReader,
+) -> Result<u8, OperationError> {
+    let entry = table
+        .get(*state)
+        .ok_or_else(|| malformed("invalid decoder state"))?;
+    *state = entry.delta + reader.pull(entry.bits)? as usize;
+    Ok(entry.symbol)
+}
+
+fn decode_value(
+    state: &mut usize,
+    table: &[ValueEntry],
+    reader: &mut BitReader,
+) -> Result<usize, OperationError> {
+    let entry = table
+        .get(*state)
+        .ok_or_else(|| malformed("invalid decoder state"))?;
+    let bits = reader.pull(entry.total_bits)? as usize;
+    *state = entry.delta + (bits >> entry.value_bits);
+    Ok(entry.base + (bits & ((1 << entry.value_bits) - 1)))
+}
+
+/// Reads an FSE bit stream, which is consumed backwards from the end of its buffer, most
+/// significant bits first.
+struct BitReader<'a> {
+    data: &'a [u8],
+    /// Bytes of `data` not yet loaded into `accum`.
+    remaining: usize,
+    accum: u64,
+    accum_bits: u32,
+}
+
+impl<'a> BitReader<'a> {
+    /// `initial_bits` is zero, or minus the number of unused bits in the last byte.
+    fn new(data: &'a [u8], initial_bits: i32) -> Result<Self, OperationError> {
+        let len = if initial_bits == 0 { 7 } else { 8 };
+        if data.len() < len {
+            return Err(malformed("truncated bit stream"));
+        }
+
+        let remaining = data.len() - len;
+        let accum = data[remaining..]
+            .iter()
+            .rev()
+            .fold(0u64, |accum, &byte| accum << 8 | byte as u64);
+        let accum_bits = (len as i32 * 8 + initial_bits) as u32;
+
+        if !(56..64).contains(&accum_bits) || accum >> accum_bits != 0 {
+            return Err(malformed("invalid bit stream"));
+        }
+
+        Ok(BitReader {
+            data,
+            remaining,
+            accum,
+            accum_bits,
+        })
+    }
+
+    /// Loads whole bytes until at least 56 bits are available.
+    fn refill(&mut self) -> Result<(), OperationError> {
+        let len = ((63 - self.accum_bits) / 8) as usize;
+        if len > self.remaining {
+            return Err(malformed("truncated bit stream"));
+        }
+
+        self.remaining -= len;
+        for &byte in self.data[self.remaining..self.remaining + len].iter().rev() {
+            self.accum = self.accum << 8 | byte as u64;
+        }
+        self.accum_bits += len as u32 * 8;
+
+        Ok(())
+    }
+
+    fn pull(&mut self, bits: u32) -> Result<u64, OperationError> {
+        if bits > self.accum_bits {
+            return Err(malformed("truncated bit stream"));
+        }
+
+        self.accum_bits -= bits;
+        let value = self.accum >> self.accum_bits;
+        self.accum &= (1 << self.accum_bits) - 1;
+        Ok(value)
+    }
+}
+
+/// Decodes an LZVN payload, appending at most `raw_len` bytes to `out`.
+fn lzvn_decode(payload: &[u8], out: &mut Vec<u8>, raw_len: usize) -> Result<(), OperationError> {
+    let end = out.len() + raw_len;
+    let byte = |pos: usize| {
+        payload
+            .get(pos)
+            .map(|&byte| byte as usize)
+            .ok_or_else(|| malformed("truncated LZVN payload"))
+    };
+
+    let mut pos = 0;
+    let mut distance = 0;
+
+    while out.len() < end {
+        let op = byte(pos)?;
+
+        // Literal length, match length, opcode length, and distance if not the previous one.
+        let (literal_len, match_len, op_len, new_distance) = match op {
+            // End of stream
+            0x06 => break,
+            // No operation
+            0x0e | 0x16 => (0, 0, 1, None),
+            0x1e | 0x26 | 0x2e | 0x36 | 0x3e | 0x70..=0x7f | 0xd0..=0xdf => {
+                return Err(malformed("invalid LZVN opcode"))
+            }
+            // Large and small literal
+            0xe0 => (byte(pos + 1)? + 16, 0, 2, None),
+            0xe1..=0xef => (op & 0x0f, 0, 1, None),
+            // Large and small match with the previous distance
+            0xf0 => (0, byte(pos + 1)? + 16, 2, None),
+            0xf1..=0xff => (0, op & 0x0f, 1, None),
+            // Medium distance: 101LLMMM DDDDDDMM DDDDDDDD
+            0xa0..=0xbf => {
+                let (b1, b2) = (byte(pos + 1)?, byte(pos + 2)?);
+                let match_len = ((op & 0x07) << 2 | (b1 & 0x03)) + 3;
+                (op >> 3 & 0x03, match_len, 3, Some(b1 >> 2 | b2 << 6))
+            }
+            // Previous distance: LLMMM110
+            _ if op & 0x07 == 6 => (op >> 6, (op >> 3 & 0x07) + 3, 1, None),
+            // Large distance: LLMMM111 DDDDDDDD DDDDDDDD
+            _ if op & 0x07 == 7 => {
+                let distance = byte(pos + 1)? | byte(pos + 2)? << 8;
+                (op >> 6, (op >> 3 & 0x07) + 3, 3, Some(distance))
+            }
+            // Small distance: LLMMMDDD DDDDDDDD
+            _ => {
+                let distance = (op & 0x07) << 8 | byte(pos + 1)?;
+                (op >> 6, (op >> 3 & 0x07) + 3, 2, Some(distance))
+            }
+        };
+
+        pos += op_len;
+        let literals = payload
+            .get(pos..pos + literal_len)
+            .ok_or_else(|| malformed("truncated LZVN payload"))?;
+        pos += literal_len;
+
+        if out.len() + literals.len() + match_len > end {
+            return Err(malformed("LZVN output exceeds block size"));
+        }
+        out.extend_from_slice(literals);
+
+        if let Some(new_distance) = new_distance {
+            distance = new_distance;
+        }
+        copy_match(out, distance, match_len)?;
+    }
+
+    Ok(())
+}
+
+/// Appends `len` bytes copied from `distance` bytes back, possibly overlapping the bytes being
+/// appended.
+fn copy_match(out: &mut Vec<u8>, distance: usize, len: usize) -> Result<(), OperationError> {
+    if len == 0 {
+        return Ok(());
+    }
+    if distance == 0 || distance > out.len() {
+        return Err(malformed("match distance out of range"));
+    }
+
+    let start = out.len() - distance;
+    for i in 0..len {
+        out.push(out[start + i]);
+    }
+    Ok(())
+}
+
+fn field(value: u64, offset: u32, bits: u32) -> usize {
+    (value >> offset & ((1 << bits) - 1)) as usize
+}
+
+fn le32(data: &[u8], offset: usize) -> Result<u32, OperationError> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .ok_or_else(|| malformed("truncated block"))?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn le64(data: &[u8], offset: usize) -> Result<u64, OperationError> {
+    let mut value = [0; 8];
+    value.copy_from_slice(
+        data.get(offset..offset + 8)
+            .ok_or_else(|| malformed("truncated block"))?,
+    );
+    Ok(u64::from_le_bytes(value))
+}
+
+fn malformed(detail: &str) -> OperationError {
+    OperationError::detail("Malformed LZFSE data", detail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `values` as an FSE bit stream, preceded by padding the decoder may load but
+    /// never consumes. Returns the stream and its initial bit count.
+    fn bit_stream(values: &[(u64, u32)]) -> (Vec<u8>, i32) {
+        let total_bits = values.iter().map(|(_, bits)| *bits as usize).sum::<usize>();
+        let len = 8 + total_bits.div_ceil(8);
+        let unused = (len * 8 - 64 - total_bits) % 8;
+
+        // Bit positions count down from the most significant bit of the last byte.
+        let mut data = vec![0; len];
+        let mut position = unused;
+        for &(value, bits) in values {
+            for i in (0..bits).rev() {
+                if value >> i & 1 != 0 {
+                    data[len - 1 - position / 8] |= 0x80 >> (position % 8);
+                }
+                position += 1;
+            }
+        }
+
+        (data, -(unused as i32))
+    }
+
+    /// Encodes frequencies using the 2-bit code for zero and the 14-bit code otherwise.
+    fn frequency_table(freqs: &[usize]) -> Vec<u8> {
+        let mut out = vec![];
+        let mut accum = 0u64;
+        let mut accum_bits = 0;
+        for &freq in freqs {
+            if freq == 0 {
+                accum_bits += 2;
+            } else {
+                accum |= (0b1111 | (freq as u64 - 24) << 4) << accum_bits;
+                accum_bits += 14;
+            }
+            while accum_bits >= 8 {
+                out.push(accum as u8);
+                accum >>= 8;
+                accum_bits -= 8;
+            }
+        }
+        if accum_bits > 0 {
+            out.push(accum as u8);
+        }
+        out
+    }
+
+    #[test]
+    fn test_raw_and_lzvn_blocks() {
+        let mut data = b"bvx-".to_vec();
+        data.extend(&5u32.to_le_bytes());
+        data.extend(b"hello");
+
+        // Literals "ab" and a match of 4 at distance 2, a small literal "c", then a match of 3
+        // with the previous distance.
+        let payload = [
+            0x88, 0x02, b'a', b'b', 0xe1, b'c', 0xf3, 0x06, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        data.extend(b"bvxn");
+        data.extend(&10u32.to_le_bytes());
+        data.extend(&(payload.len() as u32).to_le_bytes());
+        data.extend(&payload);
+        data.extend(b"bvx$");
+
+        assert_eq!(decompress(&data).unwrap(), b"helloabababcbcb");
+    }
+
+    #[test]
+    fn test_compressed_block() {
+        // Every state decodes to the same symbol without consuming state bits: literal 'x',
+        // literal length 3, match length 312 plus 11 extra bits, and distance 3.
+        let mut freqs = vec![0; L_SYMBOLS + M_SYMBOLS + D_SYMBOLS + LITERAL_SYMBOLS];
+        freqs[3] = L_STATES;
+        freqs[L_SYMBOLS + 19] = M_STATES;
+        freqs[L_SYMBOLS + M_SYMBOLS + 3] = D_STATES;
+        freqs[L_SYMBOLS + M_SYMBOLS + D_SYMBOLS + b'x' as usize] = LITERAL_STATES;
+        let header_freqs = frequency_table(&freqs);
+
+        let (literal_payload, literal_bits) = bit_stream(&[]);
+        let (lmd_payload, lmd_bits) = bit_stream(&[(0, 0), (5, 11), (0, 0), (1, 11)]);
+        let n_literals = 6u64;
+        let n_matches = 2u64;
+        let raw_len = 6 + 312 + 5 + 312 + 1;
+
+        let header_len = (V2_HEADER_LEN + header_freqs.len()) as u64;
+        let mut data = b"bvx2".to_vec();
+        data.extend(&(raw_len as u32).to_le_bytes());
+        data.extend(
+            &(n_literals
+                | (literal_payload.len() as u64) << 20
+                | n_matches << 40
+                | ((literal_bits + 7) as u64) << 60)
+                .to_le_bytes(),
+        );
+        data.extend(
+            &((lmd_payload.len() as u64) << 40 | ((lmd_bits + 7) as u64) << 60).to_le_bytes(),
+        );
+        data.extend(&header_len.to_le_bytes());
+        data.extend(header_freqs);
+        data.extend(literal_payload);
+        data.extend(lmd_payload);
+        data.extend(b"bvx$");
+
+        let out = decompress(&data).unwrap();
+        assert_eq!(out.len(), raw_len);
+        assert!(out.iter().all(|&byte| byte == b'x'));
+    }
+
+    #[test]
+    fn test_spread_states() {
+        // Frequencies 3 and 1 over four states: the more frequent symbol needs fewer bits.
+        let table = spread_states(4, &[3, 1]).unwrap();
+        assert_eq!(table, vec![(0, 1, 2), (0, 0, 0), (0, 0, 1), (1, 2, 0)]);
+        assert!(spread_states(4, &[3, 2]).is_err());
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert!(decompress(b"bvx-\x05\x00\x00\x00hel").is_err());
+        assert!(decompress(b"bvxz").is_err());
+        // Missing end of stream block
+        assert!(decompress(b"bvx-\x01\x00\x00\x00a").is_err());
+        // Match before any output
+        assert!(decompress(b"bvxn\x03\x00\x00\x00\x02\x00\x00\x00\x00\x03bvx$").is_err());
+    }
+}
@@ -0,0 +1,94 @@
+//! Archive formats used for notarization submissions, installer and disk image contents.
+
+mod apfs;
+mod apple_double;
+mod cpio;
+pub(crate) mod disk_image;
+mod hfs;
+mod lzfse;
+pub(crate) mod package;
+mod pbzx;
+pub(crate) mod udif;
+mod xar;
+pub(crate) mod zip;
+
+use std::fs;
+use std::io;
+use std::path::{Component, Path};
+
+use crate::util::OperationError;
+
+/// Creates `dir` below `root`. Before anything is created, fails if `dir` is not below `root`
+/// or if any existing component of it is a symlink, e.g. one extracted earlier.
+fn create_dir_within(root: &Path, dir: &Path) -> io::Result<()> {
+    let relative = dir
+        .strip_prefix(root)
+        .map_err(|_| unsafe_path(&dir.display().to_string()))?;
+
+    let mut path = root.to_path_buf();
+    for component in relative.components() {
+        match component {
+            Component::Normal(name) => path.push(name),
+            Component::CurDir => continue,
+            _ => return Err(unsafe_path(&dir.display().to_string())),
+        }
+
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                return Err(unsafe_path(&dir.display().to_string()))
+            }
+            Ok(_) => {}
+            // Nothing below a missing component exists yet.
+            Err(err) if err.kind() == io::ErrorKind::NotFound => break,
+            Err(err) => return Err(err),
+        }
+    }
+
+    fs::create_dir_all(dir)
+}
+
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0];
+
+fn is_xz(data: &[u8]) -> bool {
+    data.starts_with(XZ_MAGIC)
+}
+
+fn xz_decompress(data: &[u8]) -> Result<Vec<u8>, OperationError> {
+    let mut out = vec![];
+    lzma_rs::xz_decompress(&mut &data[..], &mut out)
+        .map_err(|err| OperationError::detail("Could not decompress XZ data", &err.to_string()))?;
+    Ok(out)
+}
+
+fn unsafe_path(name: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Archive entry {} points outside the destination", name),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use super::create_dir_within;
+
+    #[test]
+    fn test_create_dir_within() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root = temp_dir.path().join("root");
+        let outside = temp_dir.path().join("outside");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+
+        create_dir_within(&root, &root.join("Foo.app/Contents")).unwrap();
+        assert!(root.join("Foo.app/Contents").is_dir());
+
+        symlink(&outside, root.join("link")).unwrap();
+        assert!(create_dir_within(&root, &root.join("link/escaped/deeper")).is_err());
+        assert!(!outside.join("escaped").exists());
+
+        assert!(create_dir_within(&root, &root.join("../outside/escaped")).is_err());
+        assert!(!outside.join("escaped").exists());
+    }
+}
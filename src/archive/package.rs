@@ -0,0 +1,241 @@
+//! Extraction of flat installer packages: either a single component package, or a product
+//! archive whose `Distribution` references several components.
+
+use flate2::read::GzDecoder;
+use std::path::{Path, PathBuf};
+use tempfile::{Builder as TempFileBuilder, TempDir};
+
+use super::xar::XarArchive;
+use super::{cpio, pbzx};
+use crate::util::xml;
+use crate::util::OperationError;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+/// Installer package whose payloads were extracted to a temporary directory, removed on drop.
+pub(crate) struct ExtractedPackage {
+    _dir: TempDir,
+    pub(crate) components: Vec<Component>,
+}
+
+pub(crate) struct Component {
+    /// Package identifier from `PackageInfo`, e.g. com.example.app.pkg.
+    pub(crate) identifier: Option<String>,
+    /// Directory the payload was extracted to, corresponding to the install location.
+    pub(crate) root: PathBuf,
+}
+
+/// Extracts the payload of every component in the package at `pkg_path`. Components of a
+/// product archive are extracted to subdirectories named after them, e.g. `Foo.pkg`.
+pub(crate) fn extract(pkg_path: &Path) -> Result<ExtractedPackage, OperationError> {
+    let archive = XarArchive::open(pkg_path)?;
+
+    let dir = TempFileBuilder::new().tempdir().map_err(|err| {
+        OperationError::detail("Could not create temporary directory", &err.to_string())
+    })?;
+
+    let component_paths = match archive.file("Distribution") {
+        Some(distribution) => distribution_components(&archive, &archive.read(distribution)?)?,
+        None => vec![String::new()],
+    };
+
+    let mut components = vec![];
+    for component_path in component_paths {
+        let prefix = if component_path.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", component_path)
+        };
+
+        let identifier = match archive.file(&format!("{}PackageInfo", prefix)) {
+            Some(file) => xml::parse(&archive.read(file)?)?
+                .attribute("identifier")
+                .map(String::from),
+            None => None,
+        };
+
+        // Components may consist of scripts only.
+        let payload = match archive.file(&format!("{}Payload", prefix)) {
+            Some(file) => archive.read(file)?,
+            None => continue,
+        };
+
+        let root = dir.path().join(&component_path);
+        extract_payload(&payload, &root).map_err(|err| {
+            OperationError::detail(
+                &format!(
+                    "Could not extract payload of {}",
+                    identifier.as_deref().unwrap_or_else(|| pkg_path
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .unwrap_or_default())
+                ),
+                &err.to_string(),
+            )
+        })?;
+
+        components.push(Component { identifier, root });
+    }
+
+    Ok(ExtractedPackage {
+        _dir: dir,
+        components,
+    })
+}
+
+/// DER-encoded certificates the package is signed with, starting with the signing certificate.
+/// Empty if the package is not signed.
+pub(crate) fn signing_certificates(pkg_path: &Path) -> Result<Vec<Vec<u8>>, OperationError> {
+    XarArchive::open(pkg_path)?.signing_certificates()
+}
+
+/// Hash algorithm, e.g. `sha1`, and value of the checksum of the package's table of contents,
+/// which identifies the package to the notarization service.
+pub(crate) fn toc_checksum(pkg_path: &Path) -> Result<(String, Vec<u8>), OperationError> {
+    XarArchive::open(pkg_path)?.toc_checksum()?.ok_or_else(|| {
+        OperationError::new(&format!(
+            "Installer package {} has no table of contents checksum",
+            pkg_path.display()
+        ))
+    })
+}
+
+/// Paths of the components referenced by a product archive's `Distribution`, in order.
+fn distribution_components(
+    archive: &XarArchive,
+    distribution: &[u8],
+) -> Result<Vec<String>, OperationError> {
+    let distribution = xml::parse(distribution)?;
+
+    let mut refs = vec![];
+    distribution.descendants_named("pkg-ref", &mut refs);
+
+    let mut components: Vec<String> = vec![];
+    for pkg_ref in refs {
+        // References to components within the archive take the form "#Foo.pkg".
+        let path = match pkg_ref.text.trim().strip_prefix('#') {
+            Some(path) => percent_decode(path),
+            None => continue,
+        };
+        if !components.contains(&path) && archive.file(&path).is_some() {
+            components.push(path);
+        }
+    }
+
+    Ok(components)
+}
+
+fn extract_payload(payload: &[u8], dest: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    if payload.starts_with(GZIP_MAGIC) {
+        cpio::extract(GzDecoder::new(payload), dest)?;
+    } else if pbzx::is_pbzx(payload) {
+        cpio::extract(&pbzx::decompress(payload)?[..], dest)?;
+    } else if cpio::is_cpio(payload) {
+        cpio::extract(payload, dest)?;
+    } else {
+        return Err(OperationError::new("Unsupported payload format").into());
+    }
+
+    Ok(())
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let decoded = if bytes[i] == b'%' {
+            s.get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+
+        match decoded {
+            Some(byte) => {
+                out.push(byte);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&out).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    use super::super::{cpio, xar};
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_extract_component_package() {
+        let mut path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests/generated_artifacts/pkg/signed_with_correctly_signed_app.pkg");
+
+        let extracted = super::extract(&path).unwrap();
+        assert_eq!(extracted.components.len(), 1);
+
+        let component = &extracted.components[0];
+        assert_eq!(component.identifier.as_deref(), Some("com.xcnotary.test"));
+        assert!(component
+            .root
+            .join("correctly_signed.app/Contents/MacOS/correctly_signed")
+            .is_file());
+    }
+
+    #[test]
+    fn test_extract_product_archive() {
+        let payload = cpio::tests::build(&[(".", 0o040755, b""), ("./Tool", 0o100755, b"tool")]);
+        let scripts_only = br#"<pkg-info identifier="com.example.scripts"/>"#;
+
+        let pkg = xar::tests::build(&[
+            (
+                "Distribution",
+                br#"<?xml version="1.0" encoding="utf-8"?>
+                <installer-gui-script minSpecVersion="1">
+                    <choice id="default"><pkg-ref id="com.example.tool"/></choice>
+                    <pkg-ref id="com.example.tool" version="1.0">#My%20Tool.pkg</pkg-ref>
+                    <pkg-ref id="com.example.scripts">#Scripts.pkg</pkg-ref>
+                </installer-gui-script>"#,
+            ),
+            (
+                "My Tool.pkg/PackageInfo",
+                br#"<pkg-info identifier="com.example.tool" install-location="/usr/local/bin"/>"#,
+            ),
+            ("My Tool.pkg/Payload", &gzip(&payload)),
+            ("Scripts.pkg/PackageInfo", scripts_only),
+        ]);
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("Product.pkg");
+        std::fs::write(&path, pkg).unwrap();
+
+        let extracted = super::extract(&path).unwrap();
+        assert_eq!(extracted.components.len(), 1);
+
+        let component = &extracted.components[0];
+        assert_eq!(component.identifier.as_deref(), Some("com.example.tool"));
+        assert_eq!(component.root.file_name().unwrap(), "My Tool.pkg");
+        assert_eq!(std::fs::read(component.root.join("Tool")).unwrap(), b"tool");
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(super::percent_decode("My%20Tool.pkg"), "My Tool.pkg");
+        assert_eq!(super::percent_decode("100%"), "100%");
+    }
+}
@@ -0,0 +1,105 @@
+//! Decoding of the chunked "pbzx" payload format used by recent installer packages, where each
+//! chunk is either an XZ stream or stored uncompressed.
+
+use super::{is_xz, xz_decompress};
+use crate::util::OperationError;
+
+const MAGIC: &[u8] = b"pbzx";
+/// Flag set on the header and each chunk if another chunk follows.
+const MORE_CHUNKS: u64 = 1 << 24;
+
+pub(super) fn is_pbzx(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+pub(super) fn decompress(data: &[u8]) -> Result<Vec<u8>, OperationError> {
+    if !is_pbzx(data) {
+        return Err(malformed());
+    }
+
+    let mut out = vec![];
+    let mut pos = MAGIC.len();
+    let mut flags = be64(data, pos)?;
+    pos += 8;
+
+    while flags & MORE_CHUNKS != 0 {
+        flags = be64(data, pos)?;
+        let len = be64(data, pos + 8)? as usize;
+        pos += 16;
+
+        let end = pos.checked_add(len).ok_or_else(malformed)?;
+        let chunk = data.get(pos..end).ok_or_else(malformed)?;
+        pos = end;
+
+        if is_xz(chunk) {
+            out.extend(xz_decompress(chunk)?);
+        } else {
+            out.extend_from_slice(chunk);
+        }
+    }
+
+    Ok(out)
+}
+
+fn be64(data: &[u8], offset: usize) -> Result<u64, OperationError> {
+    let bytes = data.get(offset..offset + 8).ok_or_else(malformed)?;
+    let mut value = [0; 8];
+    value.copy_from_slice(bytes);
+    Ok(u64::from_be_bytes(value))
+}
+
+fn malformed() -> OperationError {
+    OperationError::new("Malformed pbzx payload")
+}
+
+#[cfg(test)]
+mod tests {
+    fn chunk(flags: u64, data: &[u8]) -> Vec<u8> {
+        [
+            &flags.to_be_bytes()[..],
+            &(data.len() as u64).to_be_bytes(),
+            data,
+        ]
+        .concat()
+    }
+
+    /// `printf 'hello hello hello hello\n' | xz`
+    const HELLO_XZ: &[u8] = &[
+        0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00, 0x00, 0x04, 0xe6, 0xd6, 0xb4, 0x46, 0x04, 0xc0, 0x14,
+        0x18, 0x21, 0x01, 0x16, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x2c, 0xf3,
+        0x46, 0x45, 0xe0, 0x00, 0x17, 0x00, 0x0c, 0x5d, 0x00, 0x34, 0x19, 0x49, 0xee, 0x8d, 0xe9,
+        0x56, 0x0a, 0xc1, 0x21, 0xb0, 0x00, 0x00, 0x6c, 0x14, 0xed, 0x1b, 0x3b, 0x68, 0xbb, 0x91,
+        0x00, 0x01, 0x30, 0x18, 0x8e, 0x1b, 0xac, 0xec, 0x1f, 0xb6, 0xf3, 0x7d, 0x01, 0x00, 0x00,
+        0x00, 0x00, 0x04, 0x59, 0x5a,
+    ];
+
+    #[test]
+    fn test_decompress() {
+        let mut data = b"pbzx".to_vec();
+        data.extend(&super::MORE_CHUNKS.to_be_bytes());
+        data.extend(chunk(super::MORE_CHUNKS, b"hello "));
+        data.extend(chunk(super::MORE_CHUNKS, b"world\n"));
+        data.extend(chunk(0, HELLO_XZ));
+
+        assert_eq!(
+            super::decompress(&data).unwrap(),
+            b"hello world\nhello hello hello hello\n"
+        );
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut data = b"pbzx".to_vec();
+        data.extend(&super::MORE_CHUNKS.to_be_bytes());
+        data.extend(&chunk(0, b"hello")[..12]);
+
+        assert!(super::decompress(&data).is_err());
+        assert!(super::decompress(b"xbzp").is_err());
+
+        let mut data = b"pbzx".to_vec();
+        data.extend(&super::MORE_CHUNKS.to_be_bytes());
+        data.extend(&0u64.to_be_bytes());
+        data.extend(&u64::MAX.to_be_bytes());
+        assert!(super::decompress(&data).is_err());
+    }
+}
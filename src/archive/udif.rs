@@ -0,0 +1,509 @@
+//! Reader for UDIF disk images (`.dmg`): the partition list in the property list referenced by
+//! the `koly` trailer, and the compressed chunks making up each partition.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use flate2::read::ZlibDecoder;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read};
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+
+use super::{is_xz, lzfse, xz_decompress};
+use crate::util::xml::{self, Element};
+use crate::util::OperationError;
+
+const KOLY_MAGIC: &[u8] = b"koly";
+const TRAILER_LEN: u64 = 512;
+const MISH_MAGIC: &[u8] = b"mish";
+const MISH_HEADER_LEN: usize = 204;
+const CHUNK_LEN: usize = 40;
+pub(super) const SECTOR_SIZE: u64 = 512;
+
+const CHUNK_ZERO_FILL: u32 = 0x0000_0000;
+const CHUNK_RAW: u32 = 0x0000_0001;
+const CHUNK_IGNORE: u32 = 0x0000_0002;
+const CHUNK_ADC: u32 = 0x8000_0004;
+const CHUNK_ZLIB: u32 = 0x8000_0005;
+const CHUNK_BZIP2: u32 = 0x8000_0006;
+const CHUNK_LZFSE: u32 = 0x8000_0007;
+const CHUNK_LZMA: u32 = 0x8000_0008;
+const CHUNK_COMMENT: u32 = 0x7fff_fffe;
+const CHUNK_TERMINATOR: u32 = 0xffff_ffff;
+
+pub(super) struct UdifImage {
+    path: PathBuf,
+    file: File,
+    len: u64,
+    data_fork_offset: u64,
+    pub(super) partitions: Vec<Partition>,
+}
+
+/// Entry of the image's `blkx` table, e.g. a partition map or a filesystem.
+pub(super) struct Partition {
+    /// Name given by `hdiutil`, e.g. `disk image (Apple_HFS : 4)`.
+    pub(super) name: String,
+    pub(super) sector_count: u64,
+    /// Offset of the chunk data, relative to the data fork.
+    data_offset: u64,
+    chunks: Vec<Chunk>,
+}
+
+struct Chunk {
+    kind: u32,
+    /// First sector, relative to the start of the partition.
+    sector: u64,
+    sector_count: u64,
+    offset: u64,
+    length: u64,
+}
+
+/// Location of the embedded code signature in the trailer, added by `codesign`.
+const CODE_SIGNATURE_OFFSET: usize = 296;
+const CODE_SIGNATURE_LEN: usize = 304;
+
+impl UdifImage {
+    pub(super) fn open(path: &Path) -> Result<Self, OperationError> {
+        let error = |detail: &dyn std::fmt::Display| {
+            OperationError::detail(
+                &format!("Could not read disk image {}", path.display()),
+                &detail.to_string(),
+            )
+        };
+
+        let file = File::open(path).map_err(|err| error(&err))?;
+        let (len, trailer) = read_trailer(&file).map_err(|err| error(&err))?;
+
+        let data_fork_offset = be64(&trailer, 24);
+        let xml_offset = be64(&trailer, 216);
+        let xml_len = be64(&trailer, 224);
+        if xml_len == 0 || xml_offset.saturating_add(xml_len) > len {
+            return Err(error(&"Missing partition list"));
+        }
+
+        let mut plist = vec![0; xml_len as usize];
+        file.read_exact_at(&mut plist, xml_offset)
+            .map_err(|err| error(&err))?;
+        let plist = xml::parse(&plist)?;
+
+        let entries = plist
+            .child("dict")
+            .and_then(|root| dict_value(root, "resource-fork"))
+            .and_then(|fork| dict_value(fork, "blkx"))
+            .ok_or_else(|| error(&"Missing partition list"))?;
+
+        let partitions = entries
+            .children_named("dict")
+            .map(parse_partition)
+            .collect::<Result<_, _>>()
+            .map_err(|err| error(&err))?;
+
+        Ok(UdifImage {
+            path: path.into(),
+            file,
+            len,
+            data_fork_offset,
+            partitions,
+        })
+    }
+
+    /// Writes the decompressed contents of `partition` to `dest`. Zero-filled runs are left
+    /// as holes.
+    pub(super) fn decode(&self, partition: &Partition, dest: &File) -> Result<(), OperationError> {
+        let error = |detail: &dyn std::fmt::Display| {
+            OperationError::detail(
+                &format!(
+                    "Could not read {} from {}",
+                    partition.name,
+                    self.path.display()
+                ),
+                &detail.to_string(),
+            )
+        };
+
+        dest.set_len(partition.sector_count * SECTOR_SIZE)
+            .map_err(|err| error(&err))?;
+
+        for chunk in &partition.chunks {
+            let data = match chunk.kind {
+                CHUNK_ZERO_FILL | CHUNK_IGNORE | CHUNK_COMMENT | CHUNK_TERMINATOR => continue,
+                _ if chunk.length > self.len => return Err(error(&"Chunk outside of image")),
+                _ => {
+                    let mut data = vec![0; chunk.length as usize];
+                    self.file
+                        .read_exact_at(
+                            &mut data,
+                            self.data_fork_offset + partition.data_offset + chunk.offset,
+                        )
+                        .map_err(|err| error(&err))?;
+                    data
+                }
+            };
+
+            let expected_len = (chunk.sector_count * SECTOR_SIZE) as usize;
+            let mut decoded = match chunk.kind {
+                CHUNK_RAW => data,
+                CHUNK_ADC => adc_decompress(&data, expected_len).map_err(|err| error(&err))?,
+                CHUNK_ZLIB => read_all(ZlibDecoder::new(&data[..])).map_err(|err| error(&err))?,
+                CHUNK_BZIP2 => {
+                    read_all(bzip2_rs::DecoderReader::new(&data[..])).map_err(|err| error(&err))?
+                }
+                CHUNK_LZFSE => lzfse::decompress(&data)?,
+                CHUNK_LZMA if is_xz(&data) => xz_decompress(&data)?,
+                kind => return Err(error(&format!("Unsupported chunk type {:#010x}", kind))),
+            };
+
+            if decoded.len() < expected_len {
+                return Err(error(&"Truncated chunk"));
+            }
+            decoded.truncate(expected_len);
+
+            dest.write_all_at(&decoded, chunk.sector * SECTOR_SIZE)
+                .map_err(|err| error(&err))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Embedded signature SuperBlob of the disk image at `path`, or `None` if it is not signed.
+pub(crate) fn code_signature(path: &Path) -> Result<Option<Vec<u8>>, OperationError> {
+    let error = |detail: &dyn std::fmt::Display| {
+        OperationError::detail(
+            &format!("Could not read disk image {}", path.display()),
+            &detail.to_string(),
+        )
+    };
+
+    let file = File::open(path).map_err(|err| error(&err))?;
+    let (len, trailer) = read_trailer(&file).map_err(|err| error(&err))?;
+
+    let offset = be64(&trailer, CODE_SIGNATURE_OFFSET);
+    let signature_len = be64(&trailer, CODE_SIGNATURE_LEN);
+    if signature_len == 0 {
+        return Ok(None);
+    }
+    if offset.saturating_add(signature_len) > len - TRAILER_LEN {
+        return Err(error(&"Code signature extends beyond the image"));
+    }
+
+    let mut signature = vec![0; signature_len as usize];
+    file.read_exact_at(&mut signature, offset)
+        .map_err(|err| error(&err))?;
+    Ok(Some(signature))
+}
+
+/// Replaces the code signature of the disk image at `path` with `signature`, updating the
+/// trailer. A signature immediately preceding the trailer, as written by `codesign`, is
+/// overwritten; otherwise the new signature is placed after the image data.
+pub(crate) fn replace_code_signature(path: &Path, signature: &[u8]) -> Result<(), OperationError> {
+    let error = |detail: &dyn std::fmt::Display| {
+        OperationError::detail(
+            &format!("Could not update disk image {}", path.display()),
+            &detail.to_string(),
+        )
+    };
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .map_err(|err| error(&err))?;
+    let (len, mut trailer) = read_trailer(&file).map_err(|err| error(&err))?;
+
+    let trailer_offset = len - TRAILER_LEN;
+    let old_offset = be64(&trailer, CODE_SIGNATURE_OFFSET);
+    let old_len = be64(&trailer, CODE_SIGNATURE_LEN);
+    let offset = if old_len != 0 && old_offset.saturating_add(old_len) == trailer_offset {
+        old_offset
+    } else {
+        trailer_offset
+    };
+
+    trailer[CODE_SIGNATURE_OFFSET..CODE_SIGNATURE_OFFSET + 8]
+        .copy_from_slice(&offset.to_be_bytes());
+    trailer[CODE_SIGNATURE_LEN..CODE_SIGNATURE_LEN + 8]
+        .copy_from_slice(&(signature.len() as u64).to_be_bytes());
+
+    let new_trailer_offset = offset + signature.len() as u64;
+    file.write_all_at(signature, offset)
+        .and_then(|_| file.write_all_at(&trailer, new_trailer_offset))
+        .and_then(|_| file.set_len(new_trailer_offset + TRAILER_LEN))
+        .map_err(|err| error(&err))
+}
+
+/// Length of the image and its `koly` trailer.
+fn read_trailer(file: &File) -> io::Result<(u64, [u8; TRAILER_LEN as usize])> {
+    let len = file.metadata()?.len();
+    let mut trailer = [0; TRAILER_LEN as usize];
+    if len < TRAILER_LEN {
+        return Err(malformed("Not a UDIF disk image"));
+    }
+
+    file.read_exact_at(&mut trailer, len - TRAILER_LEN)?;
+    if !trailer.starts_with(KOLY_MAGIC) {
+        return Err(malformed("Not a UDIF disk image"));
+    }
+
+    Ok((len, trailer))
+}
+
+fn parse_partition(entry: &Element) -> io::Result<Partition> {
+    let name = dict_value(entry, "Name")
+        .or_else(|| dict_value(entry, "CFName"))
+        .map(|name| name.text.trim().to_string())
+        .unwrap_or_default();
+
+    let data: String = dict_value(entry, "Data")
+        .ok_or_else(|| malformed("partition without data"))?
+        .text
+        .split_ascii_whitespace()
+        .collect();
+    let data = STANDARD
+        .decode(data)
+        .map_err(|_| malformed("invalid partition data"))?;
+
+    if data.len() < MISH_HEADER_LEN || !data.starts_with(MISH_MAGIC) {
+        return Err(malformed("invalid partition data"));
+    }
+
+    let chunk_count = be32(&data, 200) as usize;
+    let chunks = data[MISH_HEADER_LEN..]
+        .chunks_exact(CHUNK_LEN)
+        .take(chunk_count)
+        .map(|chunk| Chunk {
+            kind: be32(chunk, 0),
+            sector: be64(chunk, 8),
+            sector_count: be64(chunk, 16),
+            offset: be64(chunk, 24),
+            length: be64(chunk, 32),
+        })
+        .collect::<Vec<_>>();
+    if chunks.len() != chunk_count {
+        return Err(malformed("truncated chunk table"));
+    }
+
+    let sector_count = be64(&data, 16);
+    if chunks
+        .iter()
+        .any(|chunk| chunk.sector.saturating_add(chunk.sector_count) > sector_count)
+    {
+        return Err(malformed("chunk outside of partition"));
+    }
+
+    Ok(Partition {
+        name,
+        sector_count,
+        data_offset: be64(&data, 24),
+        chunks,
+    })
+}
+
+/// Value following `<key>key</key>` in a property list `<dict>`.
+fn dict_value<'a>(dict: &'a Element, key: &str) -> Option<&'a Element> {
+    let mut children = dict.children.iter();
+    while let Some(child) = children.next() {
+        if child.name == "key" && child.text.trim() == key {
+            return children.next();
+        }
+    }
+    None
+}
+
+/// Decompresses Apple Data Compression, used by images created before zlib became the default.
+fn adc_decompress(data: &[u8], expected_len: usize) -> io::Result<Vec<u8>> {
+    let truncated = || malformed("truncated ADC data");
+    let mut out = Vec::with_capacity(expected_len);
+    let mut pos = 0;
+
+    while pos < data.len() && out.len() < expected_len {
+        let op = data[pos];
+
+        if op & 0x80 != 0 {
+            let len = (op & 0x7f) as usize + 1;
+            out.extend_from_slice(data.get(pos + 1..pos + 1 + len).ok_or_else(truncated)?);
+            pos += 1 + len;
+            continue;
+        }
+
+        let (len, distance) = if op & 0x40 != 0 {
+            let bytes = data.get(pos + 1..pos + 3).ok_or_else(truncated)?;
+            pos += 3;
+            (
+                (op & 0x3f) as usize + 4,
+                u16::from_be_bytes([bytes[0], bytes[1]]) as usize,
+            )
+        } else {
+            let byte = *data.get(pos + 1).ok_or_else(truncated)?;
+            pos += 2;
+            (
+                ((op & 0x3f) >> 2) as usize + 3,
+                ((op & 0x03) as usize) << 8 | byte as usize,
+            )
+        };
+
+        if distance >= out.len() {
+            return Err(malformed("invalid ADC match distance"));
+        }
+        // Matches may overlap the bytes they produce.
+        let start = out.len() - distance - 1;
+        for i in 0..len {
+            out.push(out[start + i]);
+        }
+    }
+
+    Ok(out)
+}
+
+fn read_all<R: Read>(mut reader: R) -> io::Result<Vec<u8>> {
+    let mut out = vec![];
+    reader.read_to_end(&mut out)?;
+    Ok(out)
+}
+
+fn be32(data: &[u8], offset: usize) -> u32 {
+    let mut value = [0; 4];
+    value.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_be_bytes(value)
+}
+
+fn be64(data: &[u8], offset: usize) -> u64 {
+    let mut value = [0; 8];
+    value.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_be_bytes(value)
+}
+
+fn malformed(detail: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, detail)
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    use super::{CHUNK_IGNORE, CHUNK_RAW, CHUNK_TERMINATOR, CHUNK_ZLIB, SECTOR_SIZE};
+
+    /// Builds a UDIF image with one partition per entry of `partitions`, storing every other
+    /// sector run zlib-compressed. Trailing zero sectors are stored as an ignored chunk.
+    pub(crate) fn build(partitions: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut image = vec![];
+        let mut blkx = String::new();
+
+        for (name, data) in partitions {
+            let sector_count = (data.len() as u64).div_ceil(SECTOR_SIZE);
+            let mut padded = data.to_vec();
+            padded.resize((sector_count * SECTOR_SIZE) as usize, 0);
+
+            let mut chunks = vec![];
+            for (i, run) in padded.chunks(8 * SECTOR_SIZE as usize).enumerate() {
+                let sector = i as u64 * 8;
+                let run_sectors = run.len() as u64 / SECTOR_SIZE;
+                let (kind, stored) = if run.iter().all(|&b| b == 0) {
+                    (CHUNK_IGNORE, vec![])
+                } else if i % 2 == 0 {
+                    let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+                    encoder.write_all(run).unwrap();
+                    (CHUNK_ZLIB, encoder.finish().unwrap())
+                } else {
+                    (CHUNK_RAW, run.to_vec())
+                };
+                chunks.push((kind, sector, run_sectors, image.len() as u64, stored.len()));
+                image.extend(stored);
+            }
+            chunks.push((CHUNK_TERMINATOR, sector_count, 0, 0, 0));
+
+            let mut mish = b"mish".to_vec();
+            mish.extend(&1u32.to_be_bytes());
+            mish.extend(&0u64.to_be_bytes());
+            mish.extend(&sector_count.to_be_bytes());
+            mish.extend(&0u64.to_be_bytes());
+            mish.resize(200, 0);
+            mish.extend(&(chunks.len() as u32).to_be_bytes());
+            for (kind, sector, count, offset, len) in chunks {
+                mish.extend(&kind.to_be_bytes());
+                mish.extend(&0u32.to_be_bytes());
+                mish.extend(&sector.to_be_bytes());
+                mish.extend(&count.to_be_bytes());
+                mish.extend(&offset.to_be_bytes());
+                mish.extend(&(len as u64).to_be_bytes());
+            }
+
+            blkx.push_str(&format!(
+                "<dict><key>Data</key><data>{}</data><key>Name</key><string>{}</string></dict>",
+                STANDARD.encode(&mish),
+                name
+            ));
+        }
+
+        let plist = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+            <plist version="1.0"><dict><key>resource-fork</key><dict><key>blkx</key><array>{}</array></dict></dict></plist>"#,
+            blkx
+        );
+
+        let mut trailer = b"koly".to_vec();
+        trailer.extend(&4u32.to_be_bytes());
+        trailer.extend(&512u32.to_be_bytes());
+        trailer.resize(216, 0);
+        trailer.extend(&(image.len() as u64).to_be_bytes());
+        trailer.extend(&(plist.len() as u64).to_be_bytes());
+        trailer.resize(512, 0);
+
+        image.extend(plist.as_bytes());
+        image.extend(trailer);
+        image
+    }
+
+    #[test]
+    fn test_decode() {
+        let data = (0..10_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let mut sparse = vec![0; 16 * SECTOR_SIZE as usize];
+        sparse.extend(b"end");
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.dmg");
+        std::fs::write(&path, build(&[("first", &data), ("second", &sparse)])).unwrap();
+
+        let image = super::UdifImage::open(&path).unwrap();
+        assert_eq!(
+            image
+                .partitions
+                .iter()
+                .map(|partition| partition.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["first", "second"]
+        );
+
+        for (partition, expected) in image.partitions.iter().zip(&[data, sparse]) {
+            let dest = tempfile::tempfile().unwrap();
+            image.decode(partition, &dest).unwrap();
+
+            let mut decoded = vec![];
+            std::io::Read::read_to_end(&mut &dest, &mut decoded).unwrap();
+            assert_eq!(&decoded[..expected.len()], &expected[..]);
+            assert!(decoded[expected.len()..].iter().all(|&b| b == 0));
+        }
+    }
+
+    #[test]
+    fn test_adc_decompress() {
+        // Literal "abc", then a two-byte match of 3 at distance 3 and a three-byte match of 4
+        // at distance 1.
+        let data = [0x82, b'a', b'b', b'c', 0x00, 0x02, 0x40, 0x00, 0x00];
+        assert_eq!(super::adc_decompress(&data, 10).unwrap(), b"abcabccccc");
+        assert!(super::adc_decompress(&[0x00, 0x05], 3).is_err());
+    }
+
+    #[test]
+    fn test_not_udif() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.dmg");
+        std::fs::write(&path, vec![0; 1024]).unwrap();
+        assert!(super::UdifImage::open(&path).is_err());
+    }
+}
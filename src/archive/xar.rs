@@ -0,0 +1,297 @@
+//! Reader for XAR archives, the container format of flat installer packages.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use flate2::read::ZlibDecoder;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::util::xml::{self, Element};
+use crate::util::OperationError;
+
+const MAGIC: &[u8] = b"xar!";
+const HEADER_LEN: usize = 28;
+
+pub(super) struct XarArchive {
+    path: PathBuf,
+    file: File,
+    /// Offset of the heap holding file data, following the header and table of contents.
+    heap_start: u64,
+    toc: Element,
+    pub(super) files: Vec<XarFile>,
+}
+
+#[derive(Debug)]
+pub(super) struct XarFile {
+    /// Path within the archive, e.g. `Foo.pkg/Payload`.
+    pub(super) path: String,
+    data: Option<XarData>,
+}
+
+#[derive(Debug)]
+struct XarData {
+    offset: u64,
+    length: u64,
+    encoding: Option<String>,
+}
+
+impl XarArchive {
+    pub(super) fn open(path: &Path) -> Result<Self, OperationError> {
+        let error = |detail: &dyn std::fmt::Display| {
+            OperationError::detail(
+                &format!("Could not read installer package {}", path.display()),
+                &detail.to_string(),
+            )
+        };
+
+        let mut file = File::open(path).map_err(|err| error(&err))?;
+
+        let mut header = [0; HEADER_LEN];
+        file.read_exact(&mut header)
+            .map_err(|_| error(&"Not a XAR archive"))?;
+        if !header.starts_with(MAGIC) {
+            return Err(error(&"Not a XAR archive"));
+        }
+
+        let header_len = u16::from_be_bytes([header[4], header[5]]) as u64;
+        let mut toc_len = [0; 8];
+        toc_len.copy_from_slice(&header[8..16]);
+        let toc_len = u64::from_be_bytes(toc_len);
+
+        let mut toc = vec![];
+        file.seek(SeekFrom::Start(header_len))
+            .and_then(|_| ZlibDecoder::new((&mut file).take(toc_len)).read_to_end(&mut toc))
+            .map_err(|err| error(&err))?;
+        let toc = xml::parse(&toc)?;
+
+        let mut files = vec![];
+        if let Some(contents) = toc.child("toc") {
+            collect_files(contents, "", &mut files);
+        }
+
+        Ok(XarArchive {
+            path: path.into(),
+            file,
+            heap_start: header_len + toc_len,
+            toc,
+            files,
+        })
+    }
+
+    pub(super) fn file(&self, path: &str) -> Option<&XarFile> {
+        self.files.iter().find(|file| file.path == path)
+    }
+
+    /// Reads and decodes the contents of `file`.
+    pub(super) fn read(&self, file: &XarFile) -> Result<Vec<u8>, OperationError> {
+        match &file.data {
+            Some(data) => self.read_data(&file.path, data),
+            None => Ok(vec![]),
+        }
+    }
+
+    fn read_data(&self, name: &str, data: &XarData) -> Result<Vec<u8>, OperationError> {
+        let error = |detail: &dyn std::fmt::Display| {
+            OperationError::detail(
+                &format!("Could not read {} from {}", name, self.path.display()),
+                &detail.to_string(),
+            )
+        };
+
+        let mut reader = &self.file;
+        reader
+            .seek(SeekFrom::Start(self.heap_start + data.offset))
+            .map_err(|err| error(&err))?;
+        let mut reader = reader.take(data.length);
+
+        let mut contents = vec![];
+        match data.encoding.as_deref() {
+            None | Some("application/octet-stream") => reader.read_to_end(&mut contents),
+            Some("application/x-gzip") => ZlibDecoder::new(reader).read_to_end(&mut contents),
+            Some(encoding) => return Err(error(&format!("Unsupported encoding {}", encoding))),
+        }
+        .map_err(|err| error(&err))?;
+
+        Ok(contents)
+    }
+
+    /// Hash algorithm, e.g. `sha1`, and value of the checksum of the table of contents. `None`
+    /// if the archive has no checksum.
+    pub(super) fn toc_checksum(&self) -> Result<Option<(String, Vec<u8>)>, OperationError> {
+        let checksum = match self.toc.child("toc").and_then(|toc| toc.child("checksum")) {
+            Some(checksum) => checksum,
+            None => return Ok(None),
+        };
+
+        let data = checksum
+            .child_text("offset")
+            .zip(checksum.child_text("size"));
+        let data = match data
+            .and_then(|(offset, size)| Some((offset.parse().ok()?, size.parse().ok()?)))
+        {
+            Some((offset, length)) => XarData {
+                offset,
+                length,
+                encoding: None,
+            },
+            None => return Ok(None),
+        };
+
+        let style = checksum.attribute("style").unwrap_or("sha1").to_lowercase();
+        Ok(Some((style, self.read_data("checksum", &data)?)))
+    }
+
+    /// DER-encoded certificates of the archive signature, starting with the signing
+    /// certificate. Empty if the archive is not signed.
+    pub(super) fn signing_certificates(&self) -> Result<Vec<Vec<u8>>, OperationError> {
+        let signature = match self.toc.child("toc").and_then(|toc| {
+            // Packages signed by productsign carry both; either names the same certificates.
+            toc.child("signature").or_else(|| toc.child("x-signature"))
+        }) {
+            Some(signature) => signature,
+            None => return Ok(vec![]),
+        };
+
+        let mut certificates = vec![];
+        signature.descendants_named("X509Certificate", &mut certificates);
+
+        certificates
+            .into_iter()
+            .map(|certificate| {
+                let encoded: String = certificate
+                    .text
+                    .chars()
+                    .filter(|c| !c.is_whitespace())
+                    .collect();
+                STANDARD.decode(encoded).map_err(|err| {
+                    OperationError::detail(
+                        "Malformed installer package signature",
+                        &err.to_string(),
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+fn collect_files(parent: &Element, prefix: &str, files: &mut Vec<XarFile>) {
+    for file in parent.children_named("file") {
+        let name = match file.child_text("name") {
+            Some(name) => name,
+            None => continue,
+        };
+        let path = format!("{}{}", prefix, name);
+
+        let data = file.child("data").and_then(|data| {
+            Some(XarData {
+                offset: data.child_text("offset")?.parse().ok()?,
+                length: data.child_text("length")?.parse().ok()?,
+                encoding: data
+                    .child("encoding")
+                    .and_then(|encoding| encoding.attribute("style"))
+                    .map(String::from),
+            })
+        });
+
+        collect_files(file, &format!("{}/", path), files);
+        files.push(XarFile { path, data });
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// Builds an unsigned archive from (path, contents) entries, compressing the contents of
+    /// every other entry to cover both encodings.
+    pub(in crate::archive) fn build(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut heap = vec![];
+        let mut toc = String::from(r#"<?xml version="1.0" encoding="UTF-8"?><xar><toc>"#);
+
+        for (i, (path, contents)) in entries.iter().enumerate() {
+            let (encoding, data) = if i % 2 == 0 {
+                ("application/x-gzip", zlib(contents))
+            } else {
+                ("application/octet-stream", contents.to_vec())
+            };
+
+            // Nest directories for entries with multiple components.
+            let components: Vec<&str> = path.split('/').collect();
+            for dir in &components[..components.len() - 1] {
+                toc.push_str(&format!(
+                    "<file id=\"d{}\"><type>directory</type><name>{}</name>",
+                    i, dir
+                ));
+            }
+            toc.push_str(&format!(
+                "<file id=\"{}\"><data><length>{}</length><offset>{}</offset><size>{}</size><encoding style=\"{}\"/></data><type>file</type><name>{}</name></file>",
+                i,
+                data.len(),
+                heap.len(),
+                contents.len(),
+                encoding,
+                components[components.len() - 1]
+            ));
+            for _ in 1..components.len() {
+                toc.push_str("</file>");
+            }
+
+            heap.extend(data);
+        }
+        toc.push_str("</toc></xar>");
+
+        let toc = zlib(toc.as_bytes());
+        let mut out = b"xar!".to_vec();
+        out.extend(&28u16.to_be_bytes());
+        out.extend(&1u16.to_be_bytes());
+        out.extend(&(toc.len() as u64).to_be_bytes());
+        out.extend(&0u64.to_be_bytes());
+        out.extend(&0u32.to_be_bytes());
+        out.extend(toc);
+        out.extend(heap);
+        out
+    }
+
+    #[test]
+    fn test_read() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("Test.pkg");
+        std::fs::write(
+            &path,
+            build(&[
+                ("Distribution", b"<installer-gui-script/>"),
+                ("Foo.pkg/PackageInfo", b"<pkg-info/>"),
+                ("Foo.pkg/Payload", b"payload"),
+            ]),
+        )
+        .unwrap();
+
+        let archive = super::XarArchive::open(&path).unwrap();
+        let read = |name: &str| archive.read(archive.file(name).unwrap()).unwrap();
+
+        assert_eq!(read("Distribution"), b"<installer-gui-script/>");
+        assert_eq!(read("Foo.pkg/PackageInfo"), b"<pkg-info/>");
+        assert_eq!(read("Foo.pkg/Payload"), b"payload");
+        assert!(archive.file("Foo.pkg").is_some());
+        assert!(archive.signing_certificates().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_not_xar() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("Test.pkg");
+        std::fs::write(&path, b"not a xar archive, but long enough").unwrap();
+
+        assert!(super::XarArchive::open(&path).is_err());
+        assert!(super::XarArchive::open(&temp_dir.path().join("missing.pkg")).is_err());
+    }
+}
@@ -0,0 +1,550 @@
+use chrono::{Datelike, Local, Timelike};
+use std::convert::TryFrom;
+use std::fs::OpenOptions;
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read};
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path::{Path, PathBuf};
+use tempfile::{Builder as TempFileBuilder, TempDir};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, DateTime, ZipArchive, ZipWriter};
+
+use super::{apple_double, create_dir_within, unsafe_path};
+use crate::util::OperationError;
+
+/// Prefix of the entries holding extended attributes, as written by `ditto`.
+pub(crate) const APPLE_DOUBLE_DIR: &str = "__MACOSX";
+/// File name prefix of AppleDouble entries within [`APPLE_DOUBLE_DIR`].
+pub(crate) const APPLE_DOUBLE_PREFIX: &str = "._";
+
+/// Archives `bundle_path` into `zip_path` the way `ditto -ck --keepParent` does: entries are
+/// prefixed with the bundle name, symlinks are stored as links, Unix permissions are kept,
+/// and extended attributes are stored in AppleDouble form under `__MACOSX`.
+pub(crate) fn archive_bundle(bundle_path: &Path, zip_path: &Path) -> Result<(), OperationError> {
+    archive(bundle_path, zip_path, |writer| {
+        let name = bundle_path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| invalid_name(bundle_path))?;
+        writer.add(bundle_path, name)
+    })
+}
+
+/// Archives the contents of `dir` into `zip_path`, without the directory itself, e.g. to
+/// recreate an archive after modifying its extracted contents.
+pub(crate) fn archive_contents(dir: &Path, zip_path: &Path) -> Result<(), OperationError> {
+    archive(dir, zip_path, |writer| writer.add_children(dir, None))
+}
+
+fn archive<F>(source: &Path, zip_path: &Path, add: F) -> Result<(), OperationError>
+where
+    F: FnOnce(&mut ArchiveWriter) -> io::Result<()>,
+{
+    let error = |err: &dyn std::fmt::Display| {
+        OperationError::detail(
+            &format!("Could not archive {}", source.display()),
+            &err.to_string(),
+        )
+    };
+
+    let file = File::create(zip_path).map_err(|err| error(&err))?;
+    let mut writer = ArchiveWriter {
+        zip: ZipWriter::new(file),
+        apple_double: vec![],
+    };
+
+    add(&mut writer).map_err(|err| error(&err))?;
+    writer.finish().map_err(|err| error(&err))
+}
+
+struct ArchiveWriter {
+    zip: ZipWriter<File>,
+    /// AppleDouble entries, written after the bundle contents like `ditto` does.
+    apple_double: Vec<(String, Vec<u8>)>,
+}
+
+impl ArchiveWriter {
+    fn add(&mut self, path: &Path, name: &str) -> io::Result<()> {
+        let metadata = fs::symlink_metadata(path)?;
+        let options = FileOptions::default()
+            .last_modified_time(modified_time(&metadata))
+            .unix_permissions(metadata.permissions().mode() & 0o7777);
+
+        if metadata.file_type().is_symlink() {
+            let target = fs::read_link(path)?;
+            let target = target.to_str().ok_or_else(|| invalid_name(&target))?;
+            self.zip.add_symlink(name, target, options)?;
+            return Ok(());
+        }
+
+        self.add_extended_attributes(path, name)?;
+
+        if metadata.is_dir() {
+            self.zip.add_directory(name, options)?;
+
+            self.add_children(path, Some(name))?;
+        } else {
+            self.zip.start_file(
+                name,
+                options.compression_method(CompressionMethod::Deflated),
+            )?;
+            io::copy(&mut File::open(path)?, &mut self.zip)?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds the entries of `dir`, named relative to `prefix`.
+    fn add_children(&mut self, dir: &Path, prefix: Option<&str>) -> io::Result<()> {
+        let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let file_name = entry.file_name();
+            let file_name = file_name
+                .to_str()
+                .ok_or_else(|| invalid_name(&entry.path()))?;
+            let name = match prefix {
+                Some(prefix) => format!("{}/{}", prefix, file_name),
+                None => file_name.to_string(),
+            };
+            self.add(&entry.path(), &name)?;
+        }
+
+        Ok(())
+    }
+
+    fn add_extended_attributes(&mut self, path: &Path, name: &str) -> io::Result<()> {
+        let mut attributes = vec![];
+        for attribute in xattr::list(path)? {
+            let attribute = match attribute.to_str() {
+                // Linux-specific namespaces have no meaning on macOS.
+                Some(attribute)
+                    if !attribute.starts_with("security.") && !attribute.starts_with("system.") =>
+                {
+                    attribute.to_string()
+                }
+                _ => continue,
+            };
+
+            if let Some(value) = xattr::get(path, &attribute)? {
+                attributes.push((attribute, value));
+            }
+        }
+
+        if !attributes.is_empty() {
+            let entry_name = match name.rsplit_once('/') {
+                Some((parent, file_name)) => format!(
+                    "{}/{}/{}{}",
+                    APPLE_DOUBLE_DIR, parent, APPLE_DOUBLE_PREFIX, file_name
+                ),
+                None => format!("{}/{}{}", APPLE_DOUBLE_DIR, APPLE_DOUBLE_PREFIX, name),
+            };
+            self.apple_double
+                .push((entry_name, apple_double::encode(&attributes)));
+        }
+
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        let options = FileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .unix_permissions(0o644);
+
+        for (name, data) in std::mem::take(&mut self.apple_double) {
+            self.zip.start_file(name, options)?;
+            io::Write::write_all(&mut self.zip, &data)?;
+        }
+
+        self.zip.finish()?;
+        Ok(())
+    }
+}
+
+/// Extracts `zip_path` into `dest`, restoring symlinks, Unix permissions and extended
+/// attributes stored in AppleDouble form. Extended attributes the destination filesystem
+/// does not support are dropped.
+pub(crate) fn extract(zip_path: &Path, dest: &Path) -> Result<(), OperationError> {
+    let error = |err: &dyn std::fmt::Display| {
+        OperationError::detail(
+            &format!("Could not extract {}", zip_path.display()),
+            &err.to_string(),
+        )
+    };
+
+    let file = File::open(zip_path).map_err(|err| error(&err))?;
+    let mut archive = ZipArchive::new(file).map_err(|err| error(&err))?;
+
+    extract_entries(&mut archive, dest).map_err(|err| error(&err))
+}
+
+/// Zip archive extracted to a temporary directory, removed on drop.
+pub(crate) struct ExtractedArchive {
+    dir: TempDir,
+    /// Application bundles in the archive, sorted by path.
+    pub(crate) bundles: Vec<PathBuf>,
+}
+
+impl ExtractedArchive {
+    pub(crate) fn path(&self) -> &Path {
+        self.dir.path()
+    }
+}
+
+/// Extracts `zip_path` to a temporary directory, created in `temp_parent` if specified (e.g.
+/// so that extracted bundles can be moved next to the archive), and locates the application
+/// bundles inside. Fails if the archive does not contain any.
+pub(crate) fn extract_bundles(
+    zip_path: &Path,
+    temp_parent: Option<&Path>,
+) -> Result<ExtractedArchive, OperationError> {
+    let dir = match temp_parent {
+        Some(parent) => TempFileBuilder::new().tempdir_in(parent),
+        None => TempFileBuilder::new().tempdir(),
+    }
+    .map_err(|err| {
+        OperationError::detail("Could not create temporary directory", &err.to_string())
+    })?;
+
+    extract(zip_path, dir.path())?;
+
+    let mut bundles = vec![];
+    find_bundles(dir.path(), &mut bundles).map_err(|err| {
+        OperationError::detail(
+            &format!("Could not read contents of {}", zip_path.display()),
+            &err.to_string(),
+        )
+    })?;
+    bundles.sort();
+
+    if bundles.is_empty() {
+        return Err(OperationError::new(&format!(
+            "Expected an application bundle in {}",
+            zip_path.display()
+        )));
+    }
+
+    Ok(ExtractedArchive { dir, bundles })
+}
+
+fn find_bundles(dir: &Path, found: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+
+        let path = entry.path();
+        if path.extension().is_some_and(|extension| extension == "app") {
+            found.push(path);
+        } else {
+            find_bundles(&path, found)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn extract_entries(archive: &mut ZipArchive<File>, dest: &Path) -> io::Result<()> {
+    fs::create_dir_all(dest)?;
+    let root = dest.canonicalize()?;
+
+    let mut directories = vec![];
+    let mut apple_double = vec![];
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let relative = entry
+            .enclosed_name()
+            .map(PathBuf::from)
+            .ok_or_else(|| unsafe_path(entry.name()))?;
+        let mode = entry.unix_mode();
+
+        if let Ok(path) = relative.strip_prefix(APPLE_DOUBLE_DIR) {
+            if let Some(target) = apple_double_target(path) {
+                let mut data = vec![];
+                entry.read_to_end(&mut data)?;
+                apple_double.push((root.join(target), data));
+            }
+            continue;
+        }
+
+        let path = root.join(&relative);
+
+        if entry.is_dir() {
+            create_dir_within(&root, &path)?;
+            directories.push((path, mode));
+            continue;
+        }
+
+        let parent = path.parent().unwrap_or(&root);
+        create_dir_within(&root, parent)?;
+
+        if mode.is_some_and(|mode| mode & 0o170000 == 0o120000) {
+            let mut target = String::new();
+            entry.read_to_string(&mut target)?;
+            symlink(target, &path)?;
+            continue;
+        }
+
+        // Refuse to write through an existing entry, which may be a symlink.
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        io::copy(&mut entry, &mut file)?;
+        if let Some(mode) = mode {
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode & 0o7777))?;
+        }
+    }
+
+    for (path, data) in apple_double {
+        if fs::symlink_metadata(&path).is_err() {
+            continue;
+        }
+        let attributes =
+            apple_double::decode(&data).map_err(|err| io::Error::other(err.to_string()))?;
+        for (name, value) in attributes {
+            let _ = xattr::set(&path, name, &value);
+        }
+    }
+
+    // Applied last so that read-only directories can still be populated.
+    for (path, mode) in directories.into_iter().rev() {
+        if let Some(mode) = mode {
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode & 0o7777))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Path of the file described by an AppleDouble entry, given the entry path within
+/// [`APPLE_DOUBLE_DIR`].
+fn apple_double_target(path: &Path) -> Option<PathBuf> {
+    let file_name = path
+        .file_name()?
+        .to_str()?
+        .strip_prefix(APPLE_DOUBLE_PREFIX)?;
+    Some(path.with_file_name(file_name))
+}
+
+/// Modification time in local time, as stored by `ditto`. Zip timestamps cannot represent
+/// dates before 1980, which fall back to the format's default.
+fn modified_time(metadata: &Metadata) -> DateTime {
+    metadata
+        .modified()
+        .ok()
+        .map(chrono::DateTime::<Local>::from)
+        .and_then(|time| {
+            DateTime::from_date_and_time(
+                u16::try_from(time.year()).ok()?,
+                time.month() as u8,
+                time.day() as u8,
+                time.hour() as u8,
+                time.minute() as u8,
+                time.second() as u8,
+            )
+            .ok()
+        })
+        .unwrap_or_default()
+}
+
+fn invalid_name(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{} is not valid UTF-8", path.display()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_file(path: &Path, contents: &[u8], mode: u32) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    #[test]
+    fn test_archive_bundle() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let bundle = temp_dir.path().join("Foo.app");
+        let framework = bundle.join("Contents/Frameworks/Bar.framework");
+
+        write_file(&bundle.join("Contents/MacOS/Foo"), b"executable", 0o755);
+        write_file(&bundle.join("Contents/Info.plist"), b"<plist/>", 0o644);
+        write_file(&framework.join("Versions/A/Bar"), b"library", 0o755);
+        symlink("A", framework.join("Versions/Current")).unwrap();
+        symlink("Versions/Current/Bar", framework.join("Bar")).unwrap();
+
+        // Not every filesystem supports user extended attributes.
+        let has_xattr =
+            xattr::set(bundle.join("Contents/Info.plist"), "user.test", b"value").is_ok();
+
+        let zip_path = temp_dir.path().join("Foo.zip");
+        archive_bundle(&bundle, &zip_path).unwrap();
+
+        let mut archive = ZipArchive::new(File::open(&zip_path).unwrap()).unwrap();
+        let mut entry = |name: &str| {
+            let mut file = archive.by_name(name).unwrap();
+            let mut contents = vec![];
+            file.read_to_end(&mut contents).unwrap();
+            (file.unix_mode().unwrap(), contents)
+        };
+
+        assert_eq!(entry("Foo.app/").0 & 0o170000, 0o040000);
+        assert_eq!(
+            entry("Foo.app/Contents/MacOS/Foo"),
+            (0o100755, b"executable".to_vec())
+        );
+        assert_eq!(
+            entry("Foo.app/Contents/Info.plist"),
+            (0o100644, b"<plist/>".to_vec())
+        );
+
+        let (mode, target) = entry("Foo.app/Contents/Frameworks/Bar.framework/Versions/Current");
+        assert_eq!(mode & 0o170000, 0o120000);
+        assert_eq!(target, b"A");
+        let (mode, target) = entry("Foo.app/Contents/Frameworks/Bar.framework/Bar");
+        assert_eq!(mode & 0o170000, 0o120000);
+        assert_eq!(target, b"Versions/Current/Bar");
+
+        if has_xattr {
+            let (_, data) = entry("__MACOSX/Foo.app/Contents/._Info.plist");
+            assert_eq!(
+                apple_double::decode(&data).unwrap(),
+                vec![("user.test".to_string(), b"value".to_vec())]
+            );
+        }
+    }
+
+    #[test]
+    fn test_archive_extracts_with_unzip() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let bundle = temp_dir.path().join("Foo.app");
+        let versions = bundle.join("Contents/Frameworks/Bar.framework/Versions");
+
+        write_file(&bundle.join("Contents/MacOS/Foo"), b"executable", 0o755);
+        write_file(&versions.join("A/Bar"), b"library", 0o755);
+        symlink("A", versions.join("Current")).unwrap();
+
+        let zip_path = temp_dir.path().join("Foo.zip");
+        archive_bundle(&bundle, &zip_path).unwrap();
+
+        let unzip = if cfg!(target_os = "macos") {
+            std::process::Command::new("/usr/bin/ditto")
+                .args(["-xk"])
+                .arg(&zip_path)
+                .arg(temp_dir.path().join("out"))
+                .status()
+        } else {
+            std::process::Command::new("unzip")
+                .arg("-q")
+                .arg(&zip_path)
+                .arg("-d")
+                .arg(temp_dir.path().join("out"))
+                .status()
+        };
+        assert!(unzip.unwrap().success());
+
+        let extracted = temp_dir.path().join("out/Foo.app");
+        let executable = fs::metadata(extracted.join("Contents/MacOS/Foo")).unwrap();
+        assert_eq!(executable.permissions().mode() & 0o777, 0o755);
+
+        let current = extracted.join("Contents/Frameworks/Bar.framework/Versions/Current");
+        assert_eq!(fs::read_link(&current).unwrap(), Path::new("A"));
+        assert_eq!(fs::read(current.join("Bar")).unwrap(), b"library");
+    }
+
+    #[test]
+    fn test_extract() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let bundle = temp_dir.path().join("Foo.app");
+        let versions = bundle.join("Contents/Frameworks/Bar.framework/Versions");
+
+        write_file(&bundle.join("Contents/MacOS/Foo"), b"executable", 0o755);
+        write_file(&bundle.join("Contents/Info.plist"), b"<plist/>", 0o600);
+        write_file(&versions.join("A/Bar"), b"library", 0o755);
+        symlink("A", versions.join("Current")).unwrap();
+        let has_xattr =
+            xattr::set(bundle.join("Contents/Info.plist"), "user.test", b"value").is_ok();
+
+        let zip_path = temp_dir.path().join("Foo.zip");
+        archive_bundle(&bundle, &zip_path).unwrap();
+
+        let extracted = extract_bundles(&zip_path, None).unwrap();
+        assert_eq!(extracted.bundles, vec![extracted.path().join("Foo.app")]);
+
+        let bundle = &extracted.bundles[0];
+        assert!(!extracted.path().join(APPLE_DOUBLE_DIR).exists());
+
+        let info_plist = bundle.join("Contents/Info.plist");
+        assert_eq!(fs::read(&info_plist).unwrap(), b"<plist/>");
+        assert_eq!(
+            fs::metadata(&info_plist).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        assert_eq!(
+            fs::metadata(bundle.join("Contents/MacOS/Foo"))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777,
+            0o755
+        );
+
+        let current = bundle.join("Contents/Frameworks/Bar.framework/Versions/Current");
+        assert_eq!(fs::read_link(&current).unwrap(), Path::new("A"));
+
+        if has_xattr {
+            assert_eq!(
+                xattr::get(&info_plist, "user.test").unwrap(),
+                Some(b"value".to_vec())
+            );
+        }
+
+        // Re-archiving the extracted contents reproduces the original entries.
+        let rezipped = temp_dir.path().join("Rezipped.zip");
+        archive_contents(extracted.path(), &rezipped).unwrap();
+
+        let names = |path: &Path| {
+            let archive = ZipArchive::new(File::open(path).unwrap()).unwrap();
+            let mut names: Vec<String> = archive.file_names().map(String::from).collect();
+            names.sort();
+            names
+        };
+        assert_eq!(names(&zip_path), names(&rezipped));
+    }
+
+    #[test]
+    fn test_extract_symlink_escape() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let zip_path = temp_dir.path().join("Evil.zip");
+
+        let mut zip = ZipWriter::new(File::create(&zip_path).unwrap());
+        zip.add_symlink("Foo.app/link", "../..", FileOptions::default())
+            .unwrap();
+        zip.start_file("Foo.app/link/escaped", FileOptions::default())
+            .unwrap();
+        zip.finish().unwrap();
+
+        let dest = temp_dir.path().join("out");
+        assert!(extract(&zip_path, &dest).is_err());
+        assert!(!temp_dir.path().join("escaped").exists());
+    }
+
+    #[test]
+    fn test_extract_without_bundle() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let zip_path = temp_dir.path().join("Empty.zip");
+
+        let mut zip = ZipWriter::new(File::create(&zip_path).unwrap());
+        zip.start_file("README", FileOptions::default()).unwrap();
+        zip.finish().unwrap();
+
+        assert!(extract_bundles(&zip_path, None).is_err());
+    }
+}
@@ -0,0 +1,347 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use structopt::StructOpt;
+
+use xcnotary::notarize::{Backend, PollSettings, ServiceOptions};
+use xcnotary::output::OutputFormat;
+use xcnotary::precheck::CHECK_IDS;
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "macOS App Notarization Helper")]
+pub(crate) enum Args {
+    /// Only performs code signing checks on the input bundle or package
+    Precheck {
+        /// Path to bundle, zip archive, disk image or package
+        #[structopt(parse(from_os_str))]
+        input_path: PathBuf,
+
+        /// Stop at the first failing check instead of reporting all failures
+        #[structopt(long)]
+        fail_fast: bool,
+
+        /// Skip a check, reporting it as skipped. May be repeated
+        #[structopt(long, number_of_values = 1, possible_values = CHECK_IDS)]
+        skip_check: Vec<String>,
+
+        /// Profile in xcnotary.toml to read options from. Defaults to the "default" profile, if any
+        #[structopt(long)]
+        profile: Option<String>,
+
+        /// Output format. "json" prints a single JSON document describing the results on completion
+        #[structopt(long, default_value = "text", possible_values = &["text", "json"])]
+        output: OutputFormat,
+    },
+
+    /// Performs code signing checks on the input bundles or packages,
+    /// submits them to the notarization service, and blocks waiting for completion
+    Notarize {
+        #[structopt(flatten)]
+        service: ServiceArgs,
+
+        /// Paths to bundles, zip archives, disk images or packages. Quoted paths may contain *, ? and [...] wildcards
+        #[structopt(parse(from_os_str), required = true)]
+        input_paths: Vec<PathBuf>,
+
+        /// Maximum number of inputs uploaded at once. Every submission is then waited for together
+        #[structopt(long, default_value = "4")]
+        jobs: usize,
+
+        /// Skip client-side pre-checks, deferring to notarization service for troubleshooting
+        #[structopt(long)]
+        no_precheck: bool,
+
+        /// Skip a check, reporting it as skipped. May be repeated
+        #[structopt(long, number_of_values = 1, possible_values = CHECK_IDS)]
+        skip_check: Vec<String>,
+
+        /// For .zip input, replace the archive with one containing the stapled bundles
+        /// instead of extracting them next to it
+        #[structopt(long)]
+        rezip: bool,
+
+        #[structopt(flatten)]
+        poll: PollArgs,
+
+        #[structopt(flatten)]
+        hooks: HookArgs,
+
+        #[structopt(flatten)]
+        webhooks: WebhookArgs,
+
+        /// Output format. "json" prints a single JSON document describing the results on completion
+        #[structopt(long, default_value = "text", possible_values = &["text", "json"])]
+        output: OutputFormat,
+    },
+
+    /// Performs code signing checks on the input bundle or package and submits it to the
+    /// notarization service without waiting for completion, printing the request UUID
+    Submit {
+        #[structopt(flatten)]
+        service: ServiceArgs,
+
+        /// Path to bundle, zip archive, disk image or package
+        #[structopt(parse(from_os_str))]
+        input_path: PathBuf,
+
+        /// Skip client-side pre-checks, deferring to notarization service for troubleshooting
+        #[structopt(long)]
+        no_precheck: bool,
+
+        /// Skip a check, reporting it as skipped. May be repeated
+        #[structopt(long, number_of_values = 1, possible_values = CHECK_IDS)]
+        skip_check: Vec<String>,
+
+        /// File to save the request UUID to, e.g. for a later `wait` in another CI step
+        #[structopt(long, parse(from_os_str))]
+        request_id_file: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        webhooks: WebhookArgs,
+
+        /// Output format. "json" prints a single JSON document describing the results on completion
+        #[structopt(long, default_value = "text", possible_values = &["text", "json"])]
+        output: OutputFormat,
+    },
+
+    /// Displays the current status of a submission
+    Status {
+        #[structopt(flatten)]
+        service: ServiceArgs,
+
+        /// Request UUID returned by `submit`
+        request_id: String,
+
+        /// Output format. "json" prints a single JSON document describing the results on completion
+        #[structopt(long, default_value = "text", possible_values = &["text", "json"])]
+        output: OutputFormat,
+    },
+
+    /// Blocks waiting for completion of a submission, then staples the bundle or package
+    Wait {
+        #[structopt(flatten)]
+        service: ServiceArgs,
+
+        /// Request UUID returned by `submit`
+        #[structopt(required_unless = "resume")]
+        request_id: Option<String>,
+
+        /// Path to the submitted bundle, zip archive, disk image or package
+        #[structopt(parse(from_os_str), required_unless = "resume")]
+        input_path: Option<PathBuf>,
+
+        /// Instead of a single request, wait for every submission in the journal that was not
+        /// known to complete, e.g. because xcnotary was interrupted
+        #[structopt(long, conflicts_with_all = &["request-id", "input-path"])]
+        resume: bool,
+
+        /// For .zip input, replace the archive with one containing the stapled bundles
+        /// instead of extracting them next to it
+        #[structopt(long)]
+        rezip: bool,
+
+        #[structopt(flatten)]
+        poll: PollArgs,
+
+        #[structopt(flatten)]
+        hooks: HookArgs,
+
+        #[structopt(flatten)]
+        webhooks: WebhookArgs,
+
+        /// Output format. "json" prints a single JSON document describing the results on completion
+        #[structopt(long, default_value = "text", possible_values = &["text", "json"])]
+        output: OutputFormat,
+    },
+
+    /// Verifies offline that a notarization ticket is stapled to the bundle, disk image or
+    /// package, and that it covers the code as it is now
+    Validate {
+        /// Path to stapled bundle, disk image or package
+        #[structopt(parse(from_os_str))]
+        input_path: PathBuf,
+
+        /// Output format. "json" prints a single JSON document describing the results on completion
+        #[structopt(long, default_value = "text", possible_values = &["text", "json"])]
+        output: OutputFormat,
+    },
+
+    /// Displays the notarization service log of a completed submission
+    Log {
+        #[structopt(flatten)]
+        service: ServiceArgs,
+
+        /// Request UUID returned by `submit`
+        request_id: String,
+
+        /// Output the log as returned by the service, rather than a summary
+        #[structopt(long)]
+        raw: bool,
+    },
+
+    /// Lists recent submissions made with the developer account
+    History {
+        #[structopt(flatten)]
+        service: ServiceArgs,
+
+        /// List submissions made with the developer account from the notarization service,
+        /// rather than those recorded in the journal
+        #[structopt(long)]
+        remote: bool,
+    },
+}
+
+// Options for connecting to the notarization service, shared by subcommands.
+// Note: a doc comment here would replace the about text of each subcommand.
+#[derive(Debug, Clone, Default, StructOpt)]
+pub(crate) struct ServiceArgs {
+    /// Profile in xcnotary.toml to read options from. Defaults to the "default" profile, if any
+    #[structopt(long)]
+    pub(crate) profile: Option<String>,
+
+    /// Apple developer account username, used with altool
+    #[structopt(short, long)]
+    pub(crate) developer_account: Option<String>,
+
+    /// Name of keychain item containing developer account password, used with altool
+    /// (see: https://developer.apple.com/documentation/xcode/notarizing_macos_software_before_distribution/customizing_the_notarization_workflow)
+    #[structopt(short = "k", long = "developer-password-keychain-item")]
+    pub(crate) password_keychain_item: Option<String>,
+
+    /// Name of the keychain profile created with `xcrun notarytool store-credentials`, recording the developer account, team and password used with notarytool
+    #[structopt(long)]
+    pub(crate) keychain_profile: Option<String>,
+
+    /// Required if the developer credentials are associated with more than one team. Corresponds to "ProviderShortname" from running `xcrun altool --list-providers`. Not used by notarytool, which reads the team from the keychain profile
+    #[structopt(long)]
+    pub(crate) provider: Option<String>,
+
+    /// Tool used to communicate with the notarization service, "notarytool" by default. "api" connects to the Notary REST API directly, without Xcode
+    #[structopt(long, possible_values = &["notarytool", "altool", "api"])]
+    pub(crate) backend: Option<Backend>,
+
+    /// App Store Connect API token (JWT) used to authenticate with the api backend
+    #[structopt(long, env = "XCNOTARY_API_TOKEN", hide_env_values = true)]
+    pub(crate) api_token: Option<String>,
+
+    /// Path to App Store Connect API private key (AuthKey_<key id>.p8), used in place of the developer account
+    #[structopt(long, parse(from_os_str))]
+    pub(crate) api_key: Option<PathBuf>,
+
+    /// App Store Connect API key ID, required with --api-key
+    #[structopt(long)]
+    pub(crate) api_key_id: Option<String>,
+
+    /// App Store Connect API issuer ID, required with --api-key
+    #[structopt(long)]
+    pub(crate) api_issuer: Option<String>,
+
+    /// Directory of the journal recording each submission, "~/.xcnotary" by default
+    #[structopt(long, parse(from_os_str), env = "XCNOTARY_STATE_DIR")]
+    pub(crate) state_dir: Option<PathBuf>,
+}
+
+// Options controlling how long to wait for a submission to complete.
+#[derive(Debug, StructOpt)]
+pub(crate) struct PollArgs {
+    /// Seconds to wait before the first status check, 5 by default. The interval doubles after each check, up to --max-poll-interval
+    #[structopt(long)]
+    pub(crate) poll_interval: Option<u64>,
+
+    /// Maximum number of seconds between status checks, 60 by default
+    #[structopt(long)]
+    pub(crate) max_poll_interval: Option<u64>,
+
+    /// Stop waiting after this many seconds, printing the request UUID so that `wait` can resume later. Exits with status 2
+    #[structopt(long)]
+    pub(crate) timeout: Option<u64>,
+}
+
+// Commands run when a submission completes.
+#[derive(Debug, Default, StructOpt)]
+pub(crate) struct HookArgs {
+    /// Shell command to run once the input is notarized and stapled. XCNOTARY_REQUEST_ID, XCNOTARY_BUNDLE_ID, XCNOTARY_STATUS, XCNOTARY_ARTIFACT_PATH and XCNOTARY_LOG_PATH describe the submission
+    #[structopt(long)]
+    pub(crate) on_success: Option<String>,
+
+    /// Shell command to run if notarization or stapling fails, with the same environment as --on-success
+    #[structopt(long)]
+    pub(crate) on_failure: Option<String>,
+}
+
+// Webhooks notified when a submission is made and when it completes.
+#[derive(Debug, StructOpt)]
+pub(crate) struct WebhookArgs {
+    /// URL to POST a JSON document to on submission, success and failure. May be repeated
+    #[structopt(long, number_of_values = 1)]
+    pub(crate) webhook: Vec<String>,
+
+    /// Slack incoming webhook URL to post a message to on submission, success and failure. May be repeated
+    #[structopt(long, number_of_values = 1)]
+    pub(crate) slack_webhook: Vec<String>,
+}
+
+impl Args {
+    /// Profile selected with `--profile`.
+    pub(crate) fn profile(&self) -> Option<&str> {
+        match self {
+            Args::Precheck { profile, .. } => profile.as_deref(),
+            Args::Notarize { service, .. }
+            | Args::Submit { service, .. }
+            | Args::Status { service, .. }
+            | Args::Wait { service, .. }
+            | Args::Log { service, .. }
+            | Args::History { service, .. } => service.profile.as_deref(),
+            Args::Validate { .. } => None,
+        }
+    }
+
+    pub(crate) fn output_format(&self) -> OutputFormat {
+        match self {
+            Args::Precheck { output, .. }
+            | Args::Notarize { output, .. }
+            | Args::Submit { output, .. }
+            | Args::Status { output, .. }
+            | Args::Wait { output, .. }
+            | Args::Validate { output, .. } => *output,
+            Args::Log { .. } | Args::History { .. } => OutputFormat::Text,
+        }
+    }
+}
+
+impl From<ServiceArgs> for ServiceOptions {
+    fn from(args: ServiceArgs) -> Self {
+        ServiceOptions {
+            backend: args.backend,
+            developer_account: args.developer_account,
+            password_keychain_item: args.password_keychain_item,
+            keychain_profile: args.keychain_profile,
+            provider: args.provider,
+            api_token: args.api_token,
+            api_key: args.api_key,
+            api_key_id: args.api_key_id,
+            api_issuer: args.api_issuer,
+        }
+    }
+}
+
+impl From<PollArgs> for PollSettings {
+    fn from(args: PollArgs) -> Self {
+        let defaults = PollSettings::default();
+        let initial_interval = args
+            .poll_interval
+            .map_or(defaults.initial_interval, Duration::from_secs);
+        let max_interval = args
+            .max_poll_interval
+            .map_or(defaults.max_interval, Duration::from_secs);
+
+        PollSettings {
+            initial_interval,
+            max_interval: max_interval.max(initial_interval),
+            timeout: args.timeout.map(Duration::from_secs),
+        }
+    }
+}
+
+pub(crate) fn parse() -> Args {
+    Args::from_args()
+}
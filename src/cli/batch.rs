@@ -0,0 +1,282 @@
+//! Notarization of several inputs in one invocation. Uploads are limited to a number of
+//! concurrent jobs, then every submission is waited for together.
+
+use console::{style, Style};
+use futures_util::future::{join_all, Either};
+use std::error::Error;
+use std::future::{self, Future};
+use std::path::{Path, PathBuf};
+use tokio::sync::Semaphore;
+
+use super::error_kind;
+use super::report::{InputResult, Report, Results};
+use xcnotary::notarize::{
+    CancellationToken, Notarization, NotarizationStatus, NotarizeOp, Progress,
+};
+use xcnotary::{output, precheck};
+use xcnotary::{ErrorKind, OperationError};
+
+/// Outcome of one input.
+struct Outcome {
+    path: PathBuf,
+    results: Results,
+    /// Absent if the input failed before notarization completed.
+    notarization: Option<Notarization>,
+    /// Why the input failed: a failed pre-check, or a failure to submit, notarize or staple it.
+    error: Option<Box<dyn Error>>,
+}
+
+impl Outcome {
+    /// Outcome of notarizing the input at `path`, which passed the pre-checks in `results`.
+    fn new(
+        path: &Path,
+        mut results: Results,
+        result: Result<Notarization, OperationError>,
+    ) -> Self {
+        results.set_outcome(&result);
+        let (notarization, error) = match result {
+            Ok(notarization) => {
+                let error = notarization.error().map(Box::from);
+                (Some(notarization), error)
+            }
+            Err(err) => (None, Some(err.into())),
+        };
+
+        Outcome {
+            path: path.into(),
+            results,
+            notarization,
+            error,
+        }
+    }
+
+    /// Outcome of the input at `path`, which was not submitted as its pre-checks failed.
+    fn prechecks_failed(path: &Path, results: Results, error: Box<dyn Error>) -> Self {
+        Outcome {
+            path: path.into(),
+            results,
+            notarization: None,
+            error: Some(error),
+        }
+    }
+
+    fn status(&self) -> Option<NotarizationStatus> {
+        self.notarization
+            .as_ref()
+            .map(|notarization| notarization.status)
+    }
+
+    /// Short description of the outcome, for the summary table.
+    fn summary(&self) -> &'static str {
+        let kind = match &self.error {
+            None => return "Stapled",
+            Some(err) => error_kind(&**err),
+        };
+
+        match kind {
+            ErrorKind::PrecheckFailed => "Pre-check failed",
+            ErrorKind::Rejected => "Rejected",
+            ErrorKind::TimedOut => "Timed out",
+            ErrorKind::StapleFailed => "Stapling failed",
+            ErrorKind::Network => "Network error",
+            ErrorKind::Credentials => "Invalid credentials",
+            ErrorKind::MalformedResponse => "Unexpected response",
+            _ => "Failed",
+        }
+    }
+}
+
+/// Callback printing the progress of the input at `path`, so that the progress of concurrent
+/// submissions can be told apart.
+pub(super) fn print_progress(path: &Path) -> impl Fn(&Progress) + Send + Sync + 'static {
+    let name = style(path.display().to_string()).bold();
+
+    move |progress| {
+        let message = match progress {
+            Progress::Compressing => "compressing".into(),
+            Progress::Uploading => "uploading".into(),
+            Progress::Submitted(request_id) => format!("submitted, request UUID {}", request_id),
+            Progress::AlreadyNotarized(request_id) => {
+                format!("already notarized, request UUID {}", request_id)
+            }
+            Progress::StatusChecked(NotarizationStatus::InProgress) | Progress::RequestingLog => {
+                return
+            }
+            Progress::StatusChecked(status) => status.to_string(),
+            Progress::Stapling => "stapling".into(),
+        };
+        println!("{}: {}", name, message);
+    }
+}
+
+/// Pre-checks each input unless `skip` is `None`, then uploads at most `jobs` of those that pass
+/// at once, waits for every submission to complete, and prints a summary. Fails if any input
+/// failed, with the kind of the first failure.
+pub(super) fn notarize(
+    ops: &[NotarizeOp],
+    jobs: usize,
+    skip: Option<&[String]>,
+    report: &mut Report,
+) -> Result<(), OperationError> {
+    let mut checked = vec![];
+    for op in ops {
+        let mut results = Results::default();
+        let failure = match skip {
+            Some(skip) => prechecks(op, skip, &mut results).err(),
+            None => None,
+        };
+        checked.push((op, results, failure));
+    }
+
+    let uploads = Semaphore::new(jobs.max(1));
+    complete(
+        checked
+            .into_iter()
+            .map(|(op, results, failure)| match failure {
+                Some(err) => Either::Left(future::ready(Outcome::prechecks_failed(
+                    op.input_path(),
+                    results,
+                    err,
+                ))),
+                None => Either::Right(notarize_one(op, results, &uploads)),
+            }),
+        report,
+    )
+}
+
+/// Runs the pre-checks of `op`, stopping at the first failure, and adds their results to
+/// `results`.
+fn prechecks(
+    op: &NotarizeOp,
+    skip: &[String],
+    results: &mut Results,
+) -> Result<(), Box<dyn Error>> {
+    if output::is_text() {
+        println!("{}", style(op.input_path().display()).bold());
+    }
+
+    let checks = precheck::run(&op.input_path().to_path_buf(), op.path_type(), true, skip)?;
+    results.add_prechecks(&checks);
+    checks.into_result()
+}
+
+/// Waits for every previously submitted request to complete, and prints a summary. Fails if any
+/// input failed, with the kind of the first failure.
+pub(super) fn wait(
+    requests: &[(NotarizeOp, String)],
+    report: &mut Report,
+) -> Result<(), OperationError> {
+    complete(
+        requests.iter().map(|(op, request_id)| async move {
+            let result = op.wait_async(request_id, &CancellationToken::new()).await;
+            Outcome::new(op.input_path(), Results::default(), result)
+        }),
+        report,
+    )
+}
+
+/// Runs the operations to completion, recording the outcome of each in `report`.
+fn complete<F: Future<Output = Outcome>>(
+    operations: impl Iterator<Item = F>,
+    report: &mut Report,
+) -> Result<(), OperationError> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .map_err(|err| OperationError::detail("Could not start runtime", &err.to_string()))?;
+
+    let outcomes = runtime.block_on(join_all(operations));
+
+    if output::is_text() {
+        print_summary(&outcomes);
+    }
+
+    let failures: Vec<&Box<dyn Error>> = outcomes
+        .iter()
+        .filter_map(|outcome| outcome.error.as_ref())
+        .collect();
+    let result = match failures.first() {
+        None => Ok(()),
+        Some(first) => Err(OperationError::new(&format!(
+            "{} of {} inputs failed notarization",
+            failures.len(),
+            outcomes.len()
+        ))
+        .with_kind(error_kind(&***first))),
+    };
+
+    report.set_inputs(
+        outcomes
+            .into_iter()
+            .map(|outcome| InputResult {
+                path: outcome.path.display().to_string(),
+                error: outcome.error.map(|err| err.to_string().trim().to_string()),
+                results: outcome.results,
+            })
+            .collect(),
+    );
+
+    result
+}
+
+async fn notarize_one(op: &NotarizeOp, results: Results, uploads: &Semaphore) -> Outcome {
+    let cancel = CancellationToken::new();
+
+    let permit = uploads.acquire().await;
+    let submitted = op.submit_async(&cancel).await;
+    drop(permit);
+
+    let result = match submitted {
+        Ok(request_id) => op.wait_async(&request_id, &cancel).await,
+        Err(err) => Err(err),
+    };
+    Outcome::new(op.input_path(), results, result)
+}
+
+/// Prints a table of the outcome of each input, followed by the details of each failure.
+fn print_summary(outcomes: &[Outcome]) {
+    let width = outcomes
+        .iter()
+        .map(|outcome| outcome.path.display().to_string().chars().count())
+        .max()
+        .unwrap_or_default()
+        .max("Input".len())
+        + 2;
+
+    let emphasized = Style::new().white().bold();
+    println!(
+        "\n{}",
+        emphasized.apply_to(format!(
+            "{:<width$}{:<38}{:<13}{}",
+            "Input",
+            "Request UUID",
+            "Status",
+            "Result",
+            width = width
+        ))
+    );
+
+    for outcome in outcomes {
+        let result = if outcome.error.is_none() {
+            style(outcome.summary()).green()
+        } else {
+            style(outcome.summary()).red()
+        };
+        println!(
+            "{:<width$}{:<38}{:<13}{}",
+            outcome.path.display().to_string(),
+            outcome.results.request_id.as_deref().unwrap_or("-"),
+            outcome
+                .status()
+                .map_or_else(|| "-".into(), |status| status.to_string()),
+            result,
+            width = width
+        );
+    }
+
+    for outcome in outcomes {
+        if let Some(err) = &outcome.error {
+            eprintln!("\n{}\n{}", style(outcome.path.display()).bold(), err);
+        }
+    }
+}
@@ -0,0 +1,439 @@
+//! Options read from an `xcnotary.toml` file in the working directory or one of its parents,
+//! applied where they are not given on the command line.
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::cli::args::{Args, HookArgs, PollArgs, ServiceArgs, WebhookArgs};
+use xcnotary::notarize::Backend;
+use xcnotary::precheck::CHECK_IDS;
+use xcnotary::OperationError;
+
+pub(crate) const CONFIG_FILE_NAME: &str = "xcnotary.toml";
+
+/// Profile used when `--profile` is not specified.
+const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    profile: BTreeMap<String, Profile>,
+}
+
+/// Named set of options. Keys are the names of the corresponding command line options.
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct Profile {
+    developer_account: Option<String>,
+    developer_password_keychain_item: Option<String>,
+    keychain_profile: Option<String>,
+    provider: Option<String>,
+    backend: Option<String>,
+    /// Relative to the directory containing the file.
+    api_key: Option<PathBuf>,
+    api_key_id: Option<String>,
+    api_issuer: Option<String>,
+    poll_interval: Option<u64>,
+    max_poll_interval: Option<u64>,
+    timeout: Option<u64>,
+    skip_check: Option<Vec<String>>,
+    on_success: Option<String>,
+    on_failure: Option<String>,
+    webhook: Option<Vec<String>>,
+    slack_webhook: Option<Vec<String>>,
+    /// Relative to the directory containing the file.
+    state_dir: Option<PathBuf>,
+}
+
+/// Fills in options missing from `args` from the selected profile of the nearest
+/// `xcnotary.toml`, if any.
+pub(crate) fn apply_profile(mut args: Args) -> Result<Args, OperationError> {
+    let name = args.profile().map(String::from);
+
+    let working_dir = std::env::current_dir().map_err(|err| {
+        OperationError::detail("Could not determine working directory", &err.to_string())
+    })?;
+    let path = match find(&working_dir) {
+        Some(path) => path,
+        None => match name {
+            Some(name) => {
+                return Err(OperationError::new(&format!(
+                    "Profile {} specified, but no {} found in {} or its parents",
+                    name,
+                    CONFIG_FILE_NAME,
+                    working_dir.display()
+                )))
+            }
+            None => return Ok(args),
+        },
+    };
+
+    if let Some(profile) = load(&path, name.as_deref())? {
+        profile.apply(&mut args)?;
+    }
+
+    Ok(args)
+}
+
+/// Nearest `xcnotary.toml` in `dir` or its parents.
+fn find(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(CONFIG_FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+/// Reads the profile `name` from the file at `path`, or the default profile if no name is given.
+fn load(path: &Path, name: Option<&str>) -> Result<Option<Profile>, OperationError> {
+    let error = |err: &dyn std::fmt::Display| {
+        OperationError::detail(
+            &format!("Could not read {}", path.display()),
+            &err.to_string(),
+        )
+    };
+
+    let text = std::fs::read_to_string(path).map_err(|err| error(&err))?;
+    let mut config: ConfigFile = toml::from_str(&text).map_err(|err| error(&err))?;
+
+    let profile = match name {
+        Some(name) => config.profile.remove(name).ok_or_else(|| {
+            let available: Vec<&str> = config.profile.keys().map(String::as_str).collect();
+            OperationError::new(&format!(
+                "No profile named {} in {}. Available profiles: {}",
+                name,
+                path.display(),
+                available.join(", ")
+            ))
+        })?,
+        None => match config.profile.remove(DEFAULT_PROFILE) {
+            Some(profile) => profile,
+            None => return Ok(None),
+        },
+    };
+
+    let mut profile = profile;
+    if let Some(dir) = path.parent() {
+        profile.api_key = profile.api_key.map(|api_key| dir.join(api_key));
+        profile.state_dir = profile.state_dir.map(|state_dir| dir.join(state_dir));
+    }
+
+    Ok(Some(profile))
+}
+
+impl Profile {
+    /// Fills in options missing from `args`. Options given on the command line take precedence.
+    fn apply(&self, args: &mut Args) -> Result<(), OperationError> {
+        match args {
+            Args::Precheck { skip_check, .. } => self.apply_skip_check(skip_check)?,
+            Args::Notarize {
+                service,
+                skip_check,
+                poll,
+                hooks,
+                webhooks,
+                ..
+            } => {
+                self.apply_service(service)?;
+                self.apply_skip_check(skip_check)?;
+                self.apply_poll(poll);
+                self.apply_hooks(hooks);
+                self.apply_webhooks(webhooks);
+            }
+            Args::Wait {
+                service,
+                poll,
+                hooks,
+                webhooks,
+                ..
+            } => {
+                self.apply_service(service)?;
+                self.apply_poll(poll);
+                self.apply_hooks(hooks);
+                self.apply_webhooks(webhooks);
+            }
+            Args::Submit {
+                service,
+                skip_check,
+                webhooks,
+                ..
+            } => {
+                self.apply_service(service)?;
+                self.apply_skip_check(skip_check)?;
+                self.apply_webhooks(webhooks);
+            }
+            Args::Status { service, .. }
+            | Args::Log { service, .. }
+            | Args::History { service, .. } => self.apply_service(service)?,
+            Args::Validate { .. } => {}
+        }
+
+        Ok(())
+    }
+
+    /// Credentials are taken as a whole from either the command line or the profile, so that
+    /// e.g. an API key given on the command line is not combined with an Apple ID or key ID
+    /// from the profile.
+    fn apply_service(&self, args: &mut ServiceArgs) -> Result<(), OperationError> {
+        let has_credentials = args.developer_account.is_some()
+            || args.password_keychain_item.is_some()
+            || args.keychain_profile.is_some()
+            || args.provider.is_some()
+            || args.api_token.is_some()
+            || args.api_key.is_some()
+            || args.api_key_id.is_some()
+            || args.api_issuer.is_some();
+        if !has_credentials {
+            args.developer_account = self.developer_account.clone();
+            args.password_keychain_item = self.developer_password_keychain_item.clone();
+            args.keychain_profile = self.keychain_profile.clone();
+            args.provider = self.provider.clone();
+            args.api_key = self.api_key.clone();
+            args.api_key_id = self.api_key_id.clone();
+            args.api_issuer = self.api_issuer.clone();
+        }
+        fill(&mut args.state_dir, &self.state_dir);
+
+        if let (None, Some(backend)) = (args.backend, &self.backend) {
+            args.backend = Some(backend.parse::<Backend>()?);
+        }
+
+        Ok(())
+    }
+
+    fn apply_skip_check(&self, skip_check: &mut Vec<String>) -> Result<(), OperationError> {
+        if let Some(unknown) = self
+            .skip_check
+            .iter()
+            .flatten()
+            .find(|id| !CHECK_IDS.contains(&id.as_str()))
+        {
+            return Err(OperationError::new(&format!(
+                "Unknown check {} in skip-check. Checks: {}",
+                unknown,
+                CHECK_IDS.join(", ")
+            )));
+        }
+
+        fill_list(skip_check, &self.skip_check);
+        Ok(())
+    }
+
+    fn apply_poll(&self, args: &mut PollArgs) {
+        fill(&mut args.poll_interval, &self.poll_interval);
+        fill(&mut args.max_poll_interval, &self.max_poll_interval);
+        fill(&mut args.timeout, &self.timeout);
+    }
+
+    fn apply_hooks(&self, args: &mut HookArgs) {
+        fill(&mut args.on_success, &self.on_success);
+        fill(&mut args.on_failure, &self.on_failure);
+    }
+
+    fn apply_webhooks(&self, args: &mut WebhookArgs) {
+        fill_list(&mut args.webhook, &self.webhook);
+        fill_list(&mut args.slack_webhook, &self.slack_webhook);
+    }
+}
+
+fn fill<T: Clone>(value: &mut Option<T>, default: &Option<T>) {
+    if value.is_none() {
+        *value = default.clone();
+    }
+}
+
+/// Lists given on the command line replace, rather than extend, those in the profile.
+fn fill_list(values: &mut Vec<String>, default: &Option<Vec<String>>) {
+    if let (true, Some(default)) = (values.is_empty(), default) {
+        *values = default.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use structopt::StructOpt;
+
+    use super::Profile;
+    use crate::cli::args::Args;
+    use xcnotary::notarize::Backend;
+
+    static CONFIG: &str = r#"
+[profile.default]
+developer-account = "dev@example.com"
+developer-password-keychain-item = "AC_PASSWORD"
+
+[profile.release]
+backend = "api"
+api-key = "keys/AuthKey_ABC123.p8"
+api-key-id = "ABC123"
+api-issuer = "c0dec0de-1234-5678-1234-b4d961a1d182"
+timeout = 3600
+skip-check = ["secure_timestamp"]
+on-success = "./publish.sh"
+slack-webhook = ["https://hooks.slack.com/services/T0/B0/X"]
+"#;
+
+    fn write_config(dir: &std::path::Path, text: &str) -> PathBuf {
+        let path = dir.join(super::CONFIG_FILE_NAME);
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_find() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let nested = temp_dir.path().join("a/b");
+        std::fs::create_dir_all(&nested).unwrap();
+
+        assert_eq!(super::find(&nested), None);
+
+        let path = write_config(temp_dir.path(), CONFIG);
+        assert_eq!(super::find(&nested), Some(path));
+    }
+
+    #[test]
+    fn test_load() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = write_config(temp_dir.path(), CONFIG);
+
+        let profile = super::load(&path, None).unwrap().unwrap();
+        assert_eq!(
+            profile.developer_account.as_deref(),
+            Some("dev@example.com")
+        );
+
+        let profile = super::load(&path, Some("release")).unwrap().unwrap();
+        assert_eq!(
+            profile.api_key,
+            Some(temp_dir.path().join("keys/AuthKey_ABC123.p8"))
+        );
+
+        let err = super::load(&path, Some("beta")).unwrap_err().to_string();
+        assert!(err.contains("Available profiles: default, release"));
+
+        let path = write_config(temp_dir.path(), "[profile.default]\nbackend = 1\n");
+        assert!(super::load(&path, None).is_err());
+        let path = write_config(temp_dir.path(), "[profile.default]\naccount = \"\"\n");
+        assert!(super::load(&path, None).is_err());
+
+        let path = write_config(temp_dir.path(), "[profile.release]\n");
+        assert_eq!(super::load(&path, None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_apply() {
+        let profile: super::ConfigFile = toml::from_str(CONFIG).unwrap();
+        let profile = &profile.profile["release"];
+
+        let mut args = Args::from_iter(&[
+            "xcnotary",
+            "notarize",
+            "Foo.app",
+            "--profile",
+            "release",
+            "--backend",
+            "notarytool",
+            "--on-failure",
+            "./alert.sh",
+        ]);
+        profile.apply(&mut args).unwrap();
+
+        match args {
+            Args::Notarize {
+                service,
+                skip_check,
+                poll,
+                hooks,
+                webhooks,
+                ..
+            } => {
+                assert_eq!(service.backend, Some(Backend::Notarytool));
+                assert_eq!(service.api_key_id.as_deref(), Some("ABC123"));
+                assert_eq!(skip_check, vec!["secure_timestamp"]);
+                assert_eq!(poll.timeout, Some(3600));
+                assert_eq!(poll.poll_interval, None);
+                assert_eq!(hooks.on_success.as_deref(), Some("./publish.sh"));
+                assert_eq!(hooks.on_failure.as_deref(), Some("./alert.sh"));
+                assert_eq!(webhooks.slack_webhook.len(), 1);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_apply_credentials() {
+        let profile = Profile {
+            developer_account: Some("dev@example.com".into()),
+            developer_password_keychain_item: Some("AC_PASSWORD".into()),
+            keychain_profile: Some("AC_NOTARY".into()),
+            api_key_id: Some("ABC123".into()),
+            api_issuer: Some("c0dec0de-1234-5678-1234-b4d961a1d182".into()),
+            ..Profile::default()
+        };
+        let service = |args: Args| match args {
+            Args::Status { service, .. } => service,
+            _ => unreachable!(),
+        };
+
+        let mut args = Args::from_iter(&["xcnotary", "status", "c0dec0de"]);
+        profile.apply(&mut args).unwrap();
+        let from_profile = service(args);
+        assert_eq!(
+            from_profile.developer_account.as_deref(),
+            Some("dev@example.com")
+        );
+        assert_eq!(from_profile.keychain_profile.as_deref(), Some("AC_NOTARY"));
+        assert_eq!(from_profile.api_key_id.as_deref(), Some("ABC123"));
+
+        // An API key on the command line replaces every credential in the profile.
+        let mut args = Args::from_iter(&[
+            "xcnotary",
+            "status",
+            "c0dec0de",
+            "--api-key",
+            "AuthKey_XYZ789.p8",
+        ]);
+        profile.apply(&mut args).unwrap();
+        let from_command_line = service(args);
+        assert_eq!(
+            from_command_line.api_key,
+            Some(PathBuf::from("AuthKey_XYZ789.p8"))
+        );
+        assert_eq!(from_command_line.api_key_id, None);
+        assert_eq!(from_command_line.api_issuer, None);
+        assert_eq!(from_command_line.developer_account, None);
+        assert_eq!(from_command_line.password_keychain_item, None);
+        assert_eq!(from_command_line.keychain_profile, None);
+
+        // As does a developer account.
+        let mut args = Args::from_iter(&[
+            "xcnotary",
+            "status",
+            "c0dec0de",
+            "--developer-account",
+            "other@example.com",
+        ]);
+        profile.apply(&mut args).unwrap();
+        let from_command_line = service(args);
+        assert_eq!(from_command_line.password_keychain_item, None);
+        assert_eq!(from_command_line.api_key_id, None);
+    }
+
+    #[test]
+    fn test_apply_invalid() {
+        let mut args = Args::from_iter(&["xcnotary", "precheck", "Foo.app"]);
+        let profile = Profile {
+            skip_check: Some(vec!["notarized".into()]),
+            ..Profile::default()
+        };
+        assert!(profile.apply(&mut args).is_err());
+
+        let mut args = Args::from_iter(&["xcnotary", "history"]);
+        let profile = Profile {
+            backend: Some("xcode".into()),
+            ..Profile::default()
+        };
+        assert!(profile.apply(&mut args).is_err());
+    }
+}
@@ -0,0 +1,76 @@
+//! Expansion of `*`, `?` and `[...]` wildcards in input paths, for patterns quoted to keep them
+//! from the shell, e.g. in CI configuration.
+
+use std::path::{Path, PathBuf};
+
+use xcnotary::OperationError;
+
+/// Paths matching `pattern`, sorted. A pattern without wildcards is returned as-is, whether or
+/// not it exists. Fails if a pattern with wildcards matches nothing.
+pub(crate) fn expand(pattern: &Path) -> Result<Vec<PathBuf>, OperationError> {
+    let text = pattern.to_string_lossy();
+    if !text.contains(['*', '?', '[']) {
+        return Ok(vec![pattern.into()]);
+    }
+
+    // As in the shell, wildcards do not match a leading dot.
+    let options = glob::MatchOptions {
+        require_literal_leading_dot: true,
+        ..glob::MatchOptions::new()
+    };
+    let error = |err: &dyn std::fmt::Display| {
+        OperationError::detail(
+            &format!("Could not expand {}", pattern.display()),
+            &err.to_string(),
+        )
+    };
+
+    let mut paths = glob::glob_with(&text, options)
+        .map_err(|err| error(&err))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| error(&err))?;
+    if paths.is_empty() {
+        return Err(OperationError::new(&format!(
+            "No input found matching {}",
+            pattern.display()
+        )));
+    }
+
+    paths.sort();
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::expand;
+
+    #[test]
+    fn test_expand() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        for dir in &["arm64", "x86_64"] {
+            std::fs::create_dir_all(temp_dir.path().join(dir).join("Foo.app")).unwrap();
+            std::fs::write(temp_dir.path().join(dir).join("Foo.pkg"), "").unwrap();
+        }
+        std::fs::create_dir_all(temp_dir.path().join("arm64/.Bar.app")).unwrap();
+
+        let pattern = temp_dir.path().join("*").join("*.app");
+        assert_eq!(
+            expand(&pattern).unwrap(),
+            vec![
+                temp_dir.path().join("arm64/Foo.app"),
+                temp_dir.path().join("x86_64/Foo.app"),
+            ]
+        );
+
+        let pattern = temp_dir.path().join("x86_64").join("Foo.pkg");
+        assert_eq!(expand(&pattern).unwrap(), vec![pattern.clone()]);
+
+        assert!(expand(&temp_dir.path().join("*.dmg")).is_err());
+        assert_eq!(
+            expand(&PathBuf::from("Missing.app")).unwrap(),
+            vec![PathBuf::from("Missing.app")]
+        );
+    }
+}
@@ -0,0 +1,447 @@
+//! Command line interface, a thin wrapper around the library that prints progress and results.
+
+pub(crate) mod args;
+mod batch;
+pub(crate) mod config;
+mod glob;
+mod report;
+
+use console::{style, Style};
+use std::error::Error;
+use std::path::Path;
+
+use args::{Args, HookArgs, ServiceArgs, WebhookArgs};
+use report::Report;
+use xcnotary::notarize::{
+    self, Journal, Notarization, NotarizeOp, NotarizeOpBuilder, PollSettings, Service,
+};
+use xcnotary::output::{self, OutputFormat};
+use xcnotary::precheck;
+use xcnotary::{identify_path_type, ErrorKind, OperationError};
+
+pub(crate) fn main() {
+    let args = args::parse();
+    let format = args.output_format();
+    output::set_format(format);
+
+    let mut report = Report::default();
+    let result = run(args, &mut report);
+
+    if format == OutputFormat::Json {
+        println!(
+            "{}",
+            report.into_json(result.as_ref().err().map(|err| &**err))
+        );
+        if let Err(err) = result {
+            std::process::exit(exit_code(&*err));
+        }
+        return;
+    }
+
+    result.unwrap_or_else(|err| {
+        eprintln!("\n{}", err);
+        std::process::exit(exit_code(&*err));
+    });
+}
+
+/// Exit status for `err`, documented in the README.
+fn exit_code(err: &(dyn Error + 'static)) -> i32 {
+    error_kind(err).exit_code()
+}
+
+/// Cause of `err`, including failed pre-checks, which are not reported as `OperationError`.
+fn error_kind(err: &(dyn Error + 'static)) -> ErrorKind {
+    match err.downcast_ref::<OperationError>() {
+        Some(err) => err.kind(),
+        None if precheck::is_check_failure(err) => ErrorKind::PrecheckFailed,
+        None => ErrorKind::Other,
+    }
+}
+
+/// Runs the command, recording its results in `report`.
+fn run(args: Args, report: &mut Report) -> Result<(), Box<dyn Error>> {
+    if args.output_format() == OutputFormat::Text {
+        let emphasized = Style::new().white().bold();
+        println!("{}\n", emphasized.apply_to("Processing..."),);
+    }
+
+    match config::apply_profile(args)? {
+        Args::Precheck {
+            input_path,
+            fail_fast,
+            skip_check,
+            ..
+        } => {
+            let path_type = identify_path_type(&input_path)?;
+            let checks = precheck::run(&input_path, &path_type, fail_fast, &skip_check)?;
+            report.input().add_prechecks(&checks);
+            checks.into_result()?;
+        }
+        Args::Notarize {
+            service,
+            input_paths,
+            jobs,
+            no_precheck,
+            skip_check,
+            rezip,
+            poll,
+            hooks,
+            webhooks,
+            ..
+        } => {
+            let mut paths = vec![];
+            for pattern in &input_paths {
+                paths.extend(glob::expand(pattern)?);
+            }
+            let batch = paths.len() > 1;
+
+            let journal = journal(&service)?;
+            let poll = PollSettings::from(poll);
+            let ops = paths
+                .into_iter()
+                .map(|path| {
+                    let builder = NotarizeOp::builder(&path)
+                        .service(service.clone().into())
+                        .rezip(rezip)
+                        .poll_settings(poll)
+                        .journal(journal.clone());
+                    let mut builder = with_hooks(builder, &hooks, &webhooks);
+                    if batch && output::is_text() {
+                        builder = builder.on_progress(batch::print_progress(&path));
+                    }
+                    builder.build()
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let skip = if no_precheck {
+                None
+            } else {
+                Some(skip_check.as_slice())
+            };
+            match ops.as_slice() {
+                [op] => {
+                    if let Some(skip) = skip {
+                        let checks = precheck::run(
+                            &op.input_path().to_path_buf(),
+                            op.path_type(),
+                            true,
+                            skip,
+                        )?;
+                        report.input().add_prechecks(&checks);
+                        checks.into_result()?;
+                    }
+                    print_result(op.run(), report)?
+                }
+                _ => batch::notarize(&ops, jobs, skip, report)?,
+            }
+        }
+        Args::Submit {
+            service,
+            input_path,
+            no_precheck,
+            skip_check,
+            request_id_file,
+            webhooks,
+            ..
+        } => {
+            let journal = journal(&service)?;
+            let builder = NotarizeOp::builder(input_path)
+                .service(service.into())
+                .journal(journal);
+            let op = with_hooks(builder, &HookArgs::default(), &webhooks).build()?;
+
+            if !no_precheck {
+                let checks = precheck::run(
+                    &op.input_path().to_path_buf(),
+                    op.path_type(),
+                    true,
+                    &skip_check,
+                )?;
+                report.input().add_prechecks(&checks);
+                checks.into_result()?;
+            }
+
+            submit(&op, request_id_file.as_deref(), report)?;
+        }
+        Args::Status {
+            service,
+            request_id,
+            ..
+        } => {
+            status(&request_id, &Service::connect(service.into())?, report)?;
+        }
+        Args::Wait {
+            service,
+            request_id,
+            input_path,
+            resume,
+            rezip,
+            poll,
+            hooks,
+            webhooks,
+            ..
+        } => {
+            let journal = journal(&service)?;
+            let poll = PollSettings::from(poll);
+            let builder = |path: &Path| {
+                let builder = NotarizeOp::builder(path)
+                    .service(service.clone().into())
+                    .rezip(rezip)
+                    .poll_settings(poll)
+                    .journal(journal.clone());
+                with_hooks(builder, &hooks, &webhooks)
+            };
+
+            if resume {
+                resume_unfinished(&journal, builder, report)?;
+            } else {
+                // Both are required without --resume.
+                let (request_id, input_path) = (request_id.unwrap(), input_path.unwrap());
+                report.input().request_id = Some(request_id.clone());
+                print_result(builder(&input_path).build()?.wait(&request_id), report)?;
+            }
+        }
+        Args::Validate { input_path, .. } => {
+            let path_type = identify_path_type(&input_path)?;
+            let valid = notarize::validate(&input_path, &path_type);
+            report.input().stapled = match &valid {
+                Err(err) if err.kind() != ErrorKind::InvalidTicket => None,
+                _ => Some(valid.is_ok()),
+            };
+            valid?;
+        }
+        Args::Log {
+            service,
+            request_id,
+            raw,
+        } => {
+            print_log(&request_id, &Service::connect(service.into())?, raw)?;
+        }
+        Args::History { service, remote } => {
+            if remote {
+                history(&Service::connect(service.into())?)?;
+            } else {
+                journal_history(&journal(&service)?)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Records the outcome of a submission and prints the service log if it succeeded, or fails
+/// with the issues that caused it to be rejected.
+fn print_result(
+    result: Result<Notarization, OperationError>,
+    report: &mut Report,
+) -> Result<(), OperationError> {
+    report.input().set_outcome(&result);
+    let notarization = result?;
+    if let Some(err) = notarization.error() {
+        return Err(err);
+    }
+
+    if output::is_text() {
+        println!(
+            "\n{}",
+            style("Success! Review the service response for additional issues or warnings:")
+                .green()
+                .bold()
+        );
+        println!("{}", notarization.log_summary());
+    }
+
+    Ok(())
+}
+
+/// Submits the input without waiting for completion, printing the request ID and optionally
+/// saving it to `request_id_file`.
+fn submit(
+    op: &NotarizeOp,
+    request_id_file: Option<&Path>,
+    report: &mut Report,
+) -> Result<(), OperationError> {
+    let request_id = op.submit()?;
+    report.input().request_id = Some(request_id.clone());
+
+    if let Some(path) = request_id_file {
+        std::fs::write(path, format!("{}\n", request_id)).map_err(|err| {
+            OperationError::detail(
+                &format!("Could not save request ID to {}", path.display()),
+                &err.to_string(),
+            )
+        })?;
+    }
+
+    if output::is_text() {
+        println!("\n{}", style("Submitted. Request UUID:").green().bold());
+        println!("{}", request_id);
+    }
+
+    Ok(())
+}
+
+/// `builder` with the commands and webhooks given on the command line.
+fn with_hooks(
+    mut builder: NotarizeOpBuilder,
+    hooks: &HookArgs,
+    webhooks: &WebhookArgs,
+) -> NotarizeOpBuilder {
+    if let Some(command) = &hooks.on_success {
+        builder = builder.on_success(command);
+    }
+    if let Some(command) = &hooks.on_failure {
+        builder = builder.on_failure(command);
+    }
+    for url in &webhooks.webhook {
+        builder = builder.webhook(url);
+    }
+    for url in &webhooks.slack_webhook {
+        builder = builder.slack_webhook(url);
+    }
+
+    builder
+}
+
+/// Journal in the state directory given with `--state-dir`, or in `~/.xcnotary`.
+fn journal(service: &ServiceArgs) -> Result<Journal, OperationError> {
+    service
+        .state_dir
+        .clone()
+        .or_else(Journal::default_dir)
+        .map(Journal::new)
+        .ok_or_else(|| {
+            OperationError::new("Could not determine home directory. Specify --state-dir instead.")
+        })
+}
+
+/// Waits for every unfinished submission in the journal, with options set by `builder`.
+/// Submissions whose input no longer exists are skipped.
+fn resume_unfinished(
+    journal: &Journal,
+    builder: impl Fn(&Path) -> NotarizeOpBuilder,
+    report: &mut Report,
+) -> Result<(), OperationError> {
+    let entries = journal.unfinished()?;
+    let batch = entries.len() > 1;
+
+    let mut requests = vec![];
+    for entry in entries {
+        let mut op = builder(&entry.artifact_path);
+        if batch && output::is_text() {
+            op = op.on_progress(batch::print_progress(&entry.artifact_path));
+        }
+
+        match op.build() {
+            Ok(op) => requests.push((op, entry.request_id)),
+            Err(err) => output::warn(&OperationError::detail(
+                &format!("Skipping request {}", entry.request_id),
+                &err.to_string(),
+            )),
+        }
+    }
+
+    match requests.as_slice() {
+        [] => {
+            if output::is_text() {
+                println!("No unfinished submissions in {}", journal.dir().display());
+            }
+            Ok(())
+        }
+        [(op, request_id)] => {
+            report.input().request_id = Some(request_id.clone());
+            print_result(op.wait(request_id), report)
+        }
+        _ => batch::wait(&requests, report),
+    }
+}
+
+/// Prints the current status of a request.
+fn status(request_id: &str, service: &Service, report: &mut Report) -> Result<(), OperationError> {
+    let info = service.status(request_id)?;
+
+    report.input().request_id = Some(request_id.into());
+    report
+        .input()
+        .set_status(info.status, info.status_message.clone());
+    if !output::is_text() {
+        return Ok(());
+    }
+
+    let emphasized = Style::new().white().bold();
+    println!("{} {}", emphasized.apply_to("Status:"), info.status);
+    if let Some(message) = info.status_message {
+        println!("{} {}", emphasized.apply_to("Message:"), message);
+    }
+
+    Ok(())
+}
+
+/// Prints the service log of a completed request, summarized unless `raw` is specified.
+fn print_log(request_id: &str, service: &Service, raw: bool) -> Result<(), OperationError> {
+    let log = service.log(request_id)?;
+
+    if raw {
+        println!("{}", log.text);
+    } else {
+        println!("{}", log.summary());
+    }
+
+    Ok(())
+}
+
+/// Prints the submissions recorded in the journal, most recent first.
+fn journal_history(journal: &Journal) -> Result<(), OperationError> {
+    let entries = journal.entries()?;
+
+    let emphasized = Style::new().white().bold();
+    println!(
+        "{}",
+        emphasized.apply_to(format!(
+            "{:<38}{:<26}{:<13}{}",
+            "Request UUID", "Date", "Status", "Input"
+        ))
+    );
+
+    for entry in entries.iter().rev() {
+        println!(
+            "{:<38}{:<26}{:<13}{}",
+            entry.request_id,
+            entry
+                .submitted_at
+                .as_deref()
+                .or(entry.completed_at.as_deref())
+                .unwrap_or("-"),
+            entry.status.as_deref().unwrap_or("unfinished"),
+            entry.artifact_path.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Prints recent submissions made with the developer account.
+fn history(service: &Service) -> Result<(), OperationError> {
+    let submissions = service.history()?;
+
+    let emphasized = Style::new().white().bold();
+    println!(
+        "{}",
+        emphasized.apply_to(format!(
+            "{:<38}{:<26}{:<13}{}",
+            "Request UUID", "Date", "Status", "Name"
+        ))
+    );
+
+    for submission in submissions {
+        println!(
+            "{:<38}{:<26}{:<13}{}",
+            submission.request_id,
+            submission.date,
+            submission.status.to_string(),
+            submission.name.unwrap_or_default()
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,271 @@
+//! Document printed on completion with `--output json`, built from the results returned by the
+//! library.
+
+use serde::Serialize;
+use std::error::Error;
+
+use xcnotary::notarize::{Notarization, NotarizationIssueSeverity, NotarizationStatus};
+use xcnotary::precheck::{CheckOutcome, PrecheckReport};
+use xcnotary::{ErrorKind, OperationError};
+
+/// Field names are part of the command line interface and must not change.
+#[derive(Serialize, Debug)]
+pub(crate) struct Report {
+    /// Results of the input. Absent when notarizing several inputs, whose results are listed in
+    /// `inputs` instead.
+    #[serde(flatten)]
+    results: Option<Results>,
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inputs: Option<Vec<InputResult>>,
+}
+
+/// Results of one input.
+#[derive(Serialize, Debug, Default)]
+pub(crate) struct Results {
+    pub(crate) prechecks: Vec<PrecheckResult>,
+    pub(crate) request_id: Option<String>,
+    /// One of "in_progress", "success" or "invalid".
+    pub(crate) status: Option<&'static str>,
+    pub(crate) status_message: Option<String>,
+    pub(crate) log_url: Option<String>,
+    pub(crate) issues: Vec<Issue>,
+    pub(crate) stapled: Option<bool>,
+    /// True if an identical file was already notarized, so the input was not uploaded again.
+    pub(crate) reused: bool,
+}
+
+/// Results of one of several inputs, and why it failed, if it did.
+#[derive(Serialize, Debug)]
+pub(crate) struct InputResult {
+    pub(crate) path: String,
+    #[serde(flatten)]
+    pub(crate) results: Results,
+    pub(crate) error: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct PrecheckResult {
+    pub(crate) check: &'static str,
+    /// Bundle checked, for input that may contain several.
+    pub(crate) bundle: Option<String>,
+    /// One of "pass", "fail" or "skip".
+    pub(crate) result: &'static str,
+    pub(crate) message: Option<String>,
+    pub(crate) solution: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct Issue {
+    /// One of "error", "warning" or "other".
+    pub(crate) severity: &'static str,
+    pub(crate) path: Option<String>,
+    pub(crate) message: String,
+    pub(crate) doc_url: Option<String>,
+    pub(crate) architecture: Option<String>,
+}
+
+impl Default for Report {
+    fn default() -> Self {
+        Report {
+            results: Some(Results::default()),
+            error: None,
+            inputs: None,
+        }
+    }
+}
+
+impl Report {
+    /// Results of the input, when not notarizing several.
+    pub(crate) fn input(&mut self) -> &mut Results {
+        self.results.get_or_insert_with(Results::default)
+    }
+
+    /// Lists the results of each of several inputs in place of those of a single input.
+    pub(crate) fn set_inputs(&mut self, inputs: Vec<InputResult>) {
+        self.results = None;
+        self.inputs = Some(inputs);
+    }
+
+    /// The document, describing `error` if the command failed.
+    pub(crate) fn into_json(mut self, error: Option<&dyn Error>) -> String {
+        self.error = error.map(|err| err.to_string().trim().to_string());
+
+        serde_json::to_string_pretty(&self).unwrap()
+    }
+}
+
+impl Results {
+    /// Adds the result of each check performed.
+    pub(crate) fn add_prechecks(&mut self, report: &PrecheckReport) {
+        self.prechecks.extend(report.results().iter().map(|check| {
+            let (result, message, solution) = match &check.outcome {
+                CheckOutcome::Pass => ("pass", None, None),
+                CheckOutcome::Fail(error) => (
+                    "fail",
+                    Some(error.message().into()),
+                    Some(error.solution().into()),
+                ),
+                CheckOutcome::Skip => ("skip", None, None),
+            };
+
+            PrecheckResult {
+                check: check.check,
+                bundle: check.bundle.clone(),
+                result,
+                message,
+                solution,
+            }
+        }));
+    }
+
+    /// Records the outcome of a submission. A failure occurring after submission is described
+    /// as far as its kind allows, e.g. a timed out request is still in progress.
+    pub(crate) fn set_outcome(&mut self, result: &Result<Notarization, OperationError>) {
+        let notarization = match result {
+            Ok(notarization) => notarization,
+            Err(err) => {
+                if let Some(request_id) = err.request_id() {
+                    self.request_id = Some(request_id.into());
+                }
+                match err.kind() {
+                    ErrorKind::TimedOut => self.set_status(NotarizationStatus::InProgress, None),
+                    ErrorKind::StapleFailed => {
+                        self.set_status(NotarizationStatus::Success, None);
+                        self.stapled = Some(false);
+                    }
+                    _ => (),
+                }
+                return;
+            }
+        };
+
+        self.request_id = Some(notarization.request_id.clone());
+        self.set_status(notarization.status, notarization.status_message.clone());
+        self.log_url = notarization.log_url.clone();
+        self.issues = notarization
+            .issues()
+            .into_iter()
+            .map(|issue| Issue {
+                severity: match issue.severity {
+                    NotarizationIssueSeverity::Error => "error",
+                    NotarizationIssueSeverity::Warning => "warning",
+                    NotarizationIssueSeverity::Other => "other",
+                },
+                path: issue.path,
+                message: issue.message,
+                doc_url: issue.doc_url,
+                architecture: issue.architecture,
+            })
+            .collect();
+        if notarization.is_success() {
+            self.stapled = Some(notarization.stapled);
+        }
+        self.reused = notarization.reused;
+    }
+
+    pub(crate) fn set_status(&mut self, status: NotarizationStatus, message: Option<String>) {
+        self.status = Some(status.id());
+        self.status_message = message;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InputResult, Report, Results};
+    use xcnotary::notarize::{Notarization, NotarizationStatus};
+    use xcnotary::{ErrorKind, OperationError};
+
+    fn notarization(status: NotarizationStatus, log: &str) -> Notarization {
+        Notarization {
+            request_id: "f00df00d".into(),
+            status,
+            status_message: Some("Package Invalid".into()),
+            log: log.into(),
+            log_url: Some("https://example.com/log".into()),
+            stapled: false,
+            reused: false,
+        }
+    }
+
+    #[test]
+    fn test_report_log() {
+        let mut results = Results::default();
+        results.set_outcome(&Ok(notarization(
+            NotarizationStatus::Invalid,
+            r#"{
+                "status": "Invalid",
+                "issues": [{
+                    "severity": "error",
+                    "path": "Foo.zip/Foo.app/Contents/MacOS/Foo",
+                    "message": "The binary is not signed.",
+                    "docUrl": null,
+                    "architecture": "arm64"
+                }]
+            }"#,
+        )));
+
+        let document = serde_json::to_value(&results).unwrap();
+        assert_eq!(document["request_id"], "f00df00d");
+        assert_eq!(document["status"], "invalid");
+        assert_eq!(document["status_message"], "Package Invalid");
+        assert_eq!(document["log_url"], "https://example.com/log");
+        assert_eq!(document["issues"][0]["severity"], "error");
+        assert_eq!(document["issues"][0]["architecture"], "arm64");
+        assert!(document["issues"][0]["doc_url"].is_null());
+        assert!(document["stapled"].is_null());
+    }
+
+    #[test]
+    fn test_report_unparseable_log() {
+        let mut results = Results::default();
+        results.set_outcome(&Ok(notarization(NotarizationStatus::Success, "not json")));
+
+        assert!(results.issues.is_empty());
+        assert_eq!(results.stapled, Some(false));
+    }
+
+    #[test]
+    fn test_report_error() {
+        let mut results = Results::default();
+        results.set_outcome(&Err(
+            OperationError::new("Timed out").with_kind(ErrorKind::TimedOut)
+        ));
+        assert_eq!(results.status, Some("in_progress"));
+
+        let mut results = Results::default();
+        results.set_outcome(&Err(
+            OperationError::new("Could not staple").with_kind(ErrorKind::StapleFailed)
+        ));
+        assert_eq!(results.status, Some("success"));
+        assert_eq!(results.stapled, Some(false));
+    }
+
+    #[test]
+    fn test_report_inputs() {
+        let mut report = Report::default();
+        report.input().request_id = Some("f00df00d".into());
+        let single: serde_json::Value = serde_json::from_str(&report.into_json(None)).unwrap();
+        assert_eq!(single["request_id"], "f00df00d");
+        assert_eq!(single["prechecks"], serde_json::json!([]));
+        assert!(single.get("inputs").is_none());
+
+        let mut results = Results::default();
+        results.set_outcome(&Ok(notarization(NotarizationStatus::Invalid, "{}")));
+        let mut report = Report::default();
+        report.set_inputs(vec![InputResult {
+            path: "Foo.pkg".into(),
+            results,
+            error: Some("Notarization failed".into()),
+        }]);
+        let batch: serde_json::Value = serde_json::from_str(&report.into_json(None)).unwrap();
+        assert!(batch.get("request_id").is_none());
+        assert!(batch.get("prechecks").is_none());
+        assert!(batch["error"].is_null());
+        assert_eq!(batch["inputs"][0]["path"], "Foo.pkg");
+        assert_eq!(batch["inputs"][0]["request_id"], "f00df00d");
+        assert_eq!(batch["inputs"][0]["status"], "invalid");
+        assert_eq!(batch["inputs"][0]["log_url"], "https://example.com/log");
+        assert_eq!(batch["inputs"][0]["error"], "Notarization failed");
+    }
+}
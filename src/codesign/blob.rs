@@ -0,0 +1,282 @@
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384};
+
+use super::cms::SignedData;
+use super::macho::be32;
+use crate::util::OperationError;
+
+const CSMAGIC_EMBEDDED_SIGNATURE: u32 = 0xfade_0cc0;
+const CSMAGIC_CODEDIRECTORY: u32 = 0xfade_0c02;
+const CSMAGIC_EMBEDDED_ENTITLEMENTS: u32 = 0xfade_7171;
+const CSMAGIC_EMBEDDED_DER_ENTITLEMENTS: u32 = 0xfade_7172;
+const CSMAGIC_BLOBWRAPPER: u32 = 0xfade_0b01;
+
+const CSSLOT_CODEDIRECTORY: u32 = 0;
+const CSSLOT_ALTERNATE_CODEDIRECTORIES: u32 = 0x1000;
+const CSSLOT_ALTERNATE_CODEDIRECTORY_LIMIT: u32 = 0x1005;
+/// Notarization ticket stapled to a disk image, stored without a blob header.
+const CSSLOT_TICKET: u32 = 0x10002;
+
+/// Length of a CDHash, the truncated hash of a CodeDirectory identifying signed code.
+const CDHASH_LEN: usize = 20;
+
+const CS_SUPPORTSTEAMID: u32 = 0x20200;
+
+pub(crate) const CS_RUNTIME: u32 = 0x10000;
+
+/// Hash algorithm used for the page and special slot hashes of a CodeDirectory.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum HashType {
+    Sha1,
+    Sha256,
+    Sha256Truncated,
+    Sha384,
+    Unknown(u8),
+}
+
+impl HashType {
+    /// Value identifying the hash type in a CodeDirectory.
+    pub(crate) fn value(self) -> u8 {
+        match self {
+            HashType::Sha1 => 1,
+            HashType::Sha256 => 2,
+            HashType::Sha256Truncated => 3,
+            HashType::Sha384 => 4,
+            HashType::Unknown(value) => value,
+        }
+    }
+
+    fn digest(self, data: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            HashType::Sha1 => Some(Sha1::digest(data).to_vec()),
+            HashType::Sha256 | HashType::Sha256Truncated => Some(Sha256::digest(data).to_vec()),
+            HashType::Sha384 => Some(Sha384::digest(data).to_vec()),
+            HashType::Unknown(_) => None,
+        }
+    }
+}
+
+impl From<u8> for HashType {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => HashType::Sha1,
+            2 => HashType::Sha256,
+            3 => HashType::Sha256Truncated,
+            4 => HashType::Sha384,
+            other => HashType::Unknown(other),
+        }
+    }
+}
+
+/// Prechecks only consult the flags; the remaining fields are decoded for diagnostics.
+#[allow(dead_code)]
+#[derive(Debug)]
+pub(crate) struct CodeDirectory {
+    pub(crate) version: u32,
+    pub(crate) flags: u32,
+    pub(crate) hash_type: HashType,
+    pub(crate) identifier: String,
+    pub(crate) team_id: Option<String>,
+    /// Identifies the code to the notarization service. Absent for an unknown hash type.
+    pub(crate) cdhash: Option<Vec<u8>>,
+}
+
+impl CodeDirectory {
+    fn parse(blob: &[u8]) -> Result<Self, OperationError> {
+        let field = |offset| be32(blob, offset).ok_or_else(malformed);
+
+        let version = field(8)?;
+        let flags = field(12)?;
+        let identifier = c_string(blob, field(20)? as usize)?;
+        let hash_type = HashType::from(*blob.get(37).ok_or_else(malformed)?);
+
+        let team_id = if version >= CS_SUPPORTSTEAMID {
+            match field(48)? {
+                0 => None,
+                offset => Some(c_string(blob, offset as usize)?),
+            }
+        } else {
+            None
+        };
+
+        let cdhash = hash_type.digest(blob).map(|mut digest| {
+            digest.truncate(CDHASH_LEN);
+            digest
+        });
+
+        Ok(CodeDirectory {
+            version,
+            flags,
+            hash_type,
+            identifier,
+            team_id,
+            cdhash,
+        })
+    }
+
+    pub(crate) fn has_hardened_runtime(&self) -> bool {
+        self.flags & CS_RUNTIME != 0
+    }
+}
+
+impl CodeSignature {
+    /// CodeDirectory whose CDHash is used to look up a notarization ticket, preferring
+    /// SHA-256 over the SHA-1 CodeDirectory of signatures compatible with older systems.
+    pub(crate) fn ticket_code_directory(&self) -> Option<&CodeDirectory> {
+        let mut directories = self
+            .code_directory
+            .iter()
+            .chain(&self.alternate_code_directories)
+            .filter(|directory| directory.cdhash.is_some());
+
+        let first = directories.clone().next();
+        directories
+            .find(|directory| directory.hash_type == HashType::Sha256)
+            .or(first)
+    }
+}
+
+/// Contents of an embedded signature SuperBlob.
+#[derive(Debug)]
+pub(crate) struct CodeSignature {
+    pub(crate) code_directory: Option<CodeDirectory>,
+    pub(crate) alternate_code_directories: Vec<CodeDirectory>,
+    pub(crate) entitlements: Option<Vec<u8>>,
+    pub(crate) der_entitlements: Option<Vec<u8>>,
+    /// Absent for ad-hoc signatures.
+    pub(crate) signed_data: Option<SignedData>,
+    /// Stapled notarization ticket, only present for disk images.
+    pub(crate) ticket: Option<Vec<u8>>,
+}
+
+impl CodeSignature {
+    pub(crate) fn parse(data: &[u8]) -> Result<Self, OperationError> {
+        if be32(data, 0) != Some(CSMAGIC_EMBEDDED_SIGNATURE) {
+            return Err(OperationError::new(
+                "Code signature does not contain an embedded signature",
+            ));
+        }
+
+        let mut blob = CodeSignature {
+            code_directory: None,
+            alternate_code_directories: vec![],
+            entitlements: None,
+            der_entitlements: None,
+            signed_data: None,
+            ticket: None,
+        };
+
+        for (slot, offset) in index(data)? {
+            if slot == CSSLOT_TICKET {
+                blob.ticket = Some(blob_extent(data, slot, offset)?.to_vec());
+                continue;
+            }
+
+            let magic = be32(data, offset).ok_or_else(malformed)?;
+            let length = be32(data, offset + 4).ok_or_else(malformed)? as usize;
+            let contents = data.get(offset..offset + length).ok_or_else(malformed)?;
+            let payload = contents.get(8..).ok_or_else(malformed)?;
+
+            match magic {
+                CSMAGIC_CODEDIRECTORY if slot == CSSLOT_CODEDIRECTORY => {
+                    blob.code_directory = Some(CodeDirectory::parse(contents)?)
+                }
+                CSMAGIC_CODEDIRECTORY
+                    if (CSSLOT_ALTERNATE_CODEDIRECTORIES..CSSLOT_ALTERNATE_CODEDIRECTORY_LIMIT)
+                        .contains(&slot) =>
+                {
+                    blob.alternate_code_directories
+                        .push(CodeDirectory::parse(contents)?)
+                }
+                CSMAGIC_EMBEDDED_ENTITLEMENTS => blob.entitlements = Some(payload.to_vec()),
+                CSMAGIC_EMBEDDED_DER_ENTITLEMENTS => blob.der_entitlements = Some(payload.to_vec()),
+                // Ad-hoc signatures carry an empty wrapper.
+                CSMAGIC_BLOBWRAPPER if !payload.is_empty() => {
+                    blob.signed_data = Some(SignedData::parse(payload)?)
+                }
+                _ => {}
+            }
+        }
+
+        Ok(blob)
+    }
+}
+
+/// Copy of the embedded signature SuperBlob `data` with `ticket` stapled, replacing any
+/// previously stapled ticket.
+pub(crate) fn attach_ticket(data: &[u8], ticket: &[u8]) -> Result<Vec<u8>, OperationError> {
+    if be32(data, 0) != Some(CSMAGIC_EMBEDDED_SIGNATURE) {
+        return Err(OperationError::new(
+            "Code signature does not contain an embedded signature",
+        ));
+    }
+
+    let mut blobs = index(data)?
+        .into_iter()
+        .filter(|(slot, _)| *slot != CSSLOT_TICKET)
+        .map(|(slot, offset)| Ok((slot, blob_extent(data, slot, offset)?)))
+        .collect::<Result<Vec<_>, OperationError>>()?;
+    blobs.push((CSSLOT_TICKET, ticket));
+
+    let header_len = 12 + blobs.len() * 8;
+    let total_len = header_len + blobs.iter().map(|(_, blob)| blob.len()).sum::<usize>();
+
+    let mut out = Vec::with_capacity(total_len);
+    out.extend(&CSMAGIC_EMBEDDED_SIGNATURE.to_be_bytes());
+    out.extend(&(total_len as u32).to_be_bytes());
+    out.extend(&(blobs.len() as u32).to_be_bytes());
+
+    let mut offset = header_len;
+    for (slot, blob) in &blobs {
+        out.extend(&slot.to_be_bytes());
+        out.extend(&(offset as u32).to_be_bytes());
+        offset += blob.len();
+    }
+    for (_, blob) in blobs {
+        out.extend(blob);
+    }
+
+    Ok(out)
+}
+
+/// Slot type and offset of each blob in a SuperBlob.
+fn index(data: &[u8]) -> Result<Vec<(u32, usize)>, OperationError> {
+    let count = be32(data, 8).ok_or_else(malformed)? as usize;
+
+    (0..count)
+        .map(|i| {
+            let slot = be32(data, 12 + i * 8).ok_or_else(malformed)?;
+            let offset = be32(data, 16 + i * 8).ok_or_else(malformed)? as usize;
+            Ok((slot, offset))
+        })
+        .collect()
+}
+
+/// Bytes of the blob in `slot` at `offset`. A stapled ticket has no blob header, and extends to
+/// the next blob or the end of the SuperBlob.
+fn blob_extent(data: &[u8], slot: u32, offset: usize) -> Result<&[u8], OperationError> {
+    let end = if slot == CSSLOT_TICKET {
+        let len = be32(data, 4).ok_or_else(malformed)? as usize;
+        index(data)?
+            .into_iter()
+            .map(|(_, other)| other)
+            .filter(|other| *other > offset)
+            .min()
+            .unwrap_or(len)
+    } else {
+        offset + be32(data, offset + 4).ok_or_else(malformed)? as usize
+    };
+
+    data.get(offset..end).ok_or_else(malformed)
+}
+
+fn c_string(data: &[u8], offset: usize) -> Result<String, OperationError> {
+    let bytes = data.get(offset..).ok_or_else(malformed)?;
+    let end = bytes.iter().position(|b| *b == 0).ok_or_else(malformed)?;
+
+    Ok(String::from_utf8_lossy(&bytes[..end]).into())
+}
+
+fn malformed() -> OperationError {
+    OperationError::new("Code signature is truncated or malformed")
+}
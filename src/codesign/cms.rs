@@ -0,0 +1,189 @@
+use super::der::{
+    context, Element, Reader, TAG_INTEGER, TAG_OCTET_STRING, TAG_OID, TAG_SEQUENCE, TAG_SET,
+};
+use crate::util::OperationError;
+
+/// 2.5.4.3
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+/// 1.2.840.113549.1.9.16.2.14, attached by `codesign --timestamp`.
+const OID_TIMESTAMP_TOKEN: &[u8] = &[
+    0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x10, 0x02, 0x0e,
+];
+/// 1.2.840.113635.100.6.1.13, present in Developer ID Application certificates.
+const OID_DEVELOPER_ID_APPLICATION: &[u8] =
+    &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x63, 0x64, 0x06, 0x01, 0x0d];
+/// 1.2.840.113635.100.6.1.14, present in Developer ID Installer certificates.
+const OID_DEVELOPER_ID_INSTALLER: &[u8] =
+    &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x63, 0x64, 0x06, 0x01, 0x0e];
+
+#[derive(Debug)]
+pub(crate) struct Certificate {
+    pub(crate) common_name: Option<String>,
+    issuer: Vec<u8>,
+    serial: Vec<u8>,
+    extensions: Vec<Vec<u8>>,
+}
+
+impl Certificate {
+    /// Parses a DER-encoded X.509 certificate, e.g. one embedded in an installer package.
+    pub(crate) fn from_der(data: &[u8]) -> Result<Self, OperationError> {
+        Self::parse(&Reader::new(data).expect(TAG_SEQUENCE)?)
+    }
+
+    fn parse(element: &Element) -> Result<Self, OperationError> {
+        let mut tbs = element.reader().expect(TAG_SEQUENCE)?.reader();
+
+        tbs.optional(context(0))?;
+        let serial = tbs.expect(TAG_INTEGER)?.contents.to_vec();
+        tbs.expect(TAG_SEQUENCE)?;
+        let issuer = tbs.expect(TAG_SEQUENCE)?.contents.to_vec();
+        tbs.expect(TAG_SEQUENCE)?;
+        let subject = tbs.expect(TAG_SEQUENCE)?;
+        tbs.expect(TAG_SEQUENCE)?;
+
+        let mut extensions = vec![];
+        while !tbs.is_empty() {
+            let element = tbs.next()?;
+            if element.tag != context(3) {
+                continue;
+            }
+            let mut list = element.reader().expect(TAG_SEQUENCE)?.reader();
+            while !list.is_empty() {
+                let extension = list.expect(TAG_SEQUENCE)?;
+                extensions.push(extension.reader().expect(TAG_OID)?.contents.to_vec());
+            }
+        }
+
+        Ok(Certificate {
+            common_name: common_name(&subject)?,
+            issuer,
+            serial,
+            extensions,
+        })
+    }
+
+    pub(crate) fn is_developer_id_application(&self) -> bool {
+        self.extensions
+            .iter()
+            .any(|oid| oid.as_slice() == OID_DEVELOPER_ID_APPLICATION)
+    }
+
+    pub(crate) fn is_developer_id_installer(&self) -> bool {
+        self.extensions
+            .iter()
+            .any(|oid| oid.as_slice() == OID_DEVELOPER_ID_INSTALLER)
+    }
+}
+
+/// Fields of a CMS SignedData blob relevant to notarization, e.g. from a code signature or a
+/// notarization ticket.
+#[derive(Debug)]
+pub(crate) struct SignedData {
+    /// Encapsulated content. Absent for the detached signatures of code.
+    pub(crate) content: Option<Vec<u8>>,
+    pub(crate) certificates: Vec<Certificate>,
+    /// False if the blob has no signer information, i.e. it is not actually signed.
+    pub(crate) has_signer: bool,
+    /// Issuer and serial number identifying the signing certificate.
+    signer: Option<(Vec<u8>, Vec<u8>)>,
+    pub(crate) has_timestamp: bool,
+}
+
+impl SignedData {
+    pub(crate) fn parse(data: &[u8]) -> Result<Self, OperationError> {
+        let mut content_info = Reader::new(data).expect(TAG_SEQUENCE)?.reader();
+        content_info.expect(TAG_OID)?;
+
+        let mut signed_data = content_info
+            .expect(context(0))?
+            .reader()
+            .expect(TAG_SEQUENCE)?
+            .reader();
+        signed_data.expect(TAG_INTEGER)?;
+        signed_data.expect(TAG_SET)?;
+
+        let mut encapsulated = signed_data.expect(TAG_SEQUENCE)?.reader();
+        encapsulated.expect(TAG_OID)?;
+        let content = match encapsulated.optional(context(0))? {
+            Some(content) => Some(content.reader().expect(TAG_OCTET_STRING)?.contents.to_vec()),
+            None => None,
+        };
+
+        let mut certificates = vec![];
+        if let Some(list) = signed_data.optional(context(0))? {
+            let mut list = list.reader();
+            while !list.is_empty() {
+                certificates.push(Certificate::parse(&list.expect(TAG_SEQUENCE)?)?);
+            }
+        }
+        signed_data.optional(context(1))?;
+
+        let mut signer = None;
+        let mut has_timestamp = false;
+
+        let mut signer_infos = signed_data.expect(TAG_SET)?.reader();
+        let has_signer = !signer_infos.is_empty();
+        if has_signer {
+            let mut signer_info = signer_infos.expect(TAG_SEQUENCE)?.reader();
+            signer_info.expect(TAG_INTEGER)?;
+
+            if let Some(sid) = signer_info.optional(TAG_SEQUENCE)? {
+                let mut sid = sid.reader();
+                let issuer = sid.expect(TAG_SEQUENCE)?.contents.to_vec();
+                let serial = sid.expect(TAG_INTEGER)?.contents.to_vec();
+                signer = Some((issuer, serial));
+            } else {
+                signer_info.next()?;
+            }
+
+            signer_info.expect(TAG_SEQUENCE)?;
+            signer_info.optional(context(0))?;
+            signer_info.expect(TAG_SEQUENCE)?;
+            signer_info.expect(TAG_OCTET_STRING)?;
+
+            if let Some(attributes) = signer_info.optional(context(1))? {
+                let mut attributes = attributes.reader();
+                while !attributes.is_empty() {
+                    let attribute = attributes.expect(TAG_SEQUENCE)?;
+                    if attribute.reader().expect(TAG_OID)?.contents == OID_TIMESTAMP_TOKEN {
+                        has_timestamp = true;
+                    }
+                }
+            }
+        }
+
+        Ok(SignedData {
+            content,
+            certificates,
+            has_signer,
+            signer,
+            has_timestamp,
+        })
+    }
+
+    /// Certificate whose key produced the signature, i.e. the leaf of the chain.
+    pub(crate) fn signing_certificate(&self) -> Option<&Certificate> {
+        let (issuer, serial) = self.signer.as_ref()?;
+
+        self.certificates
+            .iter()
+            .find(|cert| &cert.issuer == issuer && &cert.serial == serial)
+    }
+}
+
+fn common_name(name: &Element) -> Result<Option<String>, OperationError> {
+    let mut rdns = name.reader();
+    while !rdns.is_empty() {
+        let mut set = rdns.expect(TAG_SET)?.reader();
+        while !set.is_empty() {
+            let mut attribute = set.expect(TAG_SEQUENCE)?.reader();
+            if attribute.expect(TAG_OID)?.contents == OID_COMMON_NAME {
+                return Ok(Some(
+                    String::from_utf8_lossy(attribute.next()?.contents).into(),
+                ));
+            }
+        }
+    }
+
+    Ok(None)
+}
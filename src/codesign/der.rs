@@ -0,0 +1,181 @@
+use crate::util::OperationError;
+
+pub(crate) const TAG_INTEGER: u8 = 0x02;
+pub(crate) const TAG_OCTET_STRING: u8 = 0x04;
+pub(crate) const TAG_OID: u8 = 0x06;
+pub(crate) const TAG_SEQUENCE: u8 = 0x30;
+pub(crate) const TAG_SET: u8 = 0x31;
+
+/// Context-specific constructed tag, e.g. `[0]` in ASN.1 notation.
+pub(crate) const fn context(n: u8) -> u8 {
+    0xa0 | n
+}
+
+/// Single element: its tag and contents, excluding the header.
+pub(crate) struct Element<'a> {
+    pub(crate) tag: u8,
+    pub(crate) contents: &'a [u8],
+}
+
+/// Minimal reader for the subset of BER used by code signing CMS blobs: single-byte tags,
+/// definite lengths, and the indefinite lengths that `codesign` emits for outer structures.
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub(crate) fn peek_tag(&self) -> Option<u8> {
+        self.data.first().copied()
+    }
+
+    pub(crate) fn next(&mut self) -> Result<Element<'a>, OperationError> {
+        let (element, len) = read_element(self.data)?;
+        self.data = &self.data[len..];
+        Ok(element)
+    }
+
+    /// Reads the next element, failing if its tag is not `tag`.
+    pub(crate) fn expect(&mut self, tag: u8) -> Result<Element<'a>, OperationError> {
+        let element = self.next()?;
+        if element.tag != tag {
+            return Err(malformed(&format!(
+                "expected tag {:#04x}, found {:#04x}",
+                tag, element.tag
+            )));
+        }
+        Ok(element)
+    }
+
+    /// Reads the next element only if its tag is `tag`.
+    pub(crate) fn optional(&mut self, tag: u8) -> Result<Option<Element<'a>>, OperationError> {
+        if self.peek_tag() == Some(tag) {
+            self.next().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl<'a> Element<'a> {
+    pub(crate) fn reader(&self) -> Reader<'a> {
+        Reader::new(self.contents)
+    }
+}
+
+fn read_element(data: &[u8]) -> Result<(Element<'_>, usize), OperationError> {
+    if data.len() < 2 {
+        return Err(malformed("unexpected end of data"));
+    }
+
+    let tag = data[0];
+    if tag & 0x1f == 0x1f {
+        return Err(malformed("multi-byte tags are not supported"));
+    }
+
+    let first_len = data[1] as usize;
+    let header_len;
+    let contents_len;
+
+    if first_len == 0x80 {
+        // Indefinite length: contents continue until an end-of-contents marker.
+        let mut offset = 2;
+        loop {
+            if data.len() < offset + 2 {
+                return Err(malformed("missing end-of-contents marker"));
+            }
+            if data[offset] == 0 && data[offset + 1] == 0 {
+                break;
+            }
+            offset += read_element(&data[offset..])?.1;
+        }
+        return Ok((
+            Element {
+                tag,
+                contents: &data[2..offset],
+            },
+            offset + 2,
+        ));
+    } else if first_len & 0x80 != 0 {
+        let count = first_len & 0x7f;
+        if count > 4 || data.len() < 2 + count {
+            return Err(malformed("invalid length"));
+        }
+        header_len = 2 + count;
+        contents_len = data[2..header_len]
+            .iter()
+            .fold(0usize, |acc, b| (acc << 8) | *b as usize);
+    } else {
+        header_len = 2;
+        contents_len = first_len;
+    }
+
+    let end = header_len
+        .checked_add(contents_len)
+        .filter(|end| *end <= data.len())
+        .ok_or_else(|| malformed("length exceeds available data"))?;
+
+    Ok((
+        Element {
+            tag,
+            contents: &data[header_len..end],
+        },
+        end,
+    ))
+}
+
+fn malformed(detail: &str) -> OperationError {
+    OperationError::new(&format!("Malformed signature data: {}", detail))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_definite_length() {
+        // SEQUENCE { INTEGER 5, OCTET STRING (130 bytes) }
+        let mut data = vec![0x30, 0x81, 0x88, 0x02, 0x01, 0x05, 0x04, 0x81, 0x82];
+        data.extend(vec![0xaa; 130]);
+
+        let mut reader = Reader::new(&data);
+        let sequence = reader.expect(TAG_SEQUENCE).unwrap();
+        assert!(reader.is_empty());
+
+        let mut contents = sequence.reader();
+        assert_eq!(contents.expect(TAG_INTEGER).unwrap().contents, &[5]);
+        assert_eq!(
+            contents.expect(TAG_OCTET_STRING).unwrap().contents.len(),
+            130
+        );
+        assert!(contents.is_empty());
+    }
+
+    #[test]
+    fn test_indefinite_length() {
+        // SEQUENCE (indefinite) { [0] (indefinite) { INTEGER 1 } } followed by INTEGER 2
+        let data = [
+            0x30, 0x80, 0xa0, 0x80, 0x02, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x02, 0x01, 0x02,
+        ];
+
+        let mut reader = Reader::new(&data);
+        let sequence = reader.expect(TAG_SEQUENCE).unwrap();
+        let inner = sequence.reader().expect(context(0)).unwrap();
+        assert_eq!(inner.reader().expect(TAG_INTEGER).unwrap().contents, &[1]);
+
+        assert_eq!(reader.expect(TAG_INTEGER).unwrap().contents, &[2]);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let data = [0x30, 0x05, 0x02, 0x01];
+        assert!(Reader::new(&data).next().is_err());
+    }
+}
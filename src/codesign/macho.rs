@@ -0,0 +1,150 @@
+use crate::util::OperationError;
+
+const FAT_MAGIC: u32 = 0xcafe_babe;
+const FAT_MAGIC_64: u32 = 0xcafe_babf;
+const MH_MAGIC: u32 = 0xfeed_face;
+const MH_MAGIC_64: u32 = 0xfeed_facf;
+
+const LC_CODE_SIGNATURE: u32 = 0x1d;
+
+/// Java class files share the fat magic number; real universal binaries have few slices.
+const MAX_FAT_ARCHS: u32 = 45;
+
+/// Single architecture slice of a (possibly universal) Mach-O binary.
+pub(super) struct Slice<'a> {
+    pub(super) cpu_type: u32,
+    pub(super) data: &'a [u8],
+}
+
+/// Returns true if `data` starts with a thin or universal Mach-O header.
+pub(super) fn is_macho(data: &[u8]) -> bool {
+    match be32(data, 0) {
+        Some(FAT_MAGIC) | Some(FAT_MAGIC_64) => be32(data, 4).is_some_and(|n| n < MAX_FAT_ARCHS),
+        _ => matches!(le32(data, 0), Some(MH_MAGIC) | Some(MH_MAGIC_64)),
+    }
+}
+
+/// Splits a universal binary into its architecture slices, or returns a thin binary as-is.
+pub(super) fn slices(data: &[u8]) -> Result<Vec<Slice<'_>>, OperationError> {
+    let magic = be32(data, 0).ok_or_else(not_macho)?;
+    if magic != FAT_MAGIC && magic != FAT_MAGIC_64 {
+        let cpu_type = thin_header(data)?.1;
+        return Ok(vec![Slice { cpu_type, data }]);
+    }
+
+    let count = be32(data, 4)
+        .filter(|n| *n < MAX_FAT_ARCHS)
+        .ok_or_else(not_macho)?;
+    // Without slices, there would be no code to check.
+    if count == 0 {
+        return Err(truncated());
+    }
+    let entry_size = if magic == FAT_MAGIC { 20 } else { 32 };
+
+    (0..count as usize)
+        .map(|i| {
+            let entry = 8 + i * entry_size;
+            let cpu_type = be32(data, entry).ok_or_else(truncated)?;
+            let (offset, size) = if magic == FAT_MAGIC {
+                (
+                    be32(data, entry + 8).map(u64::from),
+                    be32(data, entry + 12).map(u64::from),
+                )
+            } else {
+                (be64(data, entry + 8), be64(data, entry + 16))
+            };
+            let (offset, size) = (
+                offset.ok_or_else(truncated)? as usize,
+                size.ok_or_else(truncated)? as usize,
+            );
+
+            let data = offset
+                .checked_add(size)
+                .and_then(|end| data.get(offset..end))
+                .ok_or_else(truncated)?;
+            thin_header(data)?;
+
+            Ok(Slice { cpu_type, data })
+        })
+        .collect()
+}
+
+impl<'a> Slice<'a> {
+    /// Contents of the `LC_CODE_SIGNATURE` data, if the slice is signed.
+    pub(super) fn code_signature(&self) -> Result<Option<&'a [u8]>, OperationError> {
+        let (header_size, _) = thin_header(self.data)?;
+        let command_count = le32(self.data, 16).ok_or_else(truncated)?;
+
+        let mut offset = header_size;
+        for _ in 0..command_count {
+            let cmd = le32(self.data, offset).ok_or_else(truncated)?;
+            let cmd_size = le32(self.data, offset + 4).ok_or_else(truncated)? as usize;
+
+            if cmd == LC_CODE_SIGNATURE {
+                let data_offset = le32(self.data, offset + 8).ok_or_else(truncated)? as usize;
+                let data_size = le32(self.data, offset + 12).ok_or_else(truncated)? as usize;
+
+                return self
+                    .data
+                    .get(data_offset..data_offset + data_size)
+                    .map(Some)
+                    .ok_or_else(truncated);
+            }
+
+            if cmd_size < 8 {
+                return Err(truncated());
+            }
+            offset += cmd_size;
+        }
+
+        Ok(None)
+    }
+
+    pub(super) fn arch_name(&self) -> String {
+        match self.cpu_type {
+            7 => "i386".into(),
+            0x0100_0007 => "x86_64".into(),
+            12 => "arm".into(),
+            0x0100_000c => "arm64".into(),
+            0x0200_000c => "arm64_32".into(),
+            18 => "ppc".into(),
+            0x0100_0012 => "ppc64".into(),
+            other => format!("cpu type {:#x}", other),
+        }
+    }
+}
+
+/// Returns the header size and CPU type of a thin (little-endian) Mach-O binary.
+fn thin_header(data: &[u8]) -> Result<(usize, u32), OperationError> {
+    let header_size = match le32(data, 0) {
+        Some(MH_MAGIC) => 28,
+        Some(MH_MAGIC_64) => 32,
+        _ => return Err(not_macho()),
+    };
+    if data.len() < header_size {
+        return Err(truncated());
+    }
+
+    Ok((header_size, le32(data, 4).unwrap()))
+}
+
+pub(super) fn be32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn be64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from(be32(data, offset)?) << 32 | u64::from(be32(data, offset + 4)?))
+}
+
+fn le32(data: &[u8], offset: usize) -> Option<u32> {
+    be32(data, offset).map(u32::swap_bytes)
+}
+
+fn not_macho() -> OperationError {
+    OperationError::new("Not a Mach-O binary")
+}
+
+fn truncated() -> OperationError {
+    OperationError::new("Mach-O binary is truncated or malformed")
+}
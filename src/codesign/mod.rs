@@ -0,0 +1,101 @@
+//! Reads embedded code signatures from Mach-O binaries, without relying on `codesign`.
+
+mod blob;
+mod cms;
+pub(crate) mod der;
+mod macho;
+
+#[cfg(test)]
+mod tests;
+
+use std::path::{Path, PathBuf};
+
+use crate::util::OperationError;
+pub(crate) use blob::{attach_ticket, CodeSignature};
+pub(crate) use cms::{Certificate, SignedData};
+
+/// Code signature of one architecture slice.
+pub(crate) struct ArchSignature {
+    pub(crate) arch: String,
+    /// Absent if the slice is unsigned.
+    pub(crate) signature: Option<CodeSignature>,
+}
+
+/// Reads the code signature of every architecture slice in the binary at `path`.
+pub(crate) fn read_signatures<P: AsRef<Path>>(
+    path: P,
+) -> Result<Vec<ArchSignature>, OperationError> {
+    let path = path.as_ref();
+    let data = std::fs::read(path).map_err(|err| {
+        OperationError::detail(
+            &format!("Could not read {}", path.display()),
+            &err.to_string(),
+        )
+    })?;
+
+    let with_path = |err: OperationError| {
+        OperationError::detail(
+            &format!("Could not read code signature of {}", path.display()),
+            &err.to_string(),
+        )
+    };
+
+    macho::slices(&data)
+        .map_err(with_path)?
+        .iter()
+        .map(|slice| {
+            let signature = match slice.code_signature().map_err(with_path)? {
+                Some(data) => Some(CodeSignature::parse(data).map_err(with_path)?),
+                None => None,
+            };
+
+            Ok(ArchSignature {
+                arch: slice.arch_name(),
+                signature,
+            })
+        })
+        .collect()
+}
+
+/// Every Mach-O binary within a bundle, sorted: the main executable, and nested code such as
+/// frameworks, helpers, XPC services, plug-ins and loose dylibs.
+pub(crate) fn bundle_code(bundle: &Path) -> Result<Vec<PathBuf>, OperationError> {
+    let mut paths = vec![];
+    find_macho_files(&bundle.join("Contents"), &mut paths)?;
+    paths.sort();
+    Ok(paths)
+}
+
+fn find_macho_files(dir: &Path, found: &mut Vec<PathBuf>) -> Result<(), OperationError> {
+    let error = |err: std::io::Error| {
+        OperationError::detail(
+            &format!("Could not read {}", dir.display()),
+            &err.to_string(),
+        )
+    };
+
+    for entry in std::fs::read_dir(dir).map_err(error)? {
+        let entry = entry.map_err(error)?;
+        // Symlinks, e.g. Versions/Current in frameworks, point to code found elsewhere in the walk.
+        let file_type = entry.file_type().map_err(error)?;
+
+        if file_type.is_dir() {
+            find_macho_files(&entry.path(), found)?;
+        } else if file_type.is_file() && is_macho(entry.path()) {
+            found.push(entry.path());
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns true if the file at `path` is a thin or universal Mach-O binary.
+pub(crate) fn is_macho<P: AsRef<Path>>(path: P) -> bool {
+    use std::io::Read;
+
+    let mut header = [0; 8];
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .map(|_| macho::is_macho(&header))
+        .unwrap_or(false)
+}
@@ -0,0 +1,72 @@
+use super::blob::HashType;
+use super::read_signatures;
+use crate::precheck::tests::test_utils;
+use crate::util::input_path::PathType;
+
+fn signature(name: &str) -> super::CodeSignature {
+    let artifact = test_utils::artifact(PathType::AppBundle, name);
+    let executable = artifact.path.join("Contents/MacOS").join(name);
+    let mut signatures = read_signatures(executable).unwrap();
+
+    assert_eq!(signatures.len(), 1);
+    assert_eq!(signatures[0].arch, "x86_64");
+    signatures.remove(0).signature.unwrap()
+}
+
+#[test]
+fn test_correctly_signed() {
+    let signature = signature("correctly_signed");
+
+    let code_directory = signature.code_directory.unwrap();
+    assert!(code_directory.has_hardened_runtime());
+    assert_eq!(code_directory.hash_type, HashType::Sha256);
+    assert_eq!(code_directory.identifier, "com.xcnotary.test");
+    assert_eq!(code_directory.team_id.as_deref(), Some("R989A38UQ2"));
+
+    let signed_data = signature.signed_data.unwrap();
+    assert!(signed_data.has_timestamp);
+    assert!(signed_data
+        .signing_certificate()
+        .unwrap()
+        .is_developer_id_application());
+}
+
+#[test]
+fn test_entitlements() {
+    let signature = signature("has_get_task_allow");
+
+    let entitlements = signature.entitlements.unwrap();
+    assert_eq!(
+        crate::util::plist::bundle_entitlemens(&entitlements)
+            .unwrap()
+            .get_task_allow,
+        Some(true)
+    );
+    assert!(!signature.code_directory.unwrap().has_hardened_runtime());
+}
+
+#[test]
+fn test_adhoc_signed() {
+    let signature = signature("unsigned");
+
+    assert!(signature.signed_data.is_none());
+    assert!(signature.code_directory.unwrap().team_id.is_none());
+}
+
+#[test]
+fn test_not_macho() {
+    let temp_file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(temp_file.path(), "#!/bin/sh\n").unwrap();
+
+    assert!(!super::is_macho(temp_file.path()));
+    assert!(read_signatures(temp_file.path()).is_err());
+}
+
+#[test]
+fn test_fat_without_architectures() {
+    let temp_file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(temp_file.path(), [0xca, 0xfe, 0xba, 0xbe, 0, 0, 0, 0]).unwrap();
+
+    assert!(super::is_macho(temp_file.path()));
+    assert!(read_signatures(temp_file.path()).is_err());
+}
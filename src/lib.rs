@@ -0,0 +1,33 @@
+//! Helper for the Apple notarization service: checks bundles, disk images and installer
+//! packages for common code signing issues, submits them for notarization and staples the
+//! resulting tickets.
+//!
+//! ```no_run
+//! use xcnotary::notarize::NotarizeOp;
+//! use xcnotary::{path_info, precheck};
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let path = std::path::PathBuf::from("Foo.app");
+//! let (path_type, _bundle_id) = path_info(&path)?;
+//! precheck::run(&path, &path_type, false, &[])?.into_result()?;
+//!
+//! let notarization = NotarizeOp::builder(&path)
+//!     .keychain_profile("AC_NOTARY")
+//!     .build()?
+//!     .run()?;
+//! if let Some(err) = notarization.error() {
+//!     return Err(err.into());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+mod archive;
+mod codesign;
+pub mod notarize;
+pub mod output;
+pub mod precheck;
+mod util;
+
+pub use util::input_path::{identify_path_type, path_info, PathType};
+pub use util::{ErrorKind, OperationError};
@@ -0,0 +1,151 @@
+use std::path::Path;
+use std::process::Command;
+
+use super::{AppleIdCredentials, NotaryBackend, SubmissionInfo, SubmissionLog, SubmissionSummary};
+use crate::util::plist;
+use crate::util::plist::structs::NotarizationInfo;
+use crate::util::{ErrorKind, OperationError};
+
+enum AltoolArgs<'a> {
+    NotarizationInfo { request_id: &'a str },
+    NotarizeApp { path: &'a Path, bundle_id: &'a str },
+    NotarizationHistory,
+}
+
+/// Submits using `xcrun altool`, which is no longer supported by the notarization service
+/// but remains available with older Xcode versions.
+pub(super) struct Altool {
+    credentials: AppleIdCredentials,
+}
+
+impl Altool {
+    pub(super) fn new(credentials: AppleIdCredentials) -> Self {
+        Altool { credentials }
+    }
+
+    fn notarization_info(&self, request_id: &str) -> Result<NotarizationInfo, OperationError> {
+        let output = self.run_altool(AltoolArgs::NotarizationInfo { request_id })?;
+
+        let info = plist::notarization_status_response(output.as_bytes())?;
+
+        if !info
+            .success_message
+            .eq("No errors getting notarization info.")
+        {
+            return Err(OperationError::detail(
+                "Unexpected notarization message",
+                &info.success_message,
+            )
+            .with_kind(ErrorKind::MalformedResponse));
+        }
+
+        Ok(info)
+    }
+
+    fn run_altool(&self, args: AltoolArgs) -> Result<String, OperationError> {
+        let args = match args {
+            AltoolArgs::NotarizationInfo { request_id } => {
+                vec!["--notarization-info", request_id]
+            }
+            AltoolArgs::NotarizeApp { path, bundle_id } => vec![
+                "--notarize-app",
+                "--file",
+                super::path_arg(path)?,
+                "--primary-bundle-id",
+                bundle_id,
+            ],
+            AltoolArgs::NotarizationHistory => vec!["--notarization-history", "0"],
+        };
+
+        let provider_args = self
+            .credentials
+            .provider
+            .as_ref()
+            .map_or(vec![], |p| vec!["--asc-provider", p]);
+
+        let output = Command::new("/usr/bin/xcrun")
+            .args([
+                "altool",
+                "-u",
+                &self.credentials.developer_account,
+                "-p",
+                &format!("@keychain:{}", self.credentials.password_keychain_item),
+                "--output-format",
+                "xml",
+            ])
+            .args(provider_args)
+            .args(args)
+            .output()
+            .map_err(|err| super::launch_error("altool", &err))?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+
+        if output.status.success() {
+            Ok(stdout)
+        } else {
+            let combined = String::from_utf8_lossy(&output.stderr).into_owned() + &stdout;
+            Err(super::tool_error(&combined))
+        }
+    }
+}
+
+impl NotaryBackend for Altool {
+    fn submit(&self, path: &Path, bundle_id: &str) -> Result<String, OperationError> {
+        let output = self.run_altool(AltoolArgs::NotarizeApp { path, bundle_id })?;
+
+        let upload = plist::notarization_upload_response(output.as_bytes())?;
+
+        Ok(upload.details.request_uuid)
+    }
+
+    fn status(&self, request_id: &str) -> Result<SubmissionInfo, OperationError> {
+        let info = self.notarization_info(request_id)?;
+
+        Ok(SubmissionInfo {
+            status: info.details.status,
+            status_message: info.details.status_message,
+            sha256: info.details.hash,
+        })
+    }
+
+    fn log(&self, request_id: &str) -> Result<SubmissionLog, OperationError> {
+        let log_url = self
+            .notarization_info(request_id)?
+            .details
+            .logfile_url
+            .ok_or_else(|| {
+                OperationError::new("Notarization service did not provide a log")
+                    .with_kind(ErrorKind::MalformedResponse)
+            })?;
+
+        let text = reqwest::blocking::get(&log_url)
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.text())
+            .map_err(|err| {
+                OperationError::transient("Could not retrieve log file", &err.to_string())
+            })?;
+
+        Ok(SubmissionLog {
+            url: Some(log_url),
+            text,
+        })
+    }
+
+    fn history(&self) -> Result<Vec<SubmissionSummary>, OperationError> {
+        let output = self.run_altool(AltoolArgs::NotarizationHistory)?;
+
+        let history = plist::notarization_history_response(output.as_bytes())?;
+
+        Ok(history
+            .details
+            .items
+            .into_iter()
+            .map(|item| SubmissionSummary {
+                request_id: item.request_uuid,
+                date: format!("{:?}", item.date),
+                name: None,
+                status: item.status,
+            })
+            .collect())
+    }
+}
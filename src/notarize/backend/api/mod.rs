@@ -0,0 +1,186 @@
+pub(crate) mod jwt;
+mod s3;
+
+#[cfg(test)]
+mod tests;
+
+use reqwest::blocking::{Client, RequestBuilder};
+use reqwest::StatusCode;
+use std::path::Path;
+
+use super::{NotaryBackend, SubmissionInfo, SubmissionLog, SubmissionSummary};
+use crate::notarize::backoff::with_retry;
+use crate::util::hash::sha256_file;
+use crate::util::json;
+use crate::util::{ErrorKind, OperationError};
+use jwt::Authorization;
+
+static NOTARY_API_URL: &str = "https://appstoreconnect.apple.com/notary/v2";
+
+/// Submits directly to Apple's Notary REST API, without relying on Xcode tools.
+pub(super) struct NotaryApi {
+    base_url: String,
+    s3_endpoint: Option<String>,
+    authorization: Authorization,
+    client: Client,
+}
+
+impl NotaryApi {
+    pub(super) fn new(authorization: Authorization) -> Result<Self, OperationError> {
+        Self::with_endpoints(authorization, NOTARY_API_URL.into(), None)
+    }
+
+    /// Used to direct requests to a stand-in for the service, e.g. when testing.
+    fn with_endpoints(
+        authorization: Authorization,
+        base_url: String,
+        s3_endpoint: Option<String>,
+    ) -> Result<Self, OperationError> {
+        // Uploads may take well over the default 30 second timeout.
+        let client = Client::builder().timeout(None).build().map_err(|err| {
+            OperationError::detail("Could not initialize HTTP client", &err.to_string())
+        })?;
+
+        Ok(NotaryApi {
+            base_url,
+            s3_endpoint,
+            authorization,
+            client,
+        })
+    }
+
+    fn get(&self, path: &str) -> Result<Vec<u8>, OperationError> {
+        self.send(self.client.get(&format!("{}{}", self.base_url, path)))
+    }
+
+    fn send(&self, request: RequestBuilder) -> Result<Vec<u8>, OperationError> {
+        let response = request
+            .bearer_auth(self.authorization.bearer_token())
+            .send()
+            .map_err(|err| {
+                OperationError::transient("Could not reach notarization service", &err.to_string())
+            })?;
+
+        let status = response.status();
+        let bytes = response.bytes().map_err(|err| {
+            OperationError::transient("Could not read service response", &err.to_string())
+        })?;
+
+        if !status.is_success() {
+            let heading =
+                "Notarization service returned an error. Please check the output and try again";
+            let message = format!("HTTP {}\n{}", status, String::from_utf8_lossy(&bytes));
+            return Err(OperationError::detail(heading, &message).with_kind(status_kind(status)));
+        }
+
+        Ok(bytes.to_vec())
+    }
+}
+
+/// Cause of a request failing with `status`. Server errors and rate limiting may succeed when
+/// retried. Other client errors, e.g. an unknown submission ID, say nothing about the input,
+/// which is only rejected by an `Invalid` status.
+fn status_kind(status: StatusCode) -> ErrorKind {
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => ErrorKind::Credentials,
+        StatusCode::TOO_MANY_REQUESTS => ErrorKind::Network,
+        status if status.is_server_error() => ErrorKind::Network,
+        _ => ErrorKind::Other,
+    }
+}
+
+impl NotaryBackend for NotaryApi {
+    fn submit(&self, path: &Path, _bundle_id: &str) -> Result<String, OperationError> {
+        let sha256 = sha256_file(path).map_err(|err| {
+            OperationError::detail(
+                &format!("Could not read {}", path.display()),
+                &err.to_string(),
+            )
+        })?;
+
+        let name = path.file_name().ok_or_else(|| {
+            OperationError::new(&format!("Expected a file name: {}", path.display()))
+        })?;
+        let body = serde_json::json!({
+            "submissionName": name.to_string_lossy(),
+            "sha256": sha256,
+        });
+
+        let output = self.send(
+            self.client
+                .post(&format!("{}/submissions", self.base_url))
+                .json(&body),
+        )?;
+        let submission = json::notary_api_new_submission_response(&output)?.data;
+
+        // Only the upload is retried, since repeating the request above would create another
+        // submission.
+        with_retry(|| {
+            s3::upload(
+                &self.client,
+                self.s3_endpoint.as_deref(),
+                &submission.attributes,
+                path,
+                &sha256,
+            )
+        })?;
+
+        Ok(submission.id)
+    }
+
+    fn status(&self, request_id: &str) -> Result<SubmissionInfo, OperationError> {
+        let output = self.get(&format!("/submissions/{}", request_id))?;
+
+        let attributes = json::notary_api_submission_response(&output)?
+            .data
+            .attributes;
+
+        Ok(SubmissionInfo {
+            status: attributes.status,
+            status_message: None,
+            sha256: None,
+        })
+    }
+
+    fn log(&self, request_id: &str) -> Result<SubmissionLog, OperationError> {
+        let output = self.get(&format!("/submissions/{}/logs", request_id))?;
+
+        let log_url = json::notary_api_submission_log_response(&output)?
+            .data
+            .attributes
+            .developer_log_url;
+
+        // The log URL is pre-signed and must not receive the API token.
+        let text = self
+            .client
+            .get(&log_url)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.text())
+            .map_err(|err| {
+                let kind = err.status().map_or(ErrorKind::Network, status_kind);
+                OperationError::detail("Could not retrieve log file", &err.to_string())
+                    .with_kind(kind)
+            })?;
+
+        Ok(SubmissionLog {
+            url: Some(log_url),
+            text,
+        })
+    }
+
+    fn history(&self) -> Result<Vec<SubmissionSummary>, OperationError> {
+        let output = self.get("/submissions")?;
+
+        Ok(json::notary_api_submission_list_response(&output)?
+            .data
+            .into_iter()
+            .map(|item| SubmissionSummary {
+                request_id: item.id,
+                date: item.attributes.created_date,
+                name: item.attributes.name,
+                status: item.attributes.status,
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,288 @@
+mod altool;
+mod api;
+mod notarytool;
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::util::plist::structs::NotarizationStatus;
+use crate::util::{ErrorKind, OperationError};
+use api::jwt::{ApiKey, Authorization};
+
+/// Current state of a submission, independent of the tool used to query it.
+#[derive(Debug)]
+pub struct SubmissionInfo {
+    pub status: NotarizationStatus,
+    pub status_message: Option<String>,
+    /// Hex-encoded SHA-256 digest of the uploaded file, if reported with the status rather than
+    /// only in the log.
+    pub sha256: Option<String>,
+}
+
+/// Service log for a completed submission.
+#[derive(Debug)]
+pub struct SubmissionLog {
+    /// Location the log was downloaded from, if the service provides one.
+    pub url: Option<String>,
+    pub text: String,
+}
+
+/// Entry in the list of past submissions for the developer account.
+#[derive(Debug)]
+pub struct SubmissionSummary {
+    pub request_id: String,
+    pub date: String,
+    pub name: Option<String>,
+    pub status: NotarizationStatus,
+}
+
+/// Shared between threads, so that asynchronous operations can call it on the blocking pool.
+pub(crate) trait NotaryBackend: Send + Sync {
+    /// Uploads the file to the notarization service, returning the request ID.
+    fn submit(&self, path: &Path, bundle_id: &str) -> Result<String, OperationError>;
+
+    /// Retrieves current status of a submission.
+    fn status(&self, request_id: &str) -> Result<SubmissionInfo, OperationError>;
+
+    /// Retrieves the service log for a completed submission.
+    fn log(&self, request_id: &str) -> Result<SubmissionLog, OperationError>;
+
+    /// Lists recent submissions made with the developer account.
+    fn history(&self) -> Result<Vec<SubmissionSummary>, OperationError>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    Altool,
+    Notarytool,
+    Api,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "altool" => Ok(Backend::Altool),
+            "notarytool" => Ok(Backend::Notarytool),
+            "api" => Ok(Backend::Api),
+            _ => Err(format!("Unknown backend: {}", s)),
+        }
+    }
+}
+
+/// Tool to communicate with the notarization service, and credentials to authenticate with:
+/// either an Apple ID, or an App Store Connect API key or token.
+#[derive(Debug, Clone, Default)]
+pub struct ServiceOptions {
+    /// `Backend::Notarytool` if `None`.
+    pub backend: Option<Backend>,
+    pub developer_account: Option<String>,
+    /// Keychain item containing the Apple ID password, used with altool.
+    pub password_keychain_item: Option<String>,
+    /// Keychain profile created with `xcrun notarytool store-credentials`, recording the Apple
+    /// ID, team and password used with notarytool.
+    pub keychain_profile: Option<String>,
+    /// Team to use with altool, if the Apple ID is associated with more than one.
+    pub provider: Option<String>,
+    /// Previously generated token for `Backend::Api`.
+    pub api_token: Option<String>,
+    /// Path to the App Store Connect API private key, `AuthKey_<key id>.p8`.
+    pub api_key: Option<PathBuf>,
+    pub api_key_id: Option<String>,
+    pub api_issuer: Option<String>,
+}
+
+/// Apple ID used with altool.
+pub(crate) struct AppleIdCredentials {
+    developer_account: String,
+    password_keychain_item: String,
+    provider: Option<String>,
+}
+
+/// App Store Connect API key, used in place of an Apple ID password.
+pub(crate) struct ApiKeyCredentials {
+    key_path: PathBuf,
+    key_id: String,
+    issuer_id: String,
+}
+
+/// Credentials accepted by notarytool.
+pub(crate) enum Credentials {
+    /// Name of a keychain profile.
+    KeychainProfile(String),
+    ApiKey(ApiKeyCredentials),
+}
+
+/// Error for a failed invocation of an Xcode command line tool with the given output, classified
+/// by the cause the tool reports. Failures without a recognized cause are not assumed to be a
+/// rejection, which is only reported once the submission's status is invalid.
+fn tool_error(output: &str) -> OperationError {
+    const CREDENTIALS_MARKERS: &[&str] = &[
+        "Unable to authenticate",
+        "Invalid credentials",
+        "HTTP status code: 401",
+        "HTTP status code: 403",
+    ];
+    const TRANSIENT_MARKERS: &[&str] = &[
+        "NSURLErrorDomain",
+        "The request timed out",
+        "network connection was lost",
+        "Internet connection appears to be offline",
+        "Could not connect to the server",
+        "HTTP status code: 5",
+        "Service Unavailable",
+    ];
+
+    let heading = "Notarization service returned an error. Please check the output and try again";
+    let matches = |markers: &[&str]| markers.iter().any(|marker| output.contains(marker));
+    let kind = if matches(CREDENTIALS_MARKERS) {
+        ErrorKind::Credentials
+    } else if matches(TRANSIENT_MARKERS) {
+        ErrorKind::Network
+    } else {
+        ErrorKind::Other
+    };
+
+    OperationError::detail(heading, output).with_kind(kind)
+}
+
+/// Error for an Xcode command line tool that could not be started, e.g. without Xcode installed.
+fn launch_error(tool: &str, err: &std::io::Error) -> OperationError {
+    OperationError::detail(&format!("Could not run xcrun {}", tool), &err.to_string())
+}
+
+/// `path` as a command line argument for tools that require UTF-8 paths.
+fn path_arg(path: &Path) -> Result<&str, OperationError> {
+    path.to_str()
+        .ok_or_else(|| OperationError::new(&format!("Path is not valid UTF-8: {}", path.display())))
+}
+
+pub(crate) fn create(options: ServiceOptions) -> Result<Box<dyn NotaryBackend>, OperationError> {
+    let ServiceOptions {
+        developer_account,
+        password_keychain_item,
+        keychain_profile,
+        provider,
+        backend,
+        api_token,
+        api_key,
+        api_key_id,
+        api_issuer,
+    } = options;
+
+    let api_key = match (api_key, api_key_id, api_issuer) {
+        (Some(key_path), Some(key_id), Some(issuer_id)) => Some(ApiKeyCredentials {
+            key_path,
+            key_id,
+            issuer_id,
+        }),
+        (Some(_), _, _) => {
+            return Err(OperationError::new(
+                "--api-key-id and --api-issuer are required with --api-key",
+            )
+            .with_kind(ErrorKind::Credentials))
+        }
+        (None, _, _) => None,
+    };
+
+    let apple_id = || {
+        match (developer_account, password_keychain_item) {
+        (Some(developer_account), Some(password_keychain_item)) => Ok(AppleIdCredentials {
+            developer_account,
+            password_keychain_item,
+            provider,
+        }),
+        _ => Err(OperationError::new(
+            "--developer-account and --developer-password-keychain-item are required, unless an API key is specified",
+        )
+        .with_kind(ErrorKind::Credentials)),
+    }
+    };
+
+    Ok(match backend.unwrap_or(Backend::Notarytool) {
+        Backend::Api => {
+            let authorization = match (api_token, api_key) {
+                (Some(token), _) => Authorization::Token(token),
+                (None, Some(key)) => Authorization::with_key(ApiKey::from_file(
+                    key.key_path,
+                    key.key_id,
+                    key.issuer_id,
+                )?),
+                (None, None) => {
+                    return Err(OperationError::new(
+                        "--api-key or --api-token is required when using the api backend",
+                    )
+                    .with_kind(ErrorKind::Credentials))
+                }
+            };
+
+            Box::new(api::NotaryApi::new(authorization)?)
+        }
+        Backend::Notarytool => {
+            let credentials = match (api_key, keychain_profile) {
+                (Some(key), _) => Credentials::ApiKey(key),
+                (None, Some(profile)) => Credentials::KeychainProfile(profile),
+                (None, None) => {
+                    return Err(OperationError::new(
+                        "--keychain-profile is required with notarytool, unless an API key is specified. To authenticate with --developer-account and --developer-password-keychain-item, specify --backend altool",
+                    )
+                    .with_kind(ErrorKind::Credentials))
+                }
+            };
+
+            Box::new(notarytool::Notarytool::new(credentials))
+        }
+        Backend::Altool => Box::new(altool::Altool::new(apple_id()?)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Backend, ServiceOptions};
+    use crate::util::ErrorKind;
+
+    #[test]
+    fn test_create_notarytool() {
+        let options = ServiceOptions {
+            keychain_profile: Some("AC_NOTARY".into()),
+            ..ServiceOptions::default()
+        };
+        assert!(super::create(options).is_ok());
+
+        // An Apple ID is only accepted by altool.
+        let options = ServiceOptions {
+            backend: Some(Backend::Notarytool),
+            developer_account: Some("dev@example.com".into()),
+            password_keychain_item: Some("AC_PASSWORD".into()),
+            ..ServiceOptions::default()
+        };
+        let err = super::create(options.clone()).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::Credentials);
+        assert!(err.to_string().contains("--keychain-profile"));
+
+        let options = ServiceOptions {
+            backend: Some(Backend::Altool),
+            ..options
+        };
+        assert!(super::create(options).is_ok());
+    }
+
+    #[test]
+    fn test_tool_error_kind() {
+        let kind = |output| super::tool_error(output).kind();
+
+        assert_eq!(
+            kind("Error: HTTP status code: 401. Invalid credentials."),
+            ErrorKind::Credentials
+        );
+        assert_eq!(
+            kind("Error Domain=NSURLErrorDomain Code=-1001 \"The request timed out.\""),
+            ErrorKind::Network
+        );
+        assert_eq!(
+            kind("Error: No Xcode or CLT version detected!"),
+            ErrorKind::Other
+        );
+    }
+}
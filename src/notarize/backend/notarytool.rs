@@ -0,0 +1,114 @@
+use std::path::Path;
+use std::process::Command;
+
+use super::{Credentials, NotaryBackend, SubmissionInfo, SubmissionLog, SubmissionSummary};
+use crate::util::json;
+use crate::util::OperationError;
+
+enum NotarytoolArgs<'a> {
+    Submit { path: &'a Path },
+    Info { request_id: &'a str },
+    Log { request_id: &'a str },
+    History,
+}
+
+/// Submits using `xcrun notarytool`, available with Xcode 13 and later.
+pub(super) struct Notarytool {
+    credentials: Credentials,
+}
+
+impl Notarytool {
+    pub(super) fn new(credentials: Credentials) -> Self {
+        Notarytool { credentials }
+    }
+
+    /// Apple ID credentials are read by notarytool from the keychain profile, so that the
+    /// password never appears in the arguments of a process.
+    fn credential_args(&self) -> Vec<String> {
+        match &self.credentials {
+            Credentials::KeychainProfile(profile) => {
+                vec!["--keychain-profile".into(), profile.clone()]
+            }
+            Credentials::ApiKey(key) => vec![
+                "--key".into(),
+                key.key_path.to_string_lossy().into(),
+                "--key-id".into(),
+                key.key_id.clone(),
+                "--issuer".into(),
+                key.issuer_id.clone(),
+            ],
+        }
+    }
+
+    fn run_notarytool(&self, args: NotarytoolArgs) -> Result<String, OperationError> {
+        let args = match args {
+            NotarytoolArgs::Submit { path } => {
+                vec!["submit", super::path_arg(path)?, "--output-format", "json"]
+            }
+            NotarytoolArgs::Info { request_id } => {
+                vec!["info", request_id, "--output-format", "json"]
+            }
+            // Without an output path, the log itself is written to stdout.
+            NotarytoolArgs::Log { request_id } => vec!["log", request_id],
+            NotarytoolArgs::History => vec!["history", "--output-format", "json"],
+        };
+
+        let output = Command::new("/usr/bin/xcrun")
+            .arg("notarytool")
+            .args(args)
+            .args(self.credential_args())
+            .output()
+            .map_err(|err| super::launch_error("notarytool", &err))?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+
+        if output.status.success() {
+            Ok(stdout)
+        } else {
+            let combined = String::from_utf8_lossy(&output.stderr).into_owned() + &stdout;
+            Err(super::tool_error(&combined))
+        }
+    }
+}
+
+impl NotaryBackend for Notarytool {
+    fn submit(&self, path: &Path, _bundle_id: &str) -> Result<String, OperationError> {
+        let output = self.run_notarytool(NotarytoolArgs::Submit { path })?;
+
+        Ok(json::notarytool_submit_response(output.as_bytes())?.id)
+    }
+
+    fn status(&self, request_id: &str) -> Result<SubmissionInfo, OperationError> {
+        let output = self.run_notarytool(NotarytoolArgs::Info { request_id })?;
+
+        let info = json::notarytool_info_response(output.as_bytes())?;
+
+        Ok(SubmissionInfo {
+            status: info.status,
+            status_message: info.message,
+            sha256: None,
+        })
+    }
+
+    fn log(&self, request_id: &str) -> Result<SubmissionLog, OperationError> {
+        Ok(SubmissionLog {
+            url: None,
+            text: self.run_notarytool(NotarytoolArgs::Log { request_id })?,
+        })
+    }
+
+    fn history(&self) -> Result<Vec<SubmissionSummary>, OperationError> {
+        let output = self.run_notarytool(NotarytoolArgs::History)?;
+
+        Ok(json::notarytool_history_response(output.as_bytes())?
+            .history
+            .into_iter()
+            .map(|item| SubmissionSummary {
+                request_id: item.id,
+                date: item.created_date,
+                name: item.name,
+                status: item.status,
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,171 @@
+//! Timing of status polling and of retries after transient failures.
+
+use std::future::Future;
+use std::time::Duration;
+
+use rand::Rng;
+
+use super::CancellationToken;
+use crate::util::OperationError;
+
+/// Attempts made before a transient failure is reported.
+const MAX_ATTEMPTS: u32 = 5;
+const RETRY_INITIAL_INTERVAL: Duration = Duration::from_secs(2);
+const RETRY_MAX_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How often, and for how long, to check the status of a submission.
+#[derive(Debug, Clone, Copy)]
+pub struct PollSettings {
+    pub initial_interval: Duration,
+    pub max_interval: Duration,
+    /// Stop waiting after this long. Waits indefinitely if `None`.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for PollSettings {
+    fn default() -> Self {
+        PollSettings {
+            initial_interval: Duration::from_secs(5),
+            max_interval: Duration::from_secs(60),
+            timeout: None,
+        }
+    }
+}
+
+/// Exponentially increasing delay, capped at a maximum, with random jitter so that many
+/// clients started together do not poll in lockstep.
+pub(super) struct Backoff {
+    interval: Duration,
+    max_interval: Duration,
+}
+
+impl Backoff {
+    pub(super) fn new(initial_interval: Duration, max_interval: Duration) -> Self {
+        Backoff {
+            interval: initial_interval,
+            max_interval,
+        }
+    }
+
+    /// Delay before the next attempt, between half and all of the current interval. The
+    /// interval doubles after each call, up to the maximum.
+    pub(super) fn next_delay(&mut self) -> Duration {
+        let interval = self.interval;
+        self.interval = interval
+            .checked_mul(2)
+            .map_or(self.max_interval, |doubled| doubled.min(self.max_interval));
+
+        let fraction: f64 = rand::thread_rng().gen_range(0.0..=1.0);
+        interval / 2 + interval.mul_f64(fraction / 2.0)
+    }
+}
+
+/// Calls `operation` until it succeeds, fails with an error that is not transient, or has
+/// been attempted `MAX_ATTEMPTS` times.
+pub(crate) fn with_retry<T>(
+    operation: impl FnMut() -> Result<T, OperationError>,
+) -> Result<T, OperationError> {
+    retry(
+        Backoff::new(RETRY_INITIAL_INTERVAL, RETRY_MAX_INTERVAL),
+        operation,
+    )
+}
+
+/// Asynchronous `with_retry`, waiting between attempts without blocking a thread. Stops with
+/// `ErrorKind::Cancelled` once `cancel` is cancelled.
+pub(crate) async fn with_retry_async<T, F, Fut>(
+    cancel: &CancellationToken,
+    mut operation: F,
+) -> Result<T, OperationError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, OperationError>>,
+{
+    let mut backoff = Backoff::new(RETRY_INITIAL_INTERVAL, RETRY_MAX_INTERVAL);
+    let mut attempt = 1;
+
+    loop {
+        match cancel.guard(operation()).await? {
+            Err(err) if err.is_transient() && attempt < MAX_ATTEMPTS => {
+                attempt += 1;
+                cancel
+                    .guard(tokio::time::sleep(backoff.next_delay()))
+                    .await?;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn retry<T>(
+    mut backoff: Backoff,
+    mut operation: impl FnMut() -> Result<T, OperationError>,
+) -> Result<T, OperationError> {
+    let mut attempt = 1;
+
+    loop {
+        match operation() {
+            Err(err) if err.is_transient() && attempt < MAX_ATTEMPTS => {
+                attempt += 1;
+                std::thread::sleep(backoff.next_delay());
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{retry, Backoff, MAX_ATTEMPTS};
+    use crate::util::OperationError;
+
+    #[test]
+    fn test_backoff() {
+        let mut backoff = Backoff::new(Duration::from_secs(4), Duration::from_secs(20));
+
+        for interval in &[4, 8, 16, 20, 20] {
+            let delay = backoff.next_delay();
+            let interval = Duration::from_secs(*interval);
+            assert!(delay >= interval / 2 && delay <= interval);
+        }
+
+        // Doubling an interval near the largest duration stops at the maximum.
+        let mut backoff = Backoff::new(Duration::MAX / 2 + Duration::from_secs(1), Duration::MAX);
+        backoff.next_delay();
+        assert_eq!(backoff.interval, Duration::MAX);
+    }
+
+    #[test]
+    fn test_retry() {
+        let backoff = || Backoff::new(Duration::from_millis(1), Duration::from_millis(1));
+
+        let mut attempts = 0;
+        let result = retry(backoff(), || {
+            attempts += 1;
+            if attempts < 3 {
+                Err(OperationError::transient("Unavailable", "HTTP 503"))
+            } else {
+                Ok(attempts)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        let mut attempts = 0;
+        let result: Result<(), _> = retry(backoff(), || {
+            attempts += 1;
+            Err(OperationError::transient("Unavailable", "HTTP 503"))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, MAX_ATTEMPTS);
+
+        let mut attempts = 0;
+        let result: Result<(), _> = retry(backoff(), || {
+            attempts += 1;
+            Err(OperationError::detail("Rejected", "HTTP 403"))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+}
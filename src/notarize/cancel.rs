@@ -0,0 +1,68 @@
+//! Cancellation of asynchronous operations.
+
+use futures_util::future::{self, Either};
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+use crate::util::OperationError;
+
+/// Cancels the asynchronous operations it is passed to. Clones share the same state, so a
+/// supervising task can keep a clone and cancel an operation running elsewhere.
+#[derive(Debug, Clone)]
+pub struct CancellationToken {
+    sender: Arc<watch::Sender<bool>>,
+    receiver: watch::Receiver<bool>,
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        let (sender, receiver) = watch::channel(false);
+        CancellationToken {
+            sender: Arc::new(sender),
+            receiver,
+        }
+    }
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels every operation using this token, and any started with it later.
+    pub fn cancel(&self) {
+        // Only fails without receivers, but this token holds one.
+        let _ = self.sender.send(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    /// Completes once the token is cancelled.
+    pub async fn cancelled(&self) {
+        let mut receiver = self.receiver.clone();
+        while !*receiver.borrow() {
+            if receiver.changed().await.is_err() {
+                // Unreachable while this token holds the sender.
+                future::pending::<()>().await;
+            }
+        }
+    }
+
+    /// Runs `operation` to completion, unless the token is cancelled first. The operation is
+    /// then dropped, and `ErrorKind::Cancelled` returned.
+    pub(crate) async fn guard<F: Future>(&self, operation: F) -> Result<F::Output, OperationError> {
+        if self.is_cancelled() {
+            return Err(OperationError::cancelled());
+        }
+
+        let cancelled = self.cancelled();
+        futures_util::pin_mut!(operation, cancelled);
+        match future::select(operation, cancelled).await {
+            Either::Left((output, _)) => Ok(output),
+            Either::Right(_) => Err(OperationError::cancelled()),
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! Commands run once a submission completes, e.g. to publish the notarized input.
+
+use std::error::Error;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+use std::time::Instant;
+use tempfile::NamedTempFile;
+
+use super::notify::{Event, Notification, Webhooks};
+use crate::output;
+use crate::util::plist::structs::NotarizationStatus;
+use crate::util::OperationError;
+
+/// Shell commands run when a submission completes, depending on the outcome, and webhooks
+/// notified as it progresses.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Hooks {
+    pub(crate) on_success: Option<String>,
+    pub(crate) on_failure: Option<String>,
+    pub(crate) webhooks: Webhooks,
+}
+
+/// How far a submission progressed, described to hooks through environment variables and to
+/// webhooks.
+#[derive(Debug, Clone)]
+pub(super) struct Completion {
+    pub(super) started: Instant,
+    pub(super) request_id: Option<String>,
+    pub(super) status: Option<NotarizationStatus>,
+    /// Service log, if it was retrieved.
+    pub(super) log: Option<String>,
+}
+
+impl Completion {
+    pub(super) fn new() -> Self {
+        Completion {
+            started: Instant::now(),
+            request_id: None,
+            status: None,
+            log: None,
+        }
+    }
+}
+
+impl Hooks {
+    /// Notifies webhooks that the input was uploaded.
+    pub(super) fn submitted(&self, completion: &Completion, bundle_id: &str) {
+        self.webhooks.notify(&Notification::new(
+            Event::Submitted,
+            completion,
+            bundle_id,
+            None,
+        ));
+    }
+
+    /// Notifies webhooks of the outcome, `error` if the operation failed, then runs the
+    /// `on_success` or `on_failure` command, if any. Fails if the command cannot be started or
+    /// exits unsuccessfully.
+    pub(super) fn run(
+        &self,
+        error: Option<&dyn Error>,
+        completion: &Completion,
+        bundle_id: &str,
+        input_path: &Path,
+    ) -> Result<(), OperationError> {
+        let succeeded = error.is_none();
+        let event = if succeeded {
+            Event::Succeeded
+        } else {
+            Event::Failed
+        };
+        self.webhooks
+            .notify(&Notification::new(event, completion, bundle_id, error));
+
+        let (option, command) = if succeeded {
+            ("--on-success", &self.on_success)
+        } else {
+            ("--on-failure", &self.on_failure)
+        };
+        let command = match command {
+            Some(command) => command,
+            None => return Ok(()),
+        };
+
+        // Removed once the hook exits.
+        let log_file = match &completion.log {
+            Some(log) => Some(write_log(log)?),
+            None => None,
+        };
+
+        let mut hook = Command::new("sh");
+        hook.arg("-c")
+            .arg(command)
+            .env("XCNOTARY_BUNDLE_ID", bundle_id)
+            .env("XCNOTARY_ARTIFACT_PATH", input_path);
+        set_env(
+            &mut hook,
+            "XCNOTARY_REQUEST_ID",
+            completion.request_id.as_deref(),
+        );
+        set_env(
+            &mut hook,
+            "XCNOTARY_STATUS",
+            completion.status.map(NotarizationStatus::id),
+        );
+        set_env(
+            &mut hook,
+            "XCNOTARY_LOG_PATH",
+            log_file.as_ref().and_then(|file| file.path().to_str()),
+        );
+
+        if !output::is_text() {
+            // Keep stdout to the JSON document, or to the embedding program.
+            hook.stdout(std::io::stderr());
+        }
+
+        let status = hook.status().map_err(|err| {
+            OperationError::detail(&format!("Could not run {} hook", option), &err.to_string())
+        })?;
+        if !status.success() {
+            return Err(OperationError::new(&format!(
+                "{} hook failed ({})",
+                option, status
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Sets `name` to `value`, or clears it if there is no value rather than leaving it inherited.
+fn set_env(command: &mut Command, name: &str, value: Option<&str>) {
+    match value {
+        Some(value) => command.env(name, value),
+        None => command.env_remove(name),
+    };
+}
+
+fn write_log(log: &str) -> Result<NamedTempFile, OperationError> {
+    tempfile::Builder::new()
+        .prefix("xcnotary-log")
+        .suffix(".json")
+        .tempfile()
+        .and_then(|mut file| file.write_all(log.as_bytes()).map(|_| file))
+        .map_err(|err| OperationError::detail("Could not save log for hook", &err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{Completion, Hooks};
+    use crate::util::plist::structs::NotarizationStatus;
+    use crate::util::OperationError;
+
+    static PRINT_ENV: &str = r#"printf '%s\n' "$XCNOTARY_REQUEST_ID" "$XCNOTARY_BUNDLE_ID" "$XCNOTARY_STATUS" "$XCNOTARY_ARTIFACT_PATH" "$(cat "$XCNOTARY_LOG_PATH")" >"#;
+
+    #[test]
+    fn test_hook_environment() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let out = temp_dir.path().join("env");
+
+        let hooks = Hooks {
+            on_success: Some(format!("{} '{}'", PRINT_ENV, out.display())),
+            on_failure: Some("exit 1".into()),
+            ..Hooks::default()
+        };
+        let mut completion = Completion::new();
+        completion.request_id = Some("c0dec0de".into());
+        completion.status = Some(NotarizationStatus::Success);
+        completion.log = Some("{}".into());
+
+        hooks
+            .run(None, &completion, "com.example.app", Path::new("Foo.app"))
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&out).unwrap(),
+            "c0dec0de\ncom.example.app\nsuccess\nFoo.app\n{}\n"
+        );
+
+        let error = OperationError::new("Notarization failed");
+        assert!(hooks
+            .run(
+                Some(&error),
+                &completion,
+                "com.example.app",
+                Path::new("Foo.app")
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_no_hook() {
+        let error = OperationError::new("Notarization failed");
+        Hooks::default()
+            .run(Some(&error), &Completion::new(), "", Path::new(""))
+            .unwrap();
+    }
+}
@@ -0,0 +1,327 @@
+//! Local record of submissions, used to resume waiting after an interruption and to list past
+//! submissions without contacting the service.
+
+use chrono::{SecondsFormat, Utc};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::util::plist::structs::NotarizationStatus;
+use crate::util::OperationError;
+
+const JOURNAL_FILE_NAME: &str = "journal.jsonl";
+const LOG_DIR_NAME: &str = "logs";
+
+/// Journal kept in a state directory. Each line of the journal file is a snapshot of an
+/// entry, appended when the submission is made and again when it completes, so that an
+/// interrupted write loses at most the latest snapshot.
+#[derive(Debug, Clone)]
+pub struct Journal {
+    dir: PathBuf,
+}
+
+/// A submission recorded in the journal. Field names are stored in the journal file and must
+/// not change.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+    pub request_id: String,
+    pub artifact_path: PathBuf,
+    /// Hex-encoded SHA-256 digest of the uploaded file, absent if the submission was not made
+    /// with this journal.
+    pub sha256: Option<String>,
+    pub bundle_id: String,
+    /// RFC 3339 timestamps.
+    pub submitted_at: Option<String>,
+    pub completed_at: Option<String>,
+    /// One of "success" or "invalid" once the submission completes.
+    pub status: Option<String>,
+    /// Copy of the service log, once the submission completes.
+    pub log_path: Option<PathBuf>,
+}
+
+impl JournalEntry {
+    /// True until the submission is known to have completed, e.g. if waiting timed out or the
+    /// process was interrupted.
+    pub fn is_unfinished(&self) -> bool {
+        self.completed_at.is_none()
+    }
+}
+
+impl Journal {
+    /// Journal in `dir`, created when the first submission is recorded.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Journal { dir: dir.into() }
+    }
+
+    /// `~/.xcnotary`, if the home directory is known.
+    pub fn default_dir() -> Option<PathBuf> {
+        std::env::var_os("HOME")
+            .filter(|home| !home.is_empty())
+            .map(|home| Path::new(&home).join(".xcnotary"))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Every recorded submission, oldest first.
+    pub fn entries(&self) -> Result<Vec<JournalEntry>, OperationError> {
+        let path = self.dir.join(JOURNAL_FILE_NAME);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(self.error(&err)),
+        };
+
+        // Later snapshots replace earlier ones, keeping the position of the first. A line cut
+        // short by an interruption is skipped, keeping the previous snapshot.
+        let mut entries = IndexMap::new();
+        for entry in text
+            .lines()
+            .filter_map(|line| serde_json::from_str::<JournalEntry>(line).ok())
+        {
+            entries.insert(entry.request_id.clone(), entry);
+        }
+
+        Ok(entries.into_values().collect())
+    }
+
+    /// Submissions not known to have completed, oldest first.
+    pub fn unfinished(&self) -> Result<Vec<JournalEntry>, OperationError> {
+        let mut entries = self.entries()?;
+        entries.retain(JournalEntry::is_unfinished);
+        Ok(entries)
+    }
+
+    /// Most recent successful submission of a file with digest `sha256`, if any.
+    pub(crate) fn find_notarized(
+        &self,
+        sha256: &str,
+    ) -> Result<Option<JournalEntry>, OperationError> {
+        Ok(self.entries()?.into_iter().rev().find(|entry| {
+            entry.sha256.as_deref() == Some(sha256)
+                && entry.status.as_deref() == Some(NotarizationStatus::Success.id())
+        }))
+    }
+
+    /// Records that the file with digest `sha256` was uploaded as `request_id`.
+    pub(crate) fn record_submitted(
+        &self,
+        request_id: &str,
+        artifact_path: &Path,
+        sha256: Option<&str>,
+        bundle_id: &str,
+    ) -> Result<(), OperationError> {
+        self.append(&JournalEntry {
+            request_id: request_id.into(),
+            artifact_path: absolute(artifact_path),
+            sha256: sha256.map(String::from),
+            bundle_id: bundle_id.into(),
+            submitted_at: Some(now()),
+            completed_at: None,
+            status: None,
+            log_path: None,
+        })
+    }
+
+    /// Records the outcome of `request_id`, saving a copy of the service log. Submissions made
+    /// without the journal are added to it.
+    pub(crate) fn record_completed(
+        &self,
+        request_id: &str,
+        artifact_path: &Path,
+        bundle_id: &str,
+        status: NotarizationStatus,
+        log: &str,
+    ) -> Result<(), OperationError> {
+        // The ID names the log file, so must not contain path separators or `..`.
+        if request_id.is_empty()
+            || !request_id
+                .chars()
+                .all(|c| c.is_ascii_hexdigit() || c == '-')
+        {
+            return Err(OperationError::new(&format!(
+                "Invalid request UUID: {}",
+                request_id
+            )));
+        }
+
+        let log_dir = self.dir.join(LOG_DIR_NAME);
+        let log_path = log_dir.join(format!("{}.json", request_id));
+        std::fs::create_dir_all(&log_dir)
+            .and_then(|_| std::fs::write(&log_path, log))
+            .map_err(|err| self.error(&err))?;
+
+        let mut entry = self
+            .entries()?
+            .into_iter()
+            .find(|entry| entry.request_id == request_id)
+            .unwrap_or_else(|| JournalEntry {
+                request_id: request_id.into(),
+                artifact_path: absolute(artifact_path),
+                sha256: None,
+                bundle_id: bundle_id.into(),
+                submitted_at: None,
+                completed_at: None,
+                status: None,
+                log_path: None,
+            });
+        entry.completed_at = Some(now());
+        entry.status = Some(status.id().into());
+        entry.log_path = Some(log_path);
+
+        self.append(&entry)
+    }
+
+    fn append(&self, entry: &JournalEntry) -> Result<(), OperationError> {
+        let mut line = serde_json::to_string(entry).unwrap();
+        line.push('\n');
+
+        std::fs::create_dir_all(&self.dir)
+            .and_then(|_| {
+                OpenOptions::new()
+                    .create(true)
+                    .read(true)
+                    .append(true)
+                    .open(self.dir.join(JOURNAL_FILE_NAME))
+            })
+            .and_then(|mut file| {
+                // Terminate a line cut short by an interrupted write, so that it does not swallow
+                // this one.
+                if !ends_with_newline(&mut file)? {
+                    line.insert(0, '\n');
+                }
+                // A single write, so that concurrent submissions do not interleave lines.
+                file.write_all(line.as_bytes())
+            })
+            .map_err(|err| self.error(&err))
+    }
+
+    fn error(&self, err: &dyn std::fmt::Display) -> OperationError {
+        OperationError::detail(
+            &format!("Could not update journal in {}", self.dir.display()),
+            &err.to_string(),
+        )
+    }
+}
+
+/// True if `file` is empty or its last byte is a line feed.
+fn ends_with_newline(file: &mut File) -> std::io::Result<bool> {
+    if file.metadata()?.len() == 0 {
+        return Ok(true);
+    }
+
+    let mut last = [0u8];
+    file.seek(SeekFrom::End(-1))?;
+    file.read_exact(&mut last)?;
+    Ok(last[0] == b'\n')
+}
+
+fn now() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Absolute form of `path`, so that the entry can be resumed from another directory.
+fn absolute(path: &Path) -> PathBuf {
+    std::env::current_dir()
+        .map(|dir| dir.join(path))
+        .unwrap_or_else(|_| path.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{Journal, JOURNAL_FILE_NAME};
+    use crate::util::plist::structs::NotarizationStatus;
+
+    #[test]
+    fn test_journal() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let journal = Journal::new(temp_dir.path().join("state"));
+        assert!(journal.entries().unwrap().is_empty());
+
+        let artifact = temp_dir.path().join("Foo.dmg");
+        journal
+            .record_submitted("c0dec0de", &artifact, Some("ab12"), "Foo.dmg")
+            .unwrap();
+        journal
+            .record_submitted("f00df00d", &artifact, Some("cd34"), "Foo.dmg")
+            .unwrap();
+        journal
+            .record_completed(
+                "c0dec0de",
+                &artifact,
+                "Foo.dmg",
+                NotarizationStatus::Success,
+                "{}",
+            )
+            .unwrap();
+
+        let entries = journal.entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].request_id, "c0dec0de");
+        assert_eq!(entries[0].artifact_path, artifact);
+        assert_eq!(entries[0].sha256.as_deref(), Some("ab12"));
+        assert_eq!(entries[0].status.as_deref(), Some("success"));
+        assert!(entries[0].submitted_at.is_some());
+        let log_path = entries[0].log_path.as_ref().unwrap();
+        assert_eq!(std::fs::read_to_string(log_path).unwrap(), "{}");
+
+        let unfinished = journal.unfinished().unwrap();
+        assert_eq!(unfinished.len(), 1);
+        assert_eq!(unfinished[0].request_id, "f00df00d");
+        assert!(unfinished[0].status.is_none());
+
+        let notarized = journal.find_notarized("ab12").unwrap().unwrap();
+        assert_eq!(notarized.request_id, "c0dec0de");
+        assert!(journal.find_notarized("cd34").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_journal_invalid_request_id() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let journal = Journal::new(temp_dir.path().join("state"));
+
+        for request_id in &["", "../../escaped", "c0de/c0de"] {
+            assert!(journal
+                .record_completed(
+                    request_id,
+                    Path::new("/Foo.pkg"),
+                    "Foo.pkg",
+                    NotarizationStatus::Success,
+                    "{}",
+                )
+                .is_err());
+        }
+        assert!(!temp_dir.path().join("escaped.json").exists());
+        assert!(journal.entries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_journal_interrupted_write() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let journal = Journal::new(temp_dir.path());
+        journal
+            .record_submitted("c0dec0de", Path::new("/Foo.pkg"), None, "Foo.pkg")
+            .unwrap();
+
+        let path = temp_dir.path().join(JOURNAL_FILE_NAME);
+        let mut text = std::fs::read_to_string(&path).unwrap();
+        text.push_str(r#"{"request_id": "c0dec0de", "artifact_pa"#);
+        std::fs::write(&path, text).unwrap();
+
+        let entries = journal.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].is_unfinished());
+
+        journal
+            .record_submitted("f00df00d", Path::new("/Bar.pkg"), None, "Bar.pkg")
+            .unwrap();
+        let entries = journal.entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].request_id, "f00df00d");
+    }
+}
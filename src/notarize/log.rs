@@ -0,0 +1,190 @@
+use console::Style;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::util::json;
+use crate::util::plist::structs::{NotarizationIssueSeverity, NotarizationLog};
+
+/// Renders the notarization service log as a summary of issues grouped by file,
+/// falling back to the raw text if it cannot be parsed.
+pub(crate) fn render(log_text: &str) -> String {
+    match json::notarization_log(log_text.as_bytes()) {
+        Ok(log) => summary(&log),
+        Err(_) => log_text.into(),
+    }
+}
+
+fn summary(log: &NotarizationLog) -> String {
+    let heading_style = Style::new().white().bold();
+    let error_style = Style::new().red().bold();
+    let warning_style = Style::new().yellow().bold();
+    let detail_style = Style::new().dim();
+
+    let mut out = String::new();
+
+    write!(out, "{} {}", heading_style.apply_to("Status:"), log.status).unwrap();
+    if let Some(summary) = &log.status_summary {
+        write!(out, " ({})", summary).unwrap();
+    }
+    writeln!(out).unwrap();
+    if let Some(archive_filename) = &log.archive_filename {
+        writeln!(
+            out,
+            "{} {}",
+            heading_style.apply_to("Archive:"),
+            archive_filename
+        )
+        .unwrap();
+    }
+    if let Some(sha256) = &log.sha256 {
+        writeln!(out, "{} {}", heading_style.apply_to("SHA-256:"), sha256).unwrap();
+    }
+
+    let issues = log.issues.as_deref().unwrap_or_default();
+    let count = |severity| issues.iter().filter(|i| i.severity == severity).count();
+    writeln!(
+        out,
+        "{} {} error(s), {} warning(s)",
+        heading_style.apply_to("Issues:"),
+        count(NotarizationIssueSeverity::Error),
+        count(NotarizationIssueSeverity::Warning)
+    )
+    .unwrap();
+
+    let mut by_path = BTreeMap::new();
+    for issue in issues {
+        by_path
+            .entry(issue.path.as_deref().unwrap_or("(no path)"))
+            .or_insert_with(Vec::new)
+            .push(issue);
+    }
+
+    for (path, issues) in by_path {
+        writeln!(out, "\n{}", heading_style.apply_to(path)).unwrap();
+
+        for issue in issues {
+            let severity = match issue.severity {
+                NotarizationIssueSeverity::Error => error_style.apply_to("error:"),
+                NotarizationIssueSeverity::Warning => warning_style.apply_to("warning:"),
+                NotarizationIssueSeverity::Other => heading_style.apply_to("note:"),
+            };
+            write!(out, "   {} {}", severity, issue.message).unwrap();
+            if let Some(architecture) = &issue.architecture {
+                write!(out, " [{}]", architecture).unwrap();
+            }
+            writeln!(out).unwrap();
+            if let Some(doc_url) = &issue.doc_url {
+                writeln!(out, "      {}", detail_style.apply_to(doc_url)).unwrap();
+            }
+        }
+    }
+
+    if let Some(tickets) = &log.ticket_contents {
+        writeln!(out, "\n{}", heading_style.apply_to("Ticket contents:")).unwrap();
+        for ticket in tickets {
+            write!(out, "   {}", ticket.path).unwrap();
+            if let Some(arch) = &ticket.arch {
+                write!(out, " [{}]", arch).unwrap();
+            }
+            writeln!(out, " {}", detail_style.apply_to(&ticket.cdhash)).unwrap();
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    static INVALID_LOG: &str = r#"{
+        "logFormatVersion": 1,
+        "jobId": "c0dec0de-1234-5678-1234-b4d961a1d182",
+        "status": "Invalid",
+        "statusSummary": "Archive contains critical validation errors",
+        "statusCode": 4000,
+        "archiveFilename": "HelloWorld.zip",
+        "uploadDate": "2021-04-29T01:38:09Z",
+        "sha256": "3caffa321f3adb01d1e0eebabfd8bdb5dbfcfc467522b903ec7b64fdad24ada8",
+        "ticketContents": null,
+        "issues": [
+            {
+                "severity": "error",
+                "code": null,
+                "path": "HelloWorld.zip/HelloWorld.app/Contents/MacOS/HelloWorld",
+                "message": "The binary is not signed with a valid Developer ID certificate.",
+                "docUrl": "https://developer.apple.com/documentation/security/notarizing_macos_software_before_distribution/resolving_common_notarization_issues#3087721",
+                "architecture": "x86_64"
+            },
+            {
+                "severity": "error",
+                "code": null,
+                "path": "HelloWorld.zip/HelloWorld.app/Contents/Frameworks/Helper.dylib",
+                "message": "The signature does not include a secure timestamp.",
+                "docUrl": null,
+                "architecture": "arm64"
+            },
+            {
+                "severity": "warning",
+                "code": null,
+                "path": "HelloWorld.zip/HelloWorld.app/Contents/MacOS/HelloWorld",
+                "message": "The executable requests the com.apple.security.get-task-allow entitlement.",
+                "docUrl": null,
+                "architecture": "x86_64"
+            }
+        ]
+    }"#;
+
+    static ACCEPTED_LOG: &str = r#"{
+        "logFormatVersion": 1,
+        "jobId": "c0dec0de-1234-5678-1234-b4d961a1d182",
+        "status": "Accepted",
+        "statusSummary": "Ready for distribution",
+        "statusCode": 0,
+        "archiveFilename": "HelloWorld.zip",
+        "uploadDate": "2021-04-29T01:38:09Z",
+        "sha256": "3caffa321f3adb01d1e0eebabfd8bdb5dbfcfc467522b903ec7b64fdad24ada8",
+        "ticketContents": [
+            {
+                "path": "HelloWorld.zip/HelloWorld.app",
+                "digestAlgorithm": "SHA-256",
+                "cdhash": "5f0ed4d7f7d6bd08a9b4c2c5b4d0a1e9a7a5e8f2",
+                "arch": "x86_64"
+            }
+        ],
+        "issues": null
+    }"#;
+
+    #[test]
+    fn test_render_issues() {
+        console::set_colors_enabled(false);
+        let output = super::render(INVALID_LOG);
+
+        assert!(output.contains("Status: invalid (Archive contains critical validation errors)"));
+        assert!(output.contains("Issues: 2 error(s), 1 warning(s)"));
+
+        // Issues for the same file are grouped under a single heading.
+        assert_eq!(
+            output
+                .matches("HelloWorld.zip/HelloWorld.app/Contents/MacOS/HelloWorld\n")
+                .count(),
+            1
+        );
+        assert!(output.contains(
+            "   error: The binary is not signed with a valid Developer ID certificate. [x86_64]"
+        ));
+        assert!(output.contains("   warning: The executable requests"));
+    }
+
+    #[test]
+    fn test_render_tickets() {
+        console::set_colors_enabled(false);
+        let output = super::render(ACCEPTED_LOG);
+
+        assert!(output.contains("Issues: 0 error(s), 0 warning(s)"));
+        assert!(output.contains("HelloWorld.zip/HelloWorld.app [x86_64]"));
+    }
+
+    #[test]
+    fn test_render_unparseable() {
+        assert_eq!(super::render("not json"), "not json");
+    }
+}
@@ -1,33 +1,285 @@
+//! Submission of bundles, disk images and installer packages to the notarization service, and
+//! stapling of the resulting tickets.
+
+pub(crate) mod backend;
+pub(crate) mod backoff;
+mod cancel;
+pub(crate) mod hooks;
+mod journal;
+pub(crate) mod log;
+mod notify;
 mod run;
+mod run_async;
+mod service;
+pub(crate) mod staple;
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::util::input_path::{path_info, PathType};
+use crate::util::json;
+use crate::util::{ErrorKind, OperationError};
+use backend::NotaryBackend;
+use hooks::Hooks;
 
-use crate::util::input_path::PathType;
-use std::error::Error;
-use std::path::PathBuf;
+pub use crate::util::plist::structs::{
+    NotarizationIssue, NotarizationIssueSeverity, NotarizationStatus,
+};
+pub use backend::{Backend, ServiceOptions, SubmissionInfo, SubmissionLog, SubmissionSummary};
+pub use backoff::PollSettings;
+pub use cancel::CancellationToken;
+pub use journal::{Journal, JournalEntry};
+pub use service::Service;
+pub use staple::validate;
 
-pub(crate) struct NotarizeOp {
+type ProgressCallback = Arc<dyn Fn(&Progress) + Send + Sync>;
+
+/// Submission of an input to the notarization service. Created with [`NotarizeOp::builder`].
+///
+/// Pre-checks are not performed; run [`crate::precheck::run`] first to catch common code
+/// signing issues without waiting for the service.
+#[derive(Clone)]
+pub struct NotarizeOp {
     input_path: PathBuf,
     path_type: PathType,
     bundle_id: String,
-    developer_account: String,
-    password_keychain_item: String,
-    provider: Option<String>,
+    backend: Arc<dyn NotaryBackend>,
+    /// For .zip input, replace the archive with the stapled bundles rather than extracting them.
+    rezip: bool,
+    poll: PollSettings,
+    hooks: Hooks,
+    progress: Option<ProgressCallback>,
+    journal: Option<Journal>,
+}
+
+/// Step reached by a [`NotarizeOp`], reported to the callback given to
+/// [`NotarizeOpBuilder::on_progress`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Progress {
+    /// Zipping an app bundle for upload.
+    Compressing,
+    Uploading,
+    /// The input was uploaded, and is identified by the request ID.
+    Submitted(String),
+    /// An identical file was already notarized as the request ID, so the input is not uploaded
+    /// again.
+    AlreadyNotarized(String),
+    /// The status of the submission was checked, and is still in progress unless notarization
+    /// completed.
+    StatusChecked(NotarizationStatus),
+    RequestingLog,
+    Stapling,
+}
+
+/// Outcome of a completed submission.
+#[derive(Debug, Clone)]
+pub struct Notarization {
+    pub request_id: String,
+    pub status: NotarizationStatus,
+    pub status_message: Option<String>,
+    /// Service log, as returned by the service.
+    pub log: String,
+    /// Location the log was downloaded from, if the service provides one.
+    pub log_url: Option<String>,
+    /// True if the ticket was stapled to the input, i.e. notarization succeeded.
+    pub stapled: bool,
+    /// True if an identical file was already notarized, so the input was not uploaded again.
+    pub reused: bool,
+}
+
+impl Notarization {
+    pub fn is_success(&self) -> bool {
+        self.status == NotarizationStatus::Success
+    }
+
+    /// Issues listed in the service log, grouped by file.
+    pub fn log_summary(&self) -> String {
+        log::render(&self.log)
+    }
+
+    /// Issues listed in the service log. Empty if the log cannot be parsed.
+    pub fn issues(&self) -> Vec<NotarizationIssue> {
+        json::notarization_log(self.log.as_bytes())
+            .ok()
+            .and_then(|log| log.issues)
+            .unwrap_or_default()
+    }
+
+    /// Error describing a rejected submission, or `None` if notarization succeeded.
+    pub fn error(&self) -> Option<OperationError> {
+        if self.is_success() {
+            return None;
+        }
+
+        let heading = match &self.status_message {
+            Some(message) => format!("Notarization failed ({}). Service response:", message),
+            None => "Notarization failed. Service response:".into(),
+        };
+        Some(OperationError::detail(&heading, &self.log_summary()).with_kind(ErrorKind::Rejected))
+    }
 }
 
-pub(crate) fn run(
+/// Options of a [`NotarizeOp`]. Credentials must be given either as an Apple ID, or as an App
+/// Store Connect API key or token.
+pub struct NotarizeOpBuilder {
     input_path: PathBuf,
-    path_type: PathType,
-    bundle_id: String,
-    developer_account: String,
-    password_keychain_item: String,
-    provider: Option<String>,
-) -> Result<(), Box<dyn Error>> {
-    NotarizeOp::new(
-        input_path,
-        path_type,
-        bundle_id,
-        developer_account,
-        password_keychain_item,
-        provider,
-    )
-    .run()
+    service: ServiceOptions,
+    rezip: bool,
+    poll: PollSettings,
+    hooks: Hooks,
+    progress: Option<ProgressCallback>,
+    journal: Option<Journal>,
+}
+
+impl NotarizeOp {
+    /// Starts describing the submission of the bundle, zip archive, disk image or installer
+    /// package at `input_path`.
+    pub fn builder<P: Into<PathBuf>>(input_path: P) -> NotarizeOpBuilder {
+        NotarizeOpBuilder {
+            input_path: input_path.into(),
+            service: ServiceOptions::default(),
+            rezip: false,
+            poll: PollSettings::default(),
+            hooks: Hooks::default(),
+            progress: None,
+            journal: None,
+        }
+    }
+
+    pub fn input_path(&self) -> &Path {
+        &self.input_path
+    }
+
+    pub fn path_type(&self) -> &PathType {
+        &self.path_type
+    }
+
+    /// Bundle ID of an app bundle, or an identifier derived from the file name of other input.
+    pub fn bundle_id(&self) -> &str {
+        &self.bundle_id
+    }
+
+    fn report(&self, progress: Progress) {
+        if let Some(callback) = &self.progress {
+            callback(&progress);
+        }
+    }
+}
+
+impl NotarizeOpBuilder {
+    /// Tool used to communicate with the notarization service. Defaults to `Backend::Notarytool`.
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.service.backend = Some(backend);
+        self
+    }
+
+    /// Authenticates `Backend::Notarytool` with the Apple ID recorded in a keychain profile
+    /// created with `xcrun notarytool store-credentials`.
+    pub fn keychain_profile(mut self, profile: &str) -> Self {
+        self.service.keychain_profile = Some(profile.into());
+        self
+    }
+
+    /// Authenticates `Backend::Altool` with an Apple ID, whose app-specific password is read
+    /// from the keychain.
+    pub fn apple_id(mut self, developer_account: &str, password_keychain_item: &str) -> Self {
+        self.service.developer_account = Some(developer_account.into());
+        self.service.password_keychain_item = Some(password_keychain_item.into());
+        self
+    }
+
+    /// Selects the team, if the Apple ID is associated with more than one.
+    pub fn provider(mut self, provider: &str) -> Self {
+        self.service.provider = Some(provider.into());
+        self
+    }
+
+    /// Authenticates with an App Store Connect API key (`AuthKey_<key id>.p8`).
+    pub fn api_key<P: Into<PathBuf>>(mut self, key_path: P, key_id: &str, issuer_id: &str) -> Self {
+        self.service.api_key = Some(key_path.into());
+        self.service.api_key_id = Some(key_id.into());
+        self.service.api_issuer = Some(issuer_id.into());
+        self
+    }
+
+    /// Authenticates the `Backend::Api` backend with a previously generated token.
+    pub fn api_token(mut self, token: &str) -> Self {
+        self.service.api_token = Some(token.into());
+        self
+    }
+
+    /// For .zip input, replace the archive with one containing the stapled bundles instead of
+    /// extracting them next to it.
+    pub fn rezip(mut self, rezip: bool) -> Self {
+        self.rezip = rezip;
+        self
+    }
+
+    pub fn poll_settings(mut self, poll: PollSettings) -> Self {
+        self.poll = poll;
+        self
+    }
+
+    /// Shell command run once the input is notarized and stapled.
+    pub fn on_success(mut self, command: &str) -> Self {
+        self.hooks.on_success = Some(command.into());
+        self
+    }
+
+    /// Shell command run if submission, notarization or stapling fails.
+    pub fn on_failure(mut self, command: &str) -> Self {
+        self.hooks.on_failure = Some(command.into());
+        self
+    }
+
+    /// URL to post a JSON document to on submission, success and failure.
+    pub fn webhook(mut self, url: &str) -> Self {
+        self.hooks.webhooks.add(notify::WebhookFormat::Generic, url);
+        self
+    }
+
+    /// Slack incoming webhook URL to post a message to on submission, success and failure.
+    pub fn slack_webhook(mut self, url: &str) -> Self {
+        self.hooks.webhooks.add(notify::WebhookFormat::Slack, url);
+        self
+    }
+
+    /// Called as the operation progresses, e.g. to display its status. Called on the thread
+    /// running the operation, so must return promptly.
+    pub fn on_progress<F: Fn(&Progress) + Send + Sync + 'static>(mut self, callback: F) -> Self {
+        self.progress = Some(Arc::new(callback));
+        self
+    }
+
+    /// Records the submission and its outcome in `journal`.
+    pub fn journal(mut self, journal: Journal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Tool and credentials used to communicate with the service, replacing any set by
+    /// `backend`, `apple_id`, `provider`, `api_key` or `api_token`.
+    pub fn service(mut self, service: ServiceOptions) -> Self {
+        self.service = service;
+        self
+    }
+
+    /// Identifies the input and connects to the notarization service. Fails if the input is
+    /// not supported or credentials are missing.
+    pub fn build(self) -> Result<NotarizeOp, OperationError> {
+        let (path_type, bundle_id) = path_info(&self.input_path)?;
+        let backend = backend::create(self.service)?.into();
+
+        Ok(NotarizeOp {
+            input_path: self.input_path,
+            path_type,
+            bundle_id,
+            backend,
+            rezip: self.rezip,
+            poll: self.poll,
+            hooks: self.hooks,
+            progress: self.progress,
+            journal: self.journal,
+        })
+    }
 }
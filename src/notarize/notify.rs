@@ -0,0 +1,324 @@
+//! Webhook notifications sent when a submission is made and when it completes.
+
+use console::strip_ansi_codes;
+use reqwest::blocking::Client;
+use serde::Serialize;
+use std::error::Error;
+use std::time::Duration;
+
+use super::backoff::with_retry;
+use super::hooks::Completion;
+use crate::output;
+use crate::util::json;
+use crate::util::plist::structs::NotarizationIssueSeverity;
+use crate::util::{ErrorKind, OperationError};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Event {
+    Submitted,
+    Succeeded,
+    Failed,
+}
+
+/// Payload layout expected by the receiving service.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum WebhookFormat {
+    /// The `Notification` document.
+    Generic,
+    /// A Slack incoming webhook message.
+    Slack,
+}
+
+/// URLs notified of submission events.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Webhooks {
+    pub(crate) urls: Vec<(WebhookFormat, String)>,
+}
+
+/// Document posted to generic webhooks. Field names are part of the command line interface and
+/// must not change.
+#[derive(Serialize, Debug)]
+pub(crate) struct Notification {
+    /// One of "submitted", "succeeded" or "failed".
+    pub(crate) event: Event,
+    pub(crate) request_id: Option<String>,
+    pub(crate) bundle_id: String,
+    /// One of "in_progress", "success" or "invalid", if known.
+    pub(crate) status: Option<&'static str>,
+    pub(crate) issues: IssueCounts,
+    /// Seconds since the operation started.
+    pub(crate) duration: u64,
+    pub(crate) error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub(crate) struct IssueCounts {
+    pub(crate) errors: usize,
+    pub(crate) warnings: usize,
+}
+
+impl Notification {
+    /// Notification of `event`, describing `error` if the operation failed.
+    pub(super) fn new(
+        event: Event,
+        completion: &Completion,
+        bundle_id: &str,
+        error: Option<&dyn Error>,
+    ) -> Self {
+        Notification {
+            event,
+            request_id: completion.request_id.clone(),
+            bundle_id: bundle_id.into(),
+            status: completion.status.map(|status| status.id()),
+            issues: completion
+                .log
+                .as_deref()
+                .map(issue_counts)
+                .unwrap_or_default(),
+            duration: completion.started.elapsed().as_secs(),
+            // Errors are styled for the terminal, which the receiver cannot display.
+            error: error.map(|err| strip_ansi_codes(&err.to_string()).trim().to_string()),
+        }
+    }
+
+    fn slack_message(&self) -> serde_json::Value {
+        let request = self.request_id.as_deref().unwrap_or("unknown");
+        let text = match self.event {
+            Event::Submitted => format!(
+                ":outbox_tray: *{}* submitted for notarization (request `{}`)",
+                self.bundle_id, request
+            ),
+            Event::Succeeded => format!(
+                ":white_check_mark: *{}* notarized in {} (request `{}`, {} warnings)",
+                self.bundle_id,
+                format_duration(self.duration),
+                request,
+                self.issues.warnings
+            ),
+            Event::Failed => format!(
+                ":x: *{}* failed notarization after {} (request `{}`, {} errors, {} warnings){}",
+                self.bundle_id,
+                format_duration(self.duration),
+                request,
+                self.issues.errors,
+                self.issues.warnings,
+                self.error
+                    .as_deref()
+                    .map(|error| format!("\n```{}```", error))
+                    .unwrap_or_default()
+            ),
+        };
+
+        serde_json::json!({ "text": text })
+    }
+}
+
+impl Webhooks {
+    pub(crate) fn add(&mut self, format: WebhookFormat, url: &str) {
+        self.urls.push((format, url.into()));
+    }
+
+    /// Posts `notification` to every webhook, retrying transient failures. Notifications are
+    /// best effort: failures are reported without failing the operation.
+    pub(super) fn notify(&self, notification: &Notification) {
+        if self.urls.is_empty() {
+            return;
+        }
+
+        let client = Client::new();
+        for (format, url) in &self.urls {
+            let body = match format {
+                WebhookFormat::Generic => serde_json::to_value(notification).unwrap(),
+                WebhookFormat::Slack => notification.slack_message(),
+            };
+
+            if let Err(err) = with_retry(|| post(&client, url, &body)) {
+                output::warn(&err);
+            }
+        }
+    }
+}
+
+fn post(client: &Client, url: &str, body: &serde_json::Value) -> Result<(), OperationError> {
+    let heading = format!("Could not notify webhook {}", url);
+
+    let response = client
+        .post(url)
+        .json(body)
+        .timeout(Duration::from_secs(30))
+        .send()
+        .map_err(|err| OperationError::transient(&heading, &err.to_string()))?;
+
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+
+    let error = OperationError::detail(&heading, &format!("HTTP {}", status));
+    Err(if status.is_server_error() || status.as_u16() == 429 {
+        error.with_kind(ErrorKind::Network)
+    } else {
+        error
+    })
+}
+
+fn issue_counts(log: &str) -> IssueCounts {
+    let mut counts = IssueCounts::default();
+
+    let issues = json::notarization_log(log.as_bytes())
+        .ok()
+        .and_then(|log| log.issues)
+        .unwrap_or_default();
+    for issue in issues {
+        match issue.severity {
+            NotarizationIssueSeverity::Error => counts.errors += 1,
+            NotarizationIssueSeverity::Warning => counts.warnings += 1,
+            NotarizationIssueSeverity::Other => {}
+        }
+    }
+
+    counts
+}
+
+fn format_duration(seconds: u64) -> String {
+    if seconds < 60 {
+        format!("{}s", seconds)
+    } else {
+        format!("{}m {}s", seconds / 60, seconds % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use console::style;
+    use std::sync::mpsc;
+    use tiny_http::{Response, Server};
+
+    use super::{Event, IssueCounts, Notification, WebhookFormat, Webhooks};
+    use crate::notarize::hooks::Completion;
+    use crate::util::plist::structs::NotarizationStatus;
+    use crate::util::OperationError;
+
+    static LOG: &str = r#"{
+        "status": "Invalid",
+        "issues": [
+            { "severity": "error", "path": "Foo.app/Contents/MacOS/Foo", "message": "The binary is not signed." },
+            { "severity": "warning", "path": "Foo.app/Contents/MacOS/Foo", "message": "The binary uses an SDK older than the 10.9 SDK." }
+        ]
+    }"#;
+
+    /// Local listener standing in for webhook receivers, yielding the body of each request.
+    fn listener() -> (String, mpsc::Receiver<serde_json::Value>) {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", server.server_addr().to_ip().unwrap());
+
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            for mut request in server.incoming_requests() {
+                let body: serde_json::Value = serde_json::from_reader(request.as_reader()).unwrap();
+                sender.send(body).unwrap();
+                request.respond(Response::empty(200)).unwrap();
+            }
+        });
+
+        (url, receiver)
+    }
+
+    fn failed_completion() -> Completion {
+        let mut completion = Completion::new();
+        completion.request_id = Some("c0dec0de".into());
+        completion.status = Some(NotarizationStatus::Invalid);
+        completion.log = Some(LOG.into());
+        completion
+    }
+
+    #[test]
+    fn test_generic_webhook() {
+        let (url, received) = listener();
+        let webhooks = Webhooks {
+            urls: vec![(WebhookFormat::Generic, url)],
+        };
+
+        let notification = Notification::new(
+            Event::Failed,
+            &failed_completion(),
+            "com.example.app",
+            Some(&OperationError::new("Notarization failed")),
+        );
+        assert_eq!(
+            notification.issues,
+            IssueCounts {
+                errors: 1,
+                warnings: 1
+            }
+        );
+        webhooks.notify(&notification);
+
+        let body = received.recv().unwrap();
+        assert_eq!(body["event"], "failed");
+        assert_eq!(body["request_id"], "c0dec0de");
+        assert_eq!(body["bundle_id"], "com.example.app");
+        assert_eq!(body["status"], "invalid");
+        assert_eq!(body["issues"]["errors"], 1);
+        assert_eq!(body["issues"]["warnings"], 1);
+        assert_eq!(body["duration"], 0);
+        assert_eq!(body["error"], "Error: Notarization failed");
+    }
+
+    #[test]
+    fn test_notification_unstyled_error() {
+        let heading = style("Notarization failed").red().force_styling(true);
+        let error = OperationError::detail(&heading.to_string(), "Package Invalid");
+        let notification = Notification::new(
+            Event::Failed,
+            &failed_completion(),
+            "com.example.app",
+            Some(&error),
+        );
+
+        assert_eq!(
+            notification.error.as_deref(),
+            Some("Notarization failed:\nPackage Invalid")
+        );
+        assert!(!notification.slack_message().to_string().contains('\u{1b}'));
+    }
+
+    #[test]
+    fn test_slack_webhook() {
+        let (url, received) = listener();
+        let webhooks = Webhooks {
+            urls: vec![(WebhookFormat::Slack, url)],
+        };
+
+        let mut completion = Completion::new();
+        completion.request_id = Some("c0dec0de".into());
+        webhooks.notify(&Notification::new(
+            Event::Submitted,
+            &completion,
+            "com.example.app",
+            None,
+        ));
+
+        let body = received.recv().unwrap();
+        assert_eq!(
+            body["text"],
+            ":outbox_tray: *com.example.app* submitted for notarization (request `c0dec0de`)"
+        );
+    }
+
+    #[test]
+    fn test_rejected_webhook() {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", server.server_addr().to_ip().unwrap());
+        std::thread::spawn(move || {
+            for request in server.incoming_requests() {
+                request.respond(Response::empty(404)).unwrap();
+            }
+        });
+
+        let client = reqwest::blocking::Client::new();
+        let err = super::post(&client, &url, &serde_json::json!({})).unwrap_err();
+        assert!(!err.is_transient());
+    }
+}
@@ -1,242 +1,531 @@
-use console::style;
 use std::error::Error;
 use std::io::prelude::*;
-use std::path::PathBuf;
-use std::process::Command;
-use tempfile::{Builder as TempFileBuilder, TempDir};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tempfile::{Builder as TempFileBuilder, NamedTempFile, TempDir};
 
+use crate::archive;
+use crate::output;
 use crate::util::display::progress_bar;
+use crate::util::hash;
 use crate::util::input_path::PathType;
-use crate::util::plist;
-use crate::util::plist::structs::{NotarizationInfo, NotarizationStatus};
-use crate::util::OperationError;
-
-use super::NotarizeOp;
-
-struct InputFilePath {
-    path: PathBuf,
+use crate::util::json;
+use crate::util::plist::structs::NotarizationStatus;
+use crate::util::{ErrorKind, OperationError};
+
+use super::backend::{SubmissionInfo, SubmissionLog};
+use super::backoff::{with_retry, Backoff};
+use super::hooks::Completion;
+use super::staple::{self, TicketService};
+use super::{Notarization, NotarizeOp, Progress};
+
+pub(super) struct InputFilePath {
+    pub(super) path: PathBuf,
+    /// Hex-encoded SHA-256 digest of the file, if computed.
+    pub(super) sha256: Option<String>,
     _temp_dir: Option<TempDir>,
 }
-enum AltoolArgs<'a> {
-    NotarizationInfo {
-        request_id: &'a str,
-    },
-    NotarizeApp {
-        path: &'a PathBuf,
-        bundle_id: &'a str,
-    },
+
+/// Successful submission of a file identical to the one to upload.
+pub(super) struct PreviousSubmission {
+    pub(super) request_id: String,
+    pub(super) info: SubmissionInfo,
+    pub(super) log: SubmissionLog,
 }
 
 impl NotarizeOp {
-    pub(super) fn new(
-        input_path: PathBuf,
-        path_type: PathType,
-        bundle_id: String,
-        developer_account: String,
-        password_keychain_item: String,
-        provider: Option<String>,
-    ) -> Self {
-        NotarizeOp {
-            input_path,
-            path_type,
-            bundle_id,
-            developer_account,
-            password_keychain_item,
-            provider,
-        }
-    }
-
-    pub(super) fn run(&self) -> Result<(), Box<dyn Error>> {
-        let input_path = match self.path_type {
-            PathType::AppBundle => {
-                let pb = progress_bar("Compressing bundle");
-                let ret = self.zip_bundle()?;
-                pb.finish();
-                ret
+    /// Uploads the input, waits for notarization to complete and staples the ticket to the
+    /// input. A rejected submission is returned as a `Notarization` describing the issues.
+    ///
+    /// With a journal, the upload is skipped if an identical file was already notarized.
+    pub fn run(&self) -> Result<Notarization, OperationError> {
+        let mut completion = Completion::new();
+        let result =
+            self.input_file()
+                .and_then(|input_file| match self.previous_submission(&input_file) {
+                    Some(previous) => self.reuse(previous, &mut completion),
+                    None => {
+                        let request_id = self.upload(&input_file, &mut completion)?;
+                        self.complete(&request_id, &mut completion)
+                    }
+                });
+
+        self.run_hooks(result, &completion)
+    }
+
+    /// Uploads the input without waiting for completion, returning the request ID to pass to
+    /// `wait`. Webhooks are notified of the submission, or of the failure.
+    ///
+    /// With a journal, the request ID of a previous submission is returned instead if an
+    /// identical file was already notarized.
+    pub fn submit(&self) -> Result<String, OperationError> {
+        let mut completion = Completion::new();
+        let result =
+            self.input_file()
+                .and_then(|input_file| match self.previous_submission(&input_file) {
+                    Some(previous) => {
+                        self.reused(&previous.request_id, &mut completion);
+                        Ok(previous.request_id)
+                    }
+                    None => self.upload(&input_file, &mut completion),
+                });
+
+        result.map_err(|err| self.run_hooks(Err(err), &completion).unwrap_err())
+    }
+
+    /// Polls the notarization service until a previously submitted request completes, then
+    /// staples the input. Fails with `ErrorKind::TimedOut` if the request does not complete
+    /// in time.
+    pub fn wait(&self, request_id: &str) -> Result<Notarization, OperationError> {
+        let mut completion = Completion::new();
+        let result = self.complete(request_id, &mut completion);
+
+        self.run_hooks(result, &completion)
+    }
+
+    /// Runs the hook for the outcome of `result`. A failing hook fails an otherwise successful
+    /// operation, but does not replace the error of a failed one. An error occurring after
+    /// submission is given the request ID.
+    pub(super) fn run_hooks(
+        &self,
+        result: Result<Notarization, OperationError>,
+        completion: &Completion,
+    ) -> Result<Notarization, OperationError> {
+        let error = match &result {
+            Ok(notarization) => notarization.error(),
+            Err(err) => Some(err.clone()),
+        };
+        let hook = self.hooks.run(
+            error.as_ref().map(|err| err as &dyn Error),
+            completion,
+            &self.bundle_id,
+            &self.input_path,
+        );
+
+        let result = match hook {
+            Err(hook_err) if error.is_none() => Err(hook_err),
+            Err(hook_err) => {
+                output::warn(&hook_err);
+                result
             }
-            PathType::DiskImage | PathType::InstallerPackage => InputFilePath {
-                path: self.input_path.clone(),
-                _temp_dir: None,
-            },
+            Ok(()) => result,
         };
 
+        match &completion.request_id {
+            Some(request_id) => result.map_err(|err| err.with_request_id(request_id)),
+            None => result,
+        }
+    }
+
+    /// Uploads the input file to the notarization service, returning the request ID.
+    fn upload(
+        &self,
+        input_file: &InputFilePath,
+        completion: &mut Completion,
+    ) -> Result<String, OperationError> {
+        self.report(Progress::Uploading);
         let pb = progress_bar("Uploading to notarization service");
-        let request_id = self.submit_to_service(input_path)?;
+        // Not retried, since the service may have accepted the submission before failing.
+        let request_id = self.backend.submit(&input_file.path, &self.bundle_id)?;
         pb.finish();
 
-        let pb = progress_bar("Waiting for notarization");
+        self.submitted(&request_id, input_file, completion);
+        Ok(request_id)
+    }
 
-        let (success, log_url) = loop {
-            std::io::stdout().flush().unwrap();
+    /// Staples the ticket of a previous submission instead of uploading the input again.
+    fn reuse(
+        &self,
+        previous: PreviousSubmission,
+        completion: &mut Completion,
+    ) -> Result<Notarization, OperationError> {
+        self.reused(&previous.request_id, completion);
+        let mut notarization = self.completed(
+            &previous.request_id,
+            previous.info,
+            previous.log,
+            completion,
+        );
+        notarization.reused = true;
+
+        self.staple_notarized(notarization)
+    }
 
-            std::thread::sleep(std::time::Duration::from_secs(5));
+    /// Waits for the request to complete and, if notarized, staples the input, recording
+    /// progress in `completion`.
+    fn complete(
+        &self,
+        request_id: &str,
+        completion: &mut Completion,
+    ) -> Result<Notarization, OperationError> {
+        self.waiting(request_id, completion);
+        let pb = progress_bar("Waiting for notarization");
 
-            let info = self.get_status(&request_id)?;
+        let started = Instant::now();
+        let mut backoff = self.poll_backoff();
 
-            match info.details.status {
-                NotarizationStatus::InProgress => continue,
-                NotarizationStatus::Success => {
-                    break (true, info.details.logfile_url);
-                }
-                NotarizationStatus::Invalid => {
-                    break (false, info.details.logfile_url);
+        let info = loop {
+            std::io::stdout().flush().ok();
+
+            let delay = match self.poll_delay(&mut backoff, started) {
+                Some(delay) => delay,
+                None => {
+                    pb.finish();
+                    return Err(self.timed_out(request_id, completion));
                 }
+            };
+            std::thread::sleep(delay);
+
+            let info = with_retry(|| self.backend.status(request_id))?;
+            self.report(Progress::StatusChecked(info.status));
+
+            if info.status != NotarizationStatus::InProgress {
+                break info;
             }
         };
 
         pb.finish();
 
+        self.report(Progress::RequestingLog);
         let pb = progress_bar("Requesting log file");
-        let log_text = reqwest::blocking::get(&log_url.unwrap())
-            .unwrap()
-            .text()
-            .unwrap();
+        let log = with_retry(|| self.backend.log(request_id))?;
         pb.finish();
 
-        if !success {
-            return Err(OperationError::detail(
-                "Notarization failed. Service response:",
-                &log_text,
-            )
-            .into());
+        let notarization = self.completed(request_id, info, log, completion);
+        self.staple_notarized(notarization)
+    }
+
+    /// Staples the ticket to the input if notarization succeeded.
+    fn staple_notarized(
+        &self,
+        mut notarization: Notarization,
+    ) -> Result<Notarization, OperationError> {
+        if !notarization.is_success() {
+            return Ok(notarization);
         }
 
+        self.report(Progress::Stapling);
         let pb = progress_bar("Stapling");
-        self.staple()?;
+        self.staple_input()?;
         pb.finish();
 
-        println!("\n{}", style("Success! Review the service response for additional issues or warnings:").green().bold());
-        println!("{}", log_text);
+        notarization.stapled = true;
+        Ok(notarization)
+    }
 
-        Ok(())
+    /// The file to upload: the input, or a zip archive of it if it is a bundle.
+    pub(super) fn input_file(&self) -> Result<InputFilePath, OperationError> {
+        let mut input_file = match self.path_type {
+            PathType::AppBundle => {
+                self.report(Progress::Compressing);
+                let pb = progress_bar("Compressing bundle");
+                let ret = self.zip_bundle()?;
+                pb.finish();
+                ret
+            }
+            PathType::ZipArchive | PathType::DiskImage | PathType::InstallerPackage => {
+                InputFilePath {
+                    path: self.input_path.clone(),
+                    sha256: None,
+                    _temp_dir: None,
+                }
+            }
+        };
+
+        if self.journal.is_some() {
+            let sha256 = hash::sha256_file(&input_file.path).map_err(|err| {
+                OperationError::detail(
+                    &format!("Could not read {}", input_file.path.display()),
+                    &err.to_string(),
+                )
+            })?;
+            input_file.sha256 = Some(sha256);
+        }
+
+        Ok(input_file)
     }
 
-    fn zip_bundle(&self) -> Result<InputFilePath, OperationError> {
-        let temp_dir = TempFileBuilder::new().tempdir().unwrap();
+    /// Successful submission of a file identical to `input_file`, recorded in the journal and
+    /// confirmed by the service. If the lookup fails, the failure is reported and `None` is
+    /// returned so that the input is uploaded as usual.
+    pub(super) fn previous_submission(
+        &self,
+        input_file: &InputFilePath,
+    ) -> Option<PreviousSubmission> {
+        let (journal, sha256) = match (&self.journal, &input_file.sha256) {
+            (Some(journal), Some(sha256)) => (journal, sha256),
+            _ => return None,
+        };
+
+        let previous = journal
+            .find_notarized(sha256)
+            .and_then(|entry| match entry {
+                Some(entry) => self.confirm_notarized(&entry.request_id, sha256),
+                None => Ok(None),
+            });
+
+        match previous {
+            Ok(previous) => previous,
+            Err(err) => {
+                output::warn(&err);
+                None
+            }
+        }
+    }
 
-        let bundle_file_name = self.input_path.file_name().unwrap();
+    /// Checks with the service that `request_id` was notarized, and that the uploaded file had
+    /// digest `sha256`.
+    fn confirm_notarized(
+        &self,
+        request_id: &str,
+        sha256: &str,
+    ) -> Result<Option<PreviousSubmission>, OperationError> {
+        let pb = progress_bar("Checking previous submission");
+        let info = with_retry(|| self.backend.status(request_id))?;
+        if info.status != NotarizationStatus::Success {
+            pb.finish();
+            return Ok(None);
+        }
+        let log = with_retry(|| self.backend.log(request_id))?;
+        pb.finish();
 
-        let mut zip_path = PathBuf::from(temp_dir.path());
-        zip_path.set_file_name(bundle_file_name);
-        zip_path.set_extension("zip");
+        // Depending on the backend, the digest is reported with the status or only in the log.
+        // The journal alone is not trusted, so without a digest the file is uploaded again.
+        let service_sha256 = info.sha256.clone().or_else(|| {
+            json::notarization_log(log.text.as_bytes())
+                .ok()
+                .and_then(|log| log.sha256)
+        });
+        if !service_sha256.is_some_and(|digest| digest.eq_ignore_ascii_case(sha256)) {
+            return Ok(None);
+        }
 
-        let mut bundle_parent_dir_path = self.input_path.parent().unwrap();
-        // related: https://github.com/rust-lang/rust/issues/36861
-        if !bundle_parent_dir_path.is_dir() {
-            bundle_parent_dir_path = std::path::Path::new(".");
+        Ok(Some(PreviousSubmission {
+            request_id: request_id.into(),
+            info,
+            log,
+        }))
+    }
+
+    /// Records that an identical file was already notarized as `request_id`.
+    pub(super) fn reused(&self, request_id: &str, completion: &mut Completion) {
+        self.waiting(request_id, completion);
+        self.report(Progress::AlreadyNotarized(request_id.into()));
+
+        progress_bar(&format!(
+            "Identical file already notarized, request UUID {}",
+            request_id
+        ))
+        .finish();
+    }
+
+    /// Records that `input_file` was uploaded as `request_id`, and notifies webhooks.
+    pub(super) fn submitted(
+        &self,
+        request_id: &str,
+        input_file: &InputFilePath,
+        completion: &mut Completion,
+    ) {
+        if let Some(journal) = &self.journal {
+            let recorded = journal.record_submitted(
+                request_id,
+                &self.input_path,
+                input_file.sha256.as_deref(),
+                &self.bundle_id,
+            );
+            if let Err(err) = recorded {
+                output::warn(&err);
+            }
         }
 
-        let status = Command::new("/usr/bin/ditto")
-            .current_dir(bundle_parent_dir_path)
-            .args(&[
-                "-ck",
-                "--keepParent",
-                &bundle_file_name.to_str().unwrap(),
-                &zip_path.to_str().unwrap(),
-            ])
-            .status()
-            .unwrap();
+        completion.request_id = Some(request_id.into());
+        self.report(Progress::Submitted(request_id.into()));
+        self.hooks.submitted(completion, &self.bundle_id);
+    }
+
+    /// Records that the operation is waiting for `request_id` to complete.
+    pub(super) fn waiting(&self, request_id: &str, completion: &mut Completion) {
+        completion.request_id = Some(request_id.into());
+    }
 
-        if !status.success() {
-            return Err(OperationError::new("Notarization zip creation failed"));
+    pub(super) fn poll_backoff(&self) -> Backoff {
+        Backoff::new(self.poll.initial_interval, self.poll.max_interval)
+    }
+
+    /// Delay before the next status check, or `None` if the timeout has elapsed since
+    /// `started`.
+    pub(super) fn poll_delay(&self, backoff: &mut Backoff, started: Instant) -> Option<Duration> {
+        let delay = backoff.next_delay();
+
+        match self.poll.timeout {
+            Some(timeout) => timeout
+                .checked_sub(started.elapsed())
+                .filter(|remaining| !remaining.is_zero())
+                .map(|remaining| delay.min(remaining)),
+            None => Some(delay),
         }
+    }
 
-        return Ok(InputFilePath {
-            path: zip_path,
-            _temp_dir: Some(temp_dir),
-        });
+    /// Records that the request was still in progress when the timeout elapsed.
+    pub(super) fn timed_out(
+        &self,
+        request_id: &str,
+        completion: &mut Completion,
+    ) -> OperationError {
+        completion.status = Some(NotarizationStatus::InProgress);
+
+        OperationError::timed_out(request_id)
     }
 
-    /// Submits app to the notarization service, returning the request ID.
-    fn submit_to_service(&self, input_path: InputFilePath) -> Result<String, OperationError> {
-        let output = self.run_altool(AltoolArgs::NotarizeApp {
-            path: &input_path.path,
-            bundle_id: &self.bundle_id,
-        })?;
+    /// Records the outcome of a completed request.
+    pub(super) fn completed(
+        &self,
+        request_id: &str,
+        info: SubmissionInfo,
+        log: SubmissionLog,
+        completion: &mut Completion,
+    ) -> Notarization {
+        completion.status = Some(info.status);
+        completion.log = Some(log.text.clone());
+
+        if let Some(journal) = &self.journal {
+            let recorded = journal.record_completed(
+                request_id,
+                &self.input_path,
+                &self.bundle_id,
+                info.status,
+                &log.text,
+            );
+            if let Err(err) = recorded {
+                output::warn(&err);
+            }
+        }
 
-        let upload = plist::notarization_upload_response(output.as_bytes());
+        Notarization {
+            request_id: request_id.into(),
+            status: info.status,
+            status_message: info.status_message,
+            log: log.text,
+            log_url: log.url,
+            stapled: false,
+            reused: false,
+        }
+    }
 
-        Ok(upload.details.request_uuid)
+    /// Staples the ticket to the input, failing with `ErrorKind::StapleFailed`.
+    pub(super) fn staple_input(&self) -> Result<(), OperationError> {
+        self.staple()
+            .map_err(|err| err.with_kind(ErrorKind::StapleFailed))
     }
 
-    /// Retrieves current status from the notarization service.
-    fn get_status(&self, request_id: &str) -> Result<NotarizationInfo, OperationError> {
-        let output = self.run_altool(AltoolArgs::NotarizationInfo {
-            request_id: request_id.clone(),
+    fn zip_bundle(&self) -> Result<InputFilePath, OperationError> {
+        let temp_dir = TempFileBuilder::new().tempdir().map_err(|err| {
+            OperationError::detail("Could not create temporary directory", &err.to_string())
         })?;
 
-        let info = plist::notarization_status_response(output.as_bytes());
+        let name = self.input_path.file_name().ok_or_else(|| {
+            OperationError::new(&format!(
+                "Expected a bundle name: {}",
+                self.input_path.display()
+            ))
+        })?;
+        let mut zip_path = temp_dir.path().join(name);
+        zip_path.set_extension("zip");
 
-        if !info
-            .success_message
-            .eq("No errors getting notarization info.")
-        {
-            return Err(OperationError::detail(
-                "Unexpected notarization message",
-                &info.success_message,
-            ));
-        }
+        archive::zip::archive_bundle(&self.input_path, &zip_path)?;
 
-        Ok(info)
+        Ok(InputFilePath {
+            path: zip_path,
+            sha256: None,
+            _temp_dir: Some(temp_dir),
+        })
     }
 
     fn staple(&self) -> Result<(), OperationError> {
-        let output = Command::new("/usr/bin/xcrun")
-            .args(["stapler", "staple"])
-            .arg(self.input_path.as_os_str())
-            .output()
-            .unwrap();
+        match self.path_type {
+            PathType::ZipArchive => self.staple_archive(),
+            _ => staple(&self.input_path, &self.path_type),
+        }
+    }
 
-        if !output.status.success() {
-            let output = String::from_utf8(output.stderr).unwrap();
-            return Err(OperationError::detail("Staple failed", &output));
+    /// Staples the bundles inside a zip input. The stapled bundles are extracted next to the
+    /// archive or, with `rezip`, replace its contents.
+    fn staple_archive(&self) -> Result<(), OperationError> {
+        let input_dir = self.input_dir();
+        let extracted = archive::zip::extract_bundles(&self.input_path, Some(input_dir))?;
+
+        for bundle in &extracted.bundles {
+            staple(bundle, &PathType::AppBundle)?;
+        }
+
+        if self.rezip {
+            let error = |err: &dyn std::fmt::Display| {
+                OperationError::detail(
+                    &format!("Could not replace {}", self.input_path.display()),
+                    &err.to_string(),
+                )
+            };
+
+            let zip_file = NamedTempFile::new_in(input_dir).map_err(|err| error(&err))?;
+            archive::zip::archive_contents(extracted.path(), zip_file.path())?;
+            zip_file
+                .persist(&self.input_path)
+                .map_err(|err| error(&err))?;
+
+            return Ok(());
+        }
+
+        let destinations = extracted
+            .bundles
+            .iter()
+            .map(|bundle| {
+                let relative = bundle.strip_prefix(extracted.path()).map_err(|_| {
+                    OperationError::new(&format!(
+                        "Extracted bundle is outside the extraction directory: {}",
+                        bundle.display()
+                    ))
+                })?;
+                Ok(input_dir.join(relative))
+            })
+            .collect::<Result<Vec<PathBuf>, OperationError>>()?;
+
+        if let Some(existing) = destinations.iter().find(|path| path.exists()) {
+            return Err(OperationError::new(&format!(
+                "Could not extract stapled bundle: {} already exists. Specify --rezip to update the archive instead.",
+                existing.display()
+            )));
+        }
+
+        for (bundle, destination) in extracted.bundles.iter().zip(&destinations) {
+            std::fs::create_dir_all(destination.parent().unwrap_or(input_dir))
+                .and_then(|_| std::fs::rename(bundle, destination))
+                .map_err(|err| {
+                    OperationError::detail(
+                        &format!(
+                            "Could not extract stapled bundle to {}",
+                            destination.display()
+                        ),
+                        &err.to_string(),
+                    )
+                })?;
         }
 
         Ok(())
     }
 
-    fn run_altool(&self, args: AltoolArgs) -> Result<String, OperationError> {
-        let args = match args {
-            AltoolArgs::NotarizationInfo { request_id } => vec!["--notarization-info", &request_id],
-            AltoolArgs::NotarizeApp { path, bundle_id } => vec![
-                "--notarize-app",
-                "--file",
-                path.to_str().unwrap(),
-                "--primary-bundle-id",
-                bundle_id,
-            ],
-        };
-
-        let provider_args = self
-            .provider
-            .as_ref()
-            .map_or(vec![], |p| vec!["--asc-provider", &p]);
-
-        let output = Command::new("/usr/bin/xcrun")
-            .args(&[
-                "altool",
-                "-u",
-                &self.developer_account,
-                "-p",
-                &format!("@keychain:{}", self.password_keychain_item),
-                "--output-format",
-                "xml",
-            ])
-            .args(provider_args)
-            .args(args)
-            .output()
-            .unwrap();
-
-        let stdout = String::from_utf8(output.stdout).unwrap();
-
-        if output.status.success() {
-            Ok(stdout)
-        } else {
-            let combined = String::from_utf8(output.stderr).unwrap() + &stdout;
-            Err(OperationError::detail(
-                "Notarization service returned an error. Please check the output and try again",
-                &combined,
-            ))
+    /// Directory containing the input.
+    fn input_dir(&self) -> &Path {
+        match self.input_path.parent() {
+            // related: https://github.com/rust-lang/rust/issues/36861
+            Some(parent) if parent.is_dir() => parent,
+            _ => Path::new("."),
         }
     }
 }
+
+/// Staples the bundle, disk image or package at `path`, retrying if the ticket service is
+/// unavailable.
+fn staple(path: &Path, path_type: &PathType) -> Result<(), OperationError> {
+    let service = TicketService::new();
+    with_retry(|| staple::staple(path, path_type, &service))
+}
@@ -0,0 +1,452 @@
+//! Asynchronous variants of the operations, for programs supervising many submissions from a
+//! Tokio runtime. Service requests, compression and stapling block, so they run on the
+//! runtime's blocking thread pool; waiting between status checks does not occupy a thread.
+
+use std::time::Instant;
+
+use crate::util::plist::structs::NotarizationStatus;
+use crate::util::OperationError;
+
+use super::backend::{NotaryBackend, SubmissionInfo, SubmissionLog};
+use super::backoff::with_retry_async;
+use super::hooks::Completion;
+use super::run::{InputFilePath, PreviousSubmission};
+use super::{CancellationToken, Notarization, NotarizeOp, Progress};
+
+impl NotarizeOp {
+    /// Asynchronous [`NotarizeOp::run`]. Must be called within a Tokio runtime.
+    ///
+    /// Fails with `ErrorKind::Cancelled` once `cancel` is cancelled, after running the
+    /// `on_failure` hook. A step already running on the blocking pool, such as an upload, is
+    /// abandoned rather than interrupted.
+    pub async fn run_async(
+        &self,
+        cancel: &CancellationToken,
+    ) -> Result<Notarization, OperationError> {
+        let mut completion = Completion::new();
+        let result = match self.input_file_async(cancel).await {
+            Ok((_, Some(previous))) => self.reuse_async(previous, &mut completion, cancel).await,
+            Ok((input_file, None)) => {
+                match self.upload_async(input_file, &mut completion, cancel).await {
+                    Ok(request_id) => {
+                        self.complete_async(&request_id, &mut completion, cancel)
+                            .await
+                    }
+                    Err(err) => Err(err),
+                }
+            }
+            Err(err) => Err(err),
+        };
+
+        self.run_hooks_async(result, completion).await
+    }
+
+    /// Asynchronous [`NotarizeOp::submit`]. Must be called within a Tokio runtime.
+    pub async fn submit_async(&self, cancel: &CancellationToken) -> Result<String, OperationError> {
+        let mut completion = Completion::new();
+        let result = match self.input_file_async(cancel).await {
+            Ok((_, Some(previous))) => {
+                self.reused(&previous.request_id, &mut completion);
+                Ok(previous.request_id)
+            }
+            Ok((input_file, None)) => self.upload_async(input_file, &mut completion, cancel).await,
+            Err(err) => Err(err),
+        };
+
+        match result {
+            Ok(request_id) => Ok(request_id),
+            Err(err) => Err(self
+                .run_hooks_async(Err(err), completion)
+                .await
+                .unwrap_err()),
+        }
+    }
+
+    /// Asynchronous [`NotarizeOp::wait`]. Must be called within a Tokio runtime.
+    pub async fn wait_async(
+        &self,
+        request_id: &str,
+        cancel: &CancellationToken,
+    ) -> Result<Notarization, OperationError> {
+        let mut completion = Completion::new();
+        let result = self
+            .complete_async(request_id, &mut completion, cancel)
+            .await;
+
+        self.run_hooks_async(result, completion).await
+    }
+
+    async fn run_hooks_async(
+        &self,
+        result: Result<Notarization, OperationError>,
+        completion: Completion,
+    ) -> Result<Notarization, OperationError> {
+        let op = self.clone();
+        blocking(move || op.run_hooks(result, &completion)).await?
+    }
+
+    /// The file to upload, and a previous submission of an identical file if there is one.
+    async fn input_file_async(
+        &self,
+        cancel: &CancellationToken,
+    ) -> Result<(InputFilePath, Option<PreviousSubmission>), OperationError> {
+        let op = self.clone();
+        cancel
+            .guard(blocking(move || {
+                let input_file = op.input_file()?;
+                let previous = op.previous_submission(&input_file);
+                Ok((input_file, previous))
+            }))
+            .await??
+    }
+
+    async fn upload_async(
+        &self,
+        input_file: InputFilePath,
+        completion: &mut Completion,
+        cancel: &CancellationToken,
+    ) -> Result<String, OperationError> {
+        self.report(Progress::Uploading);
+        // Not retried, since the service may have accepted the submission before failing.
+        let (backend, path, bundle_id) = (
+            self.backend.clone(),
+            input_file.path.clone(),
+            self.bundle_id.clone(),
+        );
+        let request_id = cancel
+            .guard(blocking(move || backend.submit(&path, &bundle_id)))
+            .await???;
+
+        let (op, id, mut submitted) = (self.clone(), request_id.clone(), completion.clone());
+        *completion = blocking(move || {
+            op.submitted(&id, &input_file, &mut submitted);
+            submitted
+        })
+        .await?;
+
+        Ok(request_id)
+    }
+
+    async fn complete_async(
+        &self,
+        request_id: &str,
+        completion: &mut Completion,
+        cancel: &CancellationToken,
+    ) -> Result<Notarization, OperationError> {
+        self.waiting(request_id, completion);
+
+        let started = Instant::now();
+        let mut backoff = self.poll_backoff();
+
+        let info = loop {
+            let delay = match self.poll_delay(&mut backoff, started) {
+                Some(delay) => delay,
+                None => return Err(self.timed_out(request_id, completion)),
+            };
+            cancel.guard(tokio::time::sleep(delay)).await?;
+
+            let id = request_id.to_string();
+            let info = self
+                .call_backend(cancel, move |backend| backend.status(&id))
+                .await?;
+            self.report(Progress::StatusChecked(info.status));
+
+            if info.status != NotarizationStatus::InProgress {
+                break info;
+            }
+        };
+
+        self.report(Progress::RequestingLog);
+        let id = request_id.to_string();
+        let log = self
+            .call_backend(cancel, move |backend| backend.log(&id))
+            .await?;
+
+        let notarization = self
+            .completed_async(request_id.into(), info, log, completion)
+            .await?;
+        self.staple_notarized_async(notarization, cancel).await
+    }
+
+    async fn reuse_async(
+        &self,
+        previous: PreviousSubmission,
+        completion: &mut Completion,
+        cancel: &CancellationToken,
+    ) -> Result<Notarization, OperationError> {
+        self.reused(&previous.request_id, completion);
+        let mut notarization = self
+            .completed_async(previous.request_id, previous.info, previous.log, completion)
+            .await?;
+        notarization.reused = true;
+
+        self.staple_notarized_async(notarization, cancel).await
+    }
+
+    /// `completed` on the blocking thread pool, as it writes to the journal.
+    async fn completed_async(
+        &self,
+        request_id: String,
+        info: SubmissionInfo,
+        log: SubmissionLog,
+        completion: &mut Completion,
+    ) -> Result<Notarization, OperationError> {
+        let (op, mut completed) = (self.clone(), completion.clone());
+        let (notarization, completed) = blocking(move || {
+            let notarization = op.completed(&request_id, info, log, &mut completed);
+            (notarization, completed)
+        })
+        .await?;
+
+        *completion = completed;
+        Ok(notarization)
+    }
+
+    async fn staple_notarized_async(
+        &self,
+        mut notarization: Notarization,
+        cancel: &CancellationToken,
+    ) -> Result<Notarization, OperationError> {
+        if !notarization.is_success() {
+            return Ok(notarization);
+        }
+
+        self.report(Progress::Stapling);
+        let op = self.clone();
+        cancel
+            .guard(blocking(move || op.staple_input()))
+            .await???;
+
+        notarization.stapled = true;
+        Ok(notarization)
+    }
+
+    /// Calls the backend on the blocking thread pool, retrying transient failures.
+    async fn call_backend<T, F>(
+        &self,
+        cancel: &CancellationToken,
+        call: F,
+    ) -> Result<T, OperationError>
+    where
+        T: Send + 'static,
+        F: Fn(&dyn NotaryBackend) -> Result<T, OperationError> + Clone + Send + 'static,
+    {
+        with_retry_async(cancel, || {
+            let (backend, call) = (self.backend.clone(), call.clone());
+            async move { blocking(move || call(&*backend)).await? }
+        })
+        .await
+    }
+}
+
+/// Runs `f` on the blocking thread pool.
+async fn blocking<T, F>(f: F) -> Result<T, OperationError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|err| OperationError::detail("Background task failed", &err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use crate::notarize::backend::{
+        NotaryBackend, SubmissionInfo, SubmissionLog, SubmissionSummary,
+    };
+    use crate::notarize::hooks::Hooks;
+    use crate::notarize::{CancellationToken, Journal, NotarizeOp, PollSettings, Progress};
+    use crate::util::input_path::PathType;
+    use crate::util::plist::structs::NotarizationStatus;
+    use crate::util::{ErrorKind, OperationError};
+
+    static REQUEST_UUID: &str = "c0dec0de-1234-5678-1234-b4d961a1d182";
+
+    /// Reports each status in turn, then the last one indefinitely.
+    struct FakeBackend {
+        statuses: Vec<NotarizationStatus>,
+        polls: Arc<AtomicUsize>,
+        /// Digest of the uploaded file reported with the status.
+        sha256: Option<String>,
+    }
+
+    impl NotaryBackend for FakeBackend {
+        fn submit(&self, _path: &Path, _bundle_id: &str) -> Result<String, OperationError> {
+            Ok(REQUEST_UUID.into())
+        }
+
+        fn status(&self, _request_id: &str) -> Result<SubmissionInfo, OperationError> {
+            let poll = self.polls.fetch_add(1, Ordering::SeqCst);
+            Ok(SubmissionInfo {
+                status: self.statuses[poll.min(self.statuses.len() - 1)],
+                status_message: None,
+                sha256: self.sha256.clone(),
+            })
+        }
+
+        fn log(&self, _request_id: &str) -> Result<SubmissionLog, OperationError> {
+            Ok(SubmissionLog {
+                url: None,
+                text: r#"{"status": "Invalid", "issues": null}"#.into(),
+            })
+        }
+
+        fn history(&self) -> Result<Vec<SubmissionSummary>, OperationError> {
+            Ok(vec![])
+        }
+    }
+
+    fn notarize_op(statuses: Vec<NotarizationStatus>) -> (NotarizeOp, Arc<AtomicUsize>) {
+        let polls = Arc::new(AtomicUsize::new(0));
+        let op = NotarizeOp {
+            input_path: PathBuf::from("Foo.pkg"),
+            path_type: PathType::InstallerPackage,
+            bundle_id: "Foo.pkg".into(),
+            backend: Arc::new(FakeBackend {
+                statuses,
+                polls: polls.clone(),
+                sha256: None,
+            }),
+            rezip: false,
+            poll: PollSettings {
+                initial_interval: Duration::from_millis(1),
+                max_interval: Duration::from_millis(1),
+                timeout: None,
+            },
+            hooks: Hooks::default(),
+            progress: None,
+            journal: None,
+        };
+
+        (op, polls)
+    }
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn test_run_async() {
+        let (mut op, _) = notarize_op(vec![
+            NotarizationStatus::InProgress,
+            NotarizationStatus::Invalid,
+        ]);
+        let progress = Arc::new(Mutex::new(vec![]));
+        let recorded = progress.clone();
+        op.progress = Some(Arc::new(move |step: &Progress| {
+            recorded.lock().unwrap().push(step.clone())
+        }));
+
+        let notarization = block_on(op.run_async(&CancellationToken::new())).unwrap();
+        assert_eq!(notarization.request_id, REQUEST_UUID);
+        assert_eq!(notarization.status, NotarizationStatus::Invalid);
+        assert!(!notarization.stapled);
+        assert!(!notarization.reused);
+        assert_eq!(
+            *progress.lock().unwrap(),
+            vec![
+                Progress::Uploading,
+                Progress::Submitted(REQUEST_UUID.into()),
+                Progress::StatusChecked(NotarizationStatus::InProgress),
+                Progress::StatusChecked(NotarizationStatus::Invalid),
+                Progress::RequestingLog,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_wait_async_cancelled() {
+        let (mut op, polls) = notarize_op(vec![NotarizationStatus::InProgress]);
+        let cancel = CancellationToken::new();
+        let token = cancel.clone();
+        op.progress = Some(Arc::new(move |_: &Progress| token.cancel()));
+
+        let err = block_on(op.wait_async(REQUEST_UUID, &cancel)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Cancelled);
+        assert_eq!(err.request_id(), Some(REQUEST_UUID));
+        assert_eq!(polls.load(Ordering::SeqCst), 1);
+
+        let err = block_on(op.submit_async(&cancel)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Cancelled);
+    }
+
+    #[test]
+    fn test_submit_async_already_notarized() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let input_path = temp_dir.path().join("Foo.pkg");
+        std::fs::write(&input_path, "Foo").unwrap();
+        let sha256 = crate::util::hash::sha256_file(&input_path).unwrap();
+
+        let journal = Journal::new(temp_dir.path().join("state"));
+        journal
+            .record_submitted("f00df00d", &input_path, Some(&sha256), "Foo.pkg")
+            .unwrap();
+        journal
+            .record_completed(
+                "f00df00d",
+                &input_path,
+                "Foo.pkg",
+                NotarizationStatus::Success,
+                "{}",
+            )
+            .unwrap();
+
+        let (mut op, _) = notarize_op(vec![NotarizationStatus::Success]);
+        op.input_path = input_path.clone();
+        op.journal = Some(journal);
+        let progress = Arc::new(Mutex::new(vec![]));
+        let recorded = progress.clone();
+        op.progress = Some(Arc::new(move |step: &Progress| {
+            recorded.lock().unwrap().push(step.clone())
+        }));
+
+        // Without a digest from the service, the journal alone is not trusted.
+        let request_id = block_on(op.submit_async(&CancellationToken::new())).unwrap();
+        assert_eq!(request_id, REQUEST_UUID);
+
+        progress.lock().unwrap().clear();
+        op.backend = Arc::new(FakeBackend {
+            statuses: vec![NotarizationStatus::Success],
+            polls: Arc::new(AtomicUsize::new(0)),
+            sha256: Some(sha256),
+        });
+        let request_id = block_on(op.submit_async(&CancellationToken::new())).unwrap();
+        assert_eq!(request_id, "f00df00d");
+        assert_eq!(
+            *progress.lock().unwrap(),
+            vec![Progress::AlreadyNotarized("f00df00d".into())]
+        );
+
+        progress.lock().unwrap().clear();
+        std::fs::write(&input_path, "Bar").unwrap();
+        let request_id = block_on(op.submit_async(&CancellationToken::new())).unwrap();
+        assert_eq!(request_id, REQUEST_UUID);
+        assert_eq!(
+            *progress.lock().unwrap(),
+            vec![
+                Progress::Uploading,
+                Progress::Submitted(REQUEST_UUID.into())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_async_op_is_send() {
+        fn assert_send<T: Send>(_: T) {}
+
+        let (op, _) = notarize_op(vec![NotarizationStatus::Invalid]);
+        let cancel = CancellationToken::new();
+        assert_send(op.run_async(&cancel));
+        assert_send(op.wait_async(REQUEST_UUID, &cancel));
+    }
+}
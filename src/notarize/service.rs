@@ -0,0 +1,42 @@
+use crate::util::OperationError;
+
+use super::backend::{self, NotaryBackend, SubmissionInfo, SubmissionLog, SubmissionSummary};
+use super::backoff::with_retry;
+use super::ServiceOptions;
+
+/// Connection to the notarization service, for looking up submissions independently of an
+/// input, e.g. one submitted by another process.
+pub struct Service {
+    backend: Box<dyn NotaryBackend>,
+}
+
+impl Service {
+    /// Fails if credentials are missing or, for `Backend::Api`, the API key cannot be read.
+    pub fn connect(options: ServiceOptions) -> Result<Self, OperationError> {
+        Ok(Service {
+            backend: backend::create(options)?,
+        })
+    }
+
+    /// Current status of a submission, retrying transient failures.
+    pub fn status(&self, request_id: &str) -> Result<SubmissionInfo, OperationError> {
+        with_retry(|| self.backend.status(request_id))
+    }
+
+    /// Service log of a completed submission, retrying transient failures.
+    pub fn log(&self, request_id: &str) -> Result<SubmissionLog, OperationError> {
+        with_retry(|| self.backend.log(request_id))
+    }
+
+    /// Recent submissions made with the developer account.
+    pub fn history(&self) -> Result<Vec<SubmissionSummary>, OperationError> {
+        self.backend.history()
+    }
+}
+
+impl SubmissionLog {
+    /// Issues listed in the log, grouped by file.
+    pub fn summary(&self) -> String {
+        super::log::render(&self.text)
+    }
+}
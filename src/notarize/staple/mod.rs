@@ -0,0 +1,188 @@
+//! Stapling of notarization tickets to bundles, disk images and installer packages, without
+//! relying on `xcrun stapler`.
+
+mod ticket;
+mod validate;
+
+#[cfg(test)]
+mod tests;
+
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::archive;
+use crate::codesign::{self, CodeSignature};
+use crate::util::input_path::PathType;
+use crate::util::plist::bundle_info_from_file;
+use crate::util::OperationError;
+pub(crate) use ticket::{TicketKey, TicketService};
+pub use validate::validate;
+
+/// Trailer following a ticket appended to an installer package.
+const PACKAGE_TRAILER_MAGIC: &[u8] = b"t8lr";
+const PACKAGE_TRAILER_LEN: usize = 12;
+const PACKAGE_TRAILER_VERSION: u16 = 1;
+const PACKAGE_TRAILER_TYPE_TICKET: u16 = 1;
+
+/// Retrieves the ticket for the bundle, disk image or installer package at `path` and attaches
+/// it, replacing any previously stapled ticket.
+pub(crate) fn staple(
+    path: &Path,
+    path_type: &PathType,
+    service: &TicketService,
+) -> Result<(), OperationError> {
+    let key = ticket_key(path, path_type)?;
+    let ticket = service.ticket(&key)?;
+
+    let error = |err: &dyn std::fmt::Display| {
+        OperationError::detail(
+            &format!("Could not staple ticket to {}", path.display()),
+            &err.to_string(),
+        )
+    };
+
+    match path_type {
+        PathType::AppBundle => {
+            std::fs::write(bundle_ticket_path(path), ticket).map_err(|err| error(&err))
+        }
+        PathType::DiskImage => {
+            let signature = disk_image_signature(path)?;
+            let signature = codesign::attach_ticket(&signature, &ticket)?;
+            archive::udif::replace_code_signature(path, &signature)
+        }
+        PathType::InstallerPackage => {
+            attach_package_ticket(path, &ticket).map_err(|err| error(&err))
+        }
+        PathType::ZipArchive => Err(unsupported()),
+    }
+}
+
+/// Key under which the ticket service stores the ticket for the input at `path`.
+pub(crate) fn ticket_key(path: &Path, path_type: &PathType) -> Result<TicketKey, OperationError> {
+    match path_type {
+        PathType::AppBundle => {
+            let executable = bundle_executable(path)?;
+            let signatures = codesign::read_signatures(&executable)?;
+            signatures
+                .iter()
+                .filter_map(|slice| slice.signature.as_ref())
+                .find_map(code_signature_key)
+                .ok_or_else(|| {
+                    OperationError::new(&format!("{} is not signed", executable.display()))
+                })
+        }
+        PathType::DiskImage => {
+            let signature = CodeSignature::parse(&disk_image_signature(path)?)?;
+            code_signature_key(&signature).ok_or_else(|| {
+                OperationError::new(&format!("{} has no code directory", path.display()))
+            })
+        }
+        PathType::InstallerPackage => {
+            let (algorithm, mut hash) = archive::package::toc_checksum(path)?;
+            let hash_type = match algorithm.as_str() {
+                "sha1" => 1,
+                "sha256" => 2,
+                other => {
+                    return Err(OperationError::new(&format!(
+                        "Unsupported installer package checksum {}",
+                        other
+                    )))
+                }
+            };
+            hash.truncate(20);
+
+            Ok(TicketKey { hash_type, hash })
+        }
+        PathType::ZipArchive => Err(unsupported()),
+    }
+}
+
+fn code_signature_key(signature: &CodeSignature) -> Option<TicketKey> {
+    let code_directory = signature.ticket_code_directory()?;
+
+    Some(TicketKey {
+        hash_type: code_directory.hash_type.value(),
+        hash: code_directory.cdhash.clone()?,
+    })
+}
+
+/// Location of a ticket stapled to a bundle.
+pub(crate) fn bundle_ticket_path(bundle: &Path) -> PathBuf {
+    bundle.join("Contents/CodeResources")
+}
+
+/// Main executable of a bundle, named by `CFBundleExecutable`, or after the bundle if absent.
+fn bundle_executable(bundle: &Path) -> Result<PathBuf, OperationError> {
+    let info = bundle_info_from_file(bundle.join("Contents/Info.plist"))?;
+    let name = match info.executable {
+        Some(name) => name,
+        None => bundle
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    };
+
+    Ok(bundle.join("Contents/MacOS").join(name))
+}
+
+fn disk_image_signature(path: &Path) -> Result<Vec<u8>, OperationError> {
+    archive::udif::code_signature(path)?
+        .ok_or_else(|| OperationError::new(&format!("Disk image {} is not signed", path.display())))
+}
+
+/// Appends `ticket` to an installer package, followed by a trailer locating it. A previously
+/// stapled ticket is removed first.
+fn attach_package_ticket(path: &Path, ticket: &[u8]) -> std::io::Result<()> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+
+    let len = unstapled_package_len(&mut file)?;
+    file.set_len(len)?;
+    file.seek(SeekFrom::Start(len))?;
+
+    file.write_all(ticket)?;
+    file.write_all(PACKAGE_TRAILER_MAGIC)?;
+    file.write_all(&PACKAGE_TRAILER_VERSION.to_le_bytes())?;
+    file.write_all(&PACKAGE_TRAILER_TYPE_TICKET.to_le_bytes())?;
+    file.write_all(&(ticket.len() as u32).to_le_bytes())
+}
+
+/// Ticket stapled to an installer package, if any.
+fn package_ticket(path: &Path) -> std::io::Result<Option<Vec<u8>>> {
+    let mut file = std::fs::File::open(path)?;
+
+    let len = file.metadata()?.len();
+    let ticket_start = unstapled_package_len(&mut file)?;
+    if ticket_start == len {
+        return Ok(None);
+    }
+
+    let mut ticket = vec![0; (len - ticket_start) as usize - PACKAGE_TRAILER_LEN];
+    file.seek(SeekFrom::Start(ticket_start))?;
+    file.read_exact(&mut ticket)?;
+    Ok(Some(ticket))
+}
+
+/// Length of the package without any stapled ticket.
+fn unstapled_package_len(file: &mut std::fs::File) -> std::io::Result<u64> {
+    let len = file.metadata()?.len();
+    if len < PACKAGE_TRAILER_LEN as u64 {
+        return Ok(len);
+    }
+
+    let mut trailer = [0; PACKAGE_TRAILER_LEN];
+    file.seek(SeekFrom::Start(len - PACKAGE_TRAILER_LEN as u64))?;
+    file.read_exact(&mut trailer)?;
+    if !trailer.starts_with(PACKAGE_TRAILER_MAGIC) {
+        return Ok(len);
+    }
+
+    let ticket_len = u32::from_le_bytes([trailer[8], trailer[9], trailer[10], trailer[11]]);
+    Ok(len
+        .saturating_sub(PACKAGE_TRAILER_LEN as u64)
+        .saturating_sub(ticket_len as u64))
+}
+
+fn unsupported() -> OperationError {
+    OperationError::new("Zip archives cannot be stapled. Staple the bundles they contain instead.")
+}
@@ -0,0 +1,351 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::path::{Path, PathBuf};
+use tiny_http::{Response, Server};
+
+use super::{TicketKey, TicketService};
+use crate::archive;
+use crate::codesign::{self, CodeSignature};
+use crate::precheck::tests::test_utils;
+use crate::util::input_path::PathType;
+use crate::util::ErrorKind;
+
+static TICKET: &[u8] = b"s8ch\x01\x00\x00\x00stand-in ticket";
+
+/// Stand-in for the ticket service, holding a ticket for a single record.
+fn ticket_service(record_name: String) -> TicketService {
+    let server = Server::http("127.0.0.1:0").unwrap();
+    let url = format!(
+        "http://{}/records/lookup",
+        server.server_addr().to_ip().unwrap()
+    );
+
+    std::thread::spawn(move || {
+        for mut request in server.incoming_requests() {
+            let mut body = vec![];
+            request.as_reader().read_to_end(&mut body).unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            let requested = body["records"][0]["recordName"].as_str().unwrap();
+
+            let record = if requested == record_name {
+                serde_json::json!({
+                    "recordName": requested,
+                    "recordType": "DeveloperIDTicket",
+                    "fields": { "signedTicket": { "type": "BYTES", "value": STANDARD.encode(TICKET) } }
+                })
+            } else {
+                serde_json::json!({
+                    "recordName": requested,
+                    "reason": "Record not found",
+                    "serverErrorCode": "NOT_FOUND"
+                })
+            };
+
+            let response = serde_json::json!({ "records": [record] }).to_string();
+            request.respond(Response::from_string(response)).unwrap();
+        }
+    });
+
+    TicketService::with_url(url)
+}
+
+/// Copies `path` to a temporary directory, to be modified by stapling.
+fn copy_artifact(path: &Path) -> (tempfile::TempDir, PathBuf) {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let copy = temp_dir.path().join(path.file_name().unwrap());
+    std::fs::copy(path, &copy).unwrap();
+    (temp_dir, copy)
+}
+
+#[test]
+fn test_record_name() {
+    let key = TicketKey {
+        hash_type: 2,
+        hash: vec![0xab; 20],
+    };
+    assert_eq!(key.record_name(), format!("2/2/{}", "ab".repeat(20)));
+}
+
+#[test]
+fn test_staple_bundle() {
+    let artifact = test_utils::artifact(PathType::AppBundle, "correctly_signed");
+    let key = super::ticket_key(&artifact.path, &PathType::AppBundle).unwrap();
+    assert_eq!(key.hash_type, 2);
+    assert_eq!(key.hash.len(), 20);
+
+    let service = ticket_service(key.record_name());
+    super::staple(&artifact.path, &PathType::AppBundle, &service).unwrap();
+
+    assert_eq!(
+        std::fs::read(super::bundle_ticket_path(&artifact.path)).unwrap(),
+        TICKET
+    );
+}
+
+#[test]
+fn test_staple_disk_image() {
+    let artifact = test_utils::artifact(PathType::DiskImage, "signed_with_correctly_signed_app");
+    let (_temp_dir, path) = copy_artifact(&artifact.path);
+
+    let key = super::ticket_key(&path, &PathType::DiskImage).unwrap();
+    let service = ticket_service(key.record_name());
+
+    // Stapling again replaces the ticket rather than adding another.
+    super::staple(&path, &PathType::DiskImage, &service).unwrap();
+    super::staple(&path, &PathType::DiskImage, &service).unwrap();
+
+    let signature = archive::udif::code_signature(&path).unwrap().unwrap();
+    let signature = CodeSignature::parse(&signature).unwrap();
+    assert_eq!(signature.ticket.as_deref(), Some(TICKET));
+    assert_eq!(super::ticket_key(&path, &PathType::DiskImage).unwrap(), key);
+
+    let extracted = archive::disk_image::extract_bundles(&path).unwrap();
+    assert_eq!(extracted.bundles.len(), 1);
+}
+
+#[test]
+fn test_staple_package() {
+    let artifact = test_utils::artifact(
+        PathType::InstallerPackage,
+        "signed_with_correctly_signed_app",
+    );
+    let (_temp_dir, path) = copy_artifact(&artifact.path);
+    let len = std::fs::metadata(&path).unwrap().len();
+
+    let key = super::ticket_key(&path, &PathType::InstallerPackage).unwrap();
+    assert_eq!(key.hash_type, 1);
+    let service = ticket_service(key.record_name());
+
+    super::staple(&path, &PathType::InstallerPackage, &service).unwrap();
+    super::staple(&path, &PathType::InstallerPackage, &service).unwrap();
+
+    let stapled = std::fs::read(&path).unwrap();
+    assert_eq!(
+        stapled.len() as u64,
+        len + TICKET.len() as u64 + super::PACKAGE_TRAILER_LEN as u64
+    );
+    assert!(stapled[len as usize..].starts_with(TICKET));
+    assert!(stapled[len as usize + TICKET.len()..].starts_with(b"t8lr"));
+
+    assert_eq!(
+        super::ticket_key(&path, &PathType::InstallerPackage).unwrap(),
+        key
+    );
+}
+
+#[test]
+fn test_ticket_not_found() {
+    let artifact = test_utils::artifact(PathType::AppBundle, "unsigned");
+    let service = ticket_service("2/2/00".into());
+
+    assert!(super::staple(&artifact.path, &PathType::AppBundle, &service).is_err());
+    assert!(!super::bundle_ticket_path(&artifact.path).exists());
+}
+
+/// DER element with `tag` and `contents`.
+fn der(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let len = contents.len();
+    let mut element = match len {
+        0..=0x7f => vec![tag, len as u8],
+        0x80..=0xff => vec![tag, 0x81, len as u8],
+        _ => vec![tag, 0x82, (len >> 8) as u8, len as u8],
+    };
+    element.extend(contents);
+    element
+}
+
+/// Ticket listing the hashes of `keys`, as if issued for them. Unless `signed`, the ticket
+/// has no signer information.
+fn ticket_covering(keys: &[&TicketKey], signed: bool) -> Vec<u8> {
+    const SEQUENCE: u8 = 0x30;
+    const SET: u8 = 0x31;
+    const INTEGER: u8 = 0x02;
+    const OCTET_STRING: u8 = 0x04;
+    const OID: u8 = 0x06;
+    const EXPLICIT: u8 = 0xa0;
+
+    let sha256 = der(
+        SEQUENCE,
+        &der(OID, &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01]),
+    );
+
+    let hashes: Vec<u8> = keys
+        .iter()
+        .flat_map(|key| {
+            der(
+                SEQUENCE,
+                &[der(INTEGER, &[key.hash_type]), der(OCTET_STRING, &key.hash)].concat(),
+            )
+        })
+        .collect();
+    let content = der(
+        SEQUENCE,
+        &[
+            der(OID, &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x01]),
+            der(EXPLICIT, &der(OCTET_STRING, &der(SEQUENCE, &hashes))),
+        ]
+        .concat(),
+    );
+
+    let signer_info = der(
+        SEQUENCE,
+        &[
+            der(INTEGER, &[1]),
+            der(SEQUENCE, &[der(SEQUENCE, &[]), der(INTEGER, &[1])].concat()),
+            sha256.clone(),
+            sha256.clone(),
+            der(OCTET_STRING, b"signature"),
+        ]
+        .concat(),
+    );
+    let signed_data = der(
+        SEQUENCE,
+        &[
+            der(INTEGER, &[1]),
+            der(SET, &sha256),
+            content,
+            der(SET, if signed { &signer_info } else { &[] }),
+        ]
+        .concat(),
+    );
+    let content_info = der(
+        SEQUENCE,
+        &[
+            der(OID, &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x02]),
+            der(EXPLICIT, &signed_data),
+        ]
+        .concat(),
+    );
+
+    [b"s8ch\x01\x00\x00\x00".as_slice(), &content_info].concat()
+}
+
+/// Ticket key of every architecture of each binary in `bundle`.
+fn bundle_keys(bundle: &Path) -> Vec<TicketKey> {
+    codesign::bundle_code(bundle)
+        .unwrap()
+        .iter()
+        .flat_map(|binary| codesign::read_signatures(binary).unwrap())
+        .filter_map(|slice| super::code_signature_key(slice.signature.as_ref()?))
+        .collect()
+}
+
+fn assert_invalid_ticket(result: Result<(), crate::util::OperationError>, message: &str) {
+    let err = result.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidTicket);
+    assert!(err.to_string().contains(message), "{}", err);
+}
+
+#[test]
+fn test_validate_bundle() {
+    let artifact = test_utils::artifact(PathType::AppBundle, "correctly_signed");
+    assert_invalid_ticket(
+        super::validate(&artifact.path, &PathType::AppBundle),
+        "No ticket is stapled",
+    );
+
+    let keys = bundle_keys(&artifact.path);
+    let keys: Vec<&TicketKey> = keys.iter().collect();
+    let ticket_path = super::bundle_ticket_path(&artifact.path);
+    std::fs::write(&ticket_path, ticket_covering(&keys, true)).unwrap();
+    super::validate(&artifact.path, &PathType::AppBundle).unwrap();
+
+    // A ticket for other code, e.g. the bundle before it was modified.
+    let key = super::ticket_key(&artifact.path, &PathType::AppBundle).unwrap();
+    let other = TicketKey {
+        hash_type: key.hash_type,
+        hash: vec![0; key.hash.len()],
+    };
+    std::fs::write(&ticket_path, ticket_covering(&[&other], true)).unwrap();
+    assert_invalid_ticket(
+        super::validate(&artifact.path, &PathType::AppBundle),
+        "does not cover",
+    );
+
+    // The hash alone, outside of a signed list, does not count.
+    std::fs::write(&ticket_path, ticket_covering(&[&key], false)).unwrap();
+    assert_invalid_ticket(
+        super::validate(&artifact.path, &PathType::AppBundle),
+        "not signed",
+    );
+    std::fs::write(&ticket_path, [TICKET, key.hash.as_slice()].concat()).unwrap();
+    assert_invalid_ticket(
+        super::validate(&artifact.path, &PathType::AppBundle),
+        "could not be parsed",
+    );
+
+    std::fs::write(&ticket_path, b"not a ticket").unwrap();
+    assert_invalid_ticket(
+        super::validate(&artifact.path, &PathType::AppBundle),
+        "not a notarization ticket",
+    );
+}
+
+#[test]
+fn test_validate_nested_code() {
+    let artifact = test_utils::artifact(PathType::AppBundle, "correctly_signed");
+    let other = test_utils::artifact(PathType::AppBundle, "no_hardened_runtime");
+    let keys = bundle_keys(&artifact.path);
+    let mut keys: Vec<&TicketKey> = keys.iter().collect();
+
+    let frameworks = artifact.path.join("Contents/Frameworks");
+    std::fs::create_dir(&frameworks).unwrap();
+    std::fs::copy(
+        other.path.join("Contents/MacOS/no_hardened_runtime"),
+        frameworks.join("libhelper.dylib"),
+    )
+    .unwrap();
+
+    // A ticket issued before the framework was added.
+    let ticket_path = super::bundle_ticket_path(&artifact.path);
+    std::fs::write(&ticket_path, ticket_covering(&keys, true)).unwrap();
+    assert_invalid_ticket(
+        super::validate(&artifact.path, &PathType::AppBundle),
+        "Contents/Frameworks/libhelper.dylib",
+    );
+
+    let nested_key = super::ticket_key(&other.path, &PathType::AppBundle).unwrap();
+    keys.push(&nested_key);
+    std::fs::write(&ticket_path, ticket_covering(&keys, true)).unwrap();
+    super::validate(&artifact.path, &PathType::AppBundle).unwrap();
+}
+
+#[test]
+fn test_validate_disk_image() {
+    let artifact = test_utils::artifact(PathType::DiskImage, "signed_with_correctly_signed_app");
+    let (_temp_dir, path) = copy_artifact(&artifact.path);
+    assert_invalid_ticket(
+        super::validate(&path, &PathType::DiskImage),
+        "No ticket is stapled",
+    );
+
+    let key = super::ticket_key(&path, &PathType::DiskImage).unwrap();
+    let signature = super::disk_image_signature(&path).unwrap();
+    let signature = codesign::attach_ticket(&signature, &ticket_covering(&[&key], true)).unwrap();
+    archive::udif::replace_code_signature(&path, &signature).unwrap();
+
+    super::validate(&path, &PathType::DiskImage).unwrap();
+}
+
+#[test]
+fn test_validate_package() {
+    let artifact = test_utils::artifact(
+        PathType::InstallerPackage,
+        "signed_with_correctly_signed_app",
+    );
+    let (_temp_dir, path) = copy_artifact(&artifact.path);
+    assert_invalid_ticket(
+        super::validate(&path, &PathType::InstallerPackage),
+        "No ticket is stapled",
+    );
+
+    let key = super::ticket_key(&path, &PathType::InstallerPackage).unwrap();
+    super::attach_package_ticket(&path, &ticket_covering(&[&key], true)).unwrap();
+    super::validate(&path, &PathType::InstallerPackage).unwrap();
+
+    super::attach_package_ticket(&path, TICKET).unwrap();
+    assert_invalid_ticket(
+        super::validate(&path, &PathType::InstallerPackage),
+        "could not be parsed",
+    );
+}
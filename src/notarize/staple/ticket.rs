@@ -0,0 +1,114 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use reqwest::blocking::Client;
+use std::fmt::Write;
+
+use crate::util::json;
+use crate::util::{ErrorKind, OperationError};
+
+static TICKET_SERVICE_URL: &str =
+    "https://api.apple-cloudkit.com/database/1/com.apple.gk.ticket-delivery/production/public/records/lookup";
+
+/// Identifies notarized code to the ticket service.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TicketKey {
+    /// CodeDirectory hash type of `hash`, e.g. 2 for SHA-256.
+    pub(crate) hash_type: u8,
+    /// CDHash of a bundle or disk image, or table of contents checksum of an installer package.
+    pub(crate) hash: Vec<u8>,
+}
+
+impl TicketKey {
+    pub(crate) fn record_name(&self) -> String {
+        let mut name = format!("2/{}/", self.hash_type);
+        for byte in &self.hash {
+            write!(name, "{:02x}", byte).unwrap();
+        }
+        name
+    }
+}
+
+/// Client for Apple's ticket lookup service, from which notarization tickets are retrieved
+/// by CDHash once a submission is accepted.
+pub(crate) struct TicketService {
+    url: String,
+    client: Client,
+}
+
+impl TicketService {
+    /// Uses `XCNOTARY_TICKET_SERVICE_URL` in place of Apple's service if set, e.g. to direct
+    /// lookups to a stand-in for the service when testing.
+    pub(crate) fn new() -> Self {
+        Self::with_url(
+            std::env::var("XCNOTARY_TICKET_SERVICE_URL")
+                .unwrap_or_else(|_| TICKET_SERVICE_URL.into()),
+        )
+    }
+
+    pub(crate) fn with_url(url: String) -> Self {
+        TicketService {
+            url,
+            client: Client::new(),
+        }
+    }
+
+    /// Retrieves the ticket for `key`. Fails if the service does not have a ticket, e.g. if
+    /// the code was not notarized.
+    pub(crate) fn ticket(&self, key: &TicketKey) -> Result<Vec<u8>, OperationError> {
+        let record_name = key.record_name();
+        let body = serde_json::json!({ "records": [{ "recordName": record_name }] });
+
+        let response = self
+            .client
+            .post(&self.url)
+            .json(&body)
+            .send()
+            .map_err(|err| {
+                OperationError::transient("Could not reach ticket service", &err.to_string())
+            })?;
+
+        let status = response.status();
+        let bytes = response.bytes().map_err(|err| {
+            OperationError::transient("Could not read ticket service response", &err.to_string())
+        })?;
+        if !status.is_success() {
+            let message = format!("HTTP {}\n{}", status, String::from_utf8_lossy(&bytes));
+            let error = OperationError::detail("Ticket service returned an error", &message);
+            return Err(if status.is_server_error() {
+                error.with_kind(ErrorKind::Network)
+            } else {
+                error
+            });
+        }
+
+        let record = json::ticket_lookup_response(&bytes)?
+            .records
+            .into_iter()
+            .find(|record| record.record_name == record_name)
+            .ok_or_else(|| {
+                OperationError::detail(
+                    "Unexpected response from ticket service",
+                    &String::from_utf8_lossy(&bytes),
+                )
+                .with_kind(ErrorKind::MalformedResponse)
+            })?;
+
+        let fields = match record.fields {
+            Some(fields) => fields,
+            None => {
+                return Err(OperationError::detail(
+                    &format!("No notarization ticket found for {}", record_name),
+                    &record
+                        .reason
+                        .or(record.server_error_code)
+                        .unwrap_or_default(),
+                ))
+            }
+        };
+
+        STANDARD.decode(fields.signed_ticket.value).map_err(|err| {
+            OperationError::detail("Malformed notarization ticket", &err.to_string())
+                .with_kind(ErrorKind::MalformedResponse)
+        })
+    }
+}
@@ -0,0 +1,222 @@
+use console::Style;
+use std::path::Path;
+
+use super::{code_signature_key, ticket_key, TicketKey};
+use crate::archive;
+use crate::codesign::der::{Reader, TAG_INTEGER, TAG_OCTET_STRING, TAG_SEQUENCE};
+use crate::codesign::{self, CodeSignature, SignedData};
+use crate::output;
+use crate::util::input_path::PathType;
+use crate::util::{ErrorKind, OperationError};
+
+const TICKET_MAGIC: &[u8] = b"s8ch";
+/// Magic followed by a little-endian format version.
+const TICKET_HEADER_LEN: usize = 8;
+
+/// Notarization ticket stapled to a bundle, disk image or installer package.
+#[derive(Debug)]
+pub(crate) struct Ticket {
+    /// Code the ticket was issued for.
+    keys: Vec<TicketKey>,
+}
+
+impl Ticket {
+    /// Parses a ticket: its header, followed by a CMS SignedData structure whose content is a
+    /// SEQUENCE of (INTEGER hash type, OCTET STRING CDHash) pairs. Fails if the ticket cannot be
+    /// parsed or is not signed.
+    pub(crate) fn parse(data: &[u8]) -> Result<Self, OperationError> {
+        if data.len() <= TICKET_HEADER_LEN || !data.starts_with(TICKET_MAGIC) {
+            return Err(
+                OperationError::new("Stapled ticket is not a notarization ticket")
+                    .with_kind(ErrorKind::InvalidTicket),
+            );
+        }
+
+        let malformed = |err: &dyn std::fmt::Display| {
+            OperationError::detail("Stapled ticket could not be parsed", &err.to_string())
+                .with_kind(ErrorKind::InvalidTicket)
+        };
+
+        let signed_data =
+            SignedData::parse(&data[TICKET_HEADER_LEN..]).map_err(|err| malformed(&err))?;
+        if !signed_data.has_signer {
+            return Err(OperationError::new("Stapled ticket is not signed")
+                .with_kind(ErrorKind::InvalidTicket));
+        }
+
+        let content = signed_data
+            .content
+            .ok_or_else(|| malformed(&"The ticket does not list any code"))?;
+        let keys = parse_keys(&content).map_err(|err| malformed(&err))?;
+
+        Ok(Ticket { keys })
+    }
+
+    /// True if the ticket lists the hash of `key`.
+    pub(crate) fn covers(&self, key: &TicketKey) -> bool {
+        self.keys.contains(key)
+    }
+}
+
+fn parse_keys(content: &[u8]) -> Result<Vec<TicketKey>, OperationError> {
+    let mut list = Reader::new(content).expect(TAG_SEQUENCE)?.reader();
+
+    let mut keys = vec![];
+    while !list.is_empty() {
+        let mut entry = list.expect(TAG_SEQUENCE)?.reader();
+        let hash_type = match entry.expect(TAG_INTEGER)?.contents {
+            [hash_type] => *hash_type,
+            _ => return Err(OperationError::new("Invalid hash type")),
+        };
+        let hash = entry.expect(TAG_OCTET_STRING)?.contents.to_vec();
+        keys.push(TicketKey { hash_type, hash });
+    }
+
+    Ok(keys)
+}
+
+/// Code whose current hash must be listed by the stapled ticket.
+struct CodeItem {
+    description: String,
+    /// Hash of each code directory, any of which the ticket may list. The first is shown in
+    /// the output. Empty if the code is unsigned.
+    keys: Vec<TicketKey>,
+}
+
+/// Verifies, without contacting the ticket service, that a ticket is stapled to the input at
+/// `path` and that it covers the input's current code, e.g. that a bundle was not modified
+/// after notarization.
+pub fn validate(path: &Path, path_type: &PathType) -> Result<(), OperationError> {
+    let ticket = stapled_ticket(path, path_type)?.ok_or_else(|| {
+        OperationError::new(&format!("No ticket is stapled to {}", path.display()))
+            .with_kind(ErrorKind::InvalidTicket)
+    })?;
+    let ticket = Ticket::parse(&ticket)?;
+
+    let mut mismatches = vec![];
+    for item in code_items(path, path_type)? {
+        let covered = item.keys.iter().any(|key| ticket.covers(key));
+        let (mark, style) = if covered {
+            ("✔", Style::new().green())
+        } else {
+            ("✘", Style::new().red().bold())
+        };
+
+        let line = match item.keys.first() {
+            Some(key) => format!("{} {}", item.description, key.record_name()),
+            None => format!("{} (unsigned)", item.description),
+        };
+        if output::is_text() {
+            println!("{} {}", style.apply_to(mark), line);
+        }
+        if !covered {
+            mismatches.push(line);
+        }
+    }
+
+    if !mismatches.is_empty() {
+        return Err(OperationError::detail(
+            &format!("Stapled ticket does not cover {}", path.display()),
+            &format!(
+                "The following code was modified after notarization, or was not notarized:\n{}",
+                mismatches.join("\n")
+            ),
+        )
+        .with_kind(ErrorKind::InvalidTicket));
+    }
+
+    if output::is_text() {
+        println!(
+            "\n{}",
+            Style::new()
+                .green()
+                .bold()
+                .apply_to("Stapled ticket is valid.")
+        );
+    }
+
+    Ok(())
+}
+
+/// Raw ticket stapled to the input at `path`, if any.
+fn stapled_ticket(path: &Path, path_type: &PathType) -> Result<Option<Vec<u8>>, OperationError> {
+    let error = |err: std::io::Error| {
+        OperationError::detail(
+            &format!("Could not read stapled ticket from {}", path.display()),
+            &err.to_string(),
+        )
+    };
+
+    match path_type {
+        PathType::AppBundle => {
+            let ticket_path = super::bundle_ticket_path(path);
+            if !ticket_path.exists() {
+                return Ok(None);
+            }
+            std::fs::read(ticket_path).map(Some).map_err(error)
+        }
+        PathType::DiskImage => match archive::udif::code_signature(path)? {
+            Some(signature) => Ok(CodeSignature::parse(&signature)?.ticket),
+            None => Ok(None),
+        },
+        PathType::InstallerPackage => super::package_ticket(path).map_err(error),
+        PathType::ZipArchive => Err(OperationError::new(
+            "Zip archives cannot be stapled. Validate the bundles they contain instead.",
+        )),
+    }
+}
+
+/// Code covered by a ticket for the input at `path`: every architecture of each Mach-O binary
+/// in a bundle, including nested code, or the disk image or installer package itself.
+fn code_items(path: &Path, path_type: &PathType) -> Result<Vec<CodeItem>, OperationError> {
+    match path_type {
+        PathType::AppBundle => {
+            let mut items = vec![];
+            for binary in codesign::bundle_code(path)? {
+                let display_path = binary
+                    .strip_prefix(path)
+                    .unwrap_or(&binary)
+                    .display()
+                    .to_string();
+
+                items.extend(
+                    codesign::read_signatures(&binary)?
+                        .iter()
+                        .map(|slice| CodeItem {
+                            description: format!("{} ({})", display_path, slice.arch),
+                            keys: slice.signature.as_ref().map_or(vec![], code_signature_keys),
+                        }),
+                );
+            }
+            Ok(items)
+        }
+        _ => Ok(vec![CodeItem {
+            description: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            keys: vec![ticket_key(path, path_type)?],
+        }]),
+    }
+}
+
+/// Hash of each code directory of `signature`, starting with the one the ticket is looked up by.
+fn code_signature_keys(signature: &CodeSignature) -> Vec<TicketKey> {
+    let mut keys: Vec<TicketKey> = code_signature_key(signature).into_iter().collect();
+    for directory in signature
+        .code_directory
+        .iter()
+        .chain(&signature.alternate_code_directories)
+    {
+        if let Some(hash) = &directory.cdhash {
+            let key = TicketKey {
+                hash_type: directory.hash_type.value(),
+                hash: hash.clone(),
+            };
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+    }
+    keys
+}
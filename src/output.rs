@@ -0,0 +1,54 @@
+//! What the library prints to the terminal as it runs: progress, the outcome of each check, and
+//! failures that do not affect the result. Nothing is printed until a format is set.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// Progress and results as styled text.
+    Text,
+    /// Only warnings, without styling, leaving standard output to a document describing the
+    /// results.
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("Unknown output format: {}", s)),
+        }
+    }
+}
+
+static TEXT: AtomicBool = AtomicBool::new(false);
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Selects the output format. Nothing is printed until a format is set, so that library use is
+/// silent unless requested.
+pub fn set_format(format: OutputFormat) {
+    TEXT.store(format == OutputFormat::Text, Ordering::Relaxed);
+    JSON.store(format == OutputFormat::Json, Ordering::Relaxed);
+
+    if format == OutputFormat::Json {
+        // Messages embedded in the document must not contain escape sequences.
+        console::set_colors_enabled(false);
+    }
+}
+
+/// True if progress and results should be printed as styled text.
+pub fn is_text() -> bool {
+    TEXT.load(Ordering::Relaxed)
+}
+
+/// Prints a failure that does not affect the outcome of the operation, e.g. of a webhook.
+pub fn warn(message: &dyn fmt::Display) {
+    if is_text() || JSON.load(Ordering::Relaxed) {
+        eprintln!("\n{}", message);
+    }
+}
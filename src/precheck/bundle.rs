@@ -1,68 +1,174 @@
 use std::error::Error;
-use std::path::PathBuf;
-use std::process::Command;
+use std::path::{Path, PathBuf};
 
 use super::util::passes_spctl;
 use super::Status;
-use crate::util::OperationError;
+use crate::codesign::{self, read_signatures, ArchSignature, CodeSignature};
+
+/// Mach-O binary within the bundle: the main executable, or nested code such as frameworks,
+/// helpers, XPC services, plug-ins and loose dylibs.
+struct CodeItem {
+    /// Path relative to the bundle, e.g. Contents/Frameworks/Foo.framework/Versions/A/Foo.
+    display_path: String,
+    signatures: Vec<ArchSignature>,
+}
+
+/// Reads the code signature of every Mach-O binary in the bundle, or of the binary itself if
+/// `input_path` is a file, e.g. a command line tool installed by a package.
+fn bundle_code(input_path: &Path) -> Result<Vec<CodeItem>, Box<dyn Error>> {
+    if input_path.is_file() {
+        return Ok(vec![CodeItem {
+            display_path: input_path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into(),
+            signatures: read_signatures(input_path)?,
+        }]);
+    }
+
+    codesign::bundle_code(input_path)?
+        .into_iter()
+        .map(|path| {
+            Ok(CodeItem {
+                display_path: path
+                    .strip_prefix(input_path)
+                    .unwrap_or(&path)
+                    .display()
+                    .to_string(),
+                signatures: read_signatures(&path)?,
+            })
+        })
+        .collect()
+}
+
+/// Lists code for which `predicate` does not hold, naming the failing architectures if not all
+/// of them fail. The predicate receives `None` for unsigned architectures.
+fn failing_code<F>(items: &[CodeItem], predicate: F) -> Vec<String>
+where
+    F: Fn(Option<&CodeSignature>) -> bool,
+{
+    items
+        .iter()
+        .filter_map(|item| {
+            let failing: Vec<&str> = item
+                .signatures
+                .iter()
+                .filter(|slice| !predicate(slice.signature.as_ref()))
+                .map(|slice| slice.arch.as_str())
+                .collect();
+
+            if failing.is_empty() {
+                None
+            } else if failing.len() < item.signatures.len() {
+                Some(format!("{} ({})", item.display_path, failing.join(", ")))
+            } else {
+                Some(item.display_path.clone())
+            }
+        })
+        .collect()
+}
+
+fn fail_listing(message: &str, failing: &[String], solution: &str) -> Status {
+    Status::fail_with(
+        &format!("{}\n   {}", message, failing.join("\n   ")),
+        solution,
+        None,
+    )
+}
 
 pub(super) struct DeveloperIdCheck;
 
 impl super::Precheck for DeveloperIdCheck {
+    fn id(&self) -> &'static str {
+        "developer_id"
+    }
+
     fn display(&self) -> &'static str {
         "Developer ID signing"
     }
 
     fn run(&self, input_path: &PathBuf) -> Result<Status, Box<dyn Error>> {
-        if passes_spctl(&vec!["-t", "exec"], input_path)? {
-            Ok(Status::Pass)
-        } else {
-            Ok(Status::fail_with(
+        let items = bundle_code(input_path)?;
+
+        let mut failing = vec![];
+        for item in &items {
+            for slice in &item.signatures {
+                let certificate = slice
+                    .signature
+                    .as_ref()
+                    .and_then(|signature| signature.signed_data.as_ref())
+                    .and_then(|signed_data| signed_data.signing_certificate());
+
+                match certificate {
+                    Some(certificate) if certificate.is_developer_id_application() => {}
+                    Some(certificate) => failing.push(format!(
+                        r#"{} ({}): signed with "{}""#,
+                        item.display_path,
+                        slice.arch,
+                        certificate
+                            .common_name
+                            .as_deref()
+                            .unwrap_or("unknown certificate")
+                    )),
+                    None => failing.push(format!(
+                        "{} ({}): not signed with a certificate",
+                        item.display_path, slice.arch
+                    )),
+                }
+            }
+        }
+
+        if !failing.is_empty() {
+            return Ok(fail_listing(
+                "Bundle includes code not signed with a Developer ID Application certificate:",
+                &failing,
+                "Make sure CODE_SIGN_IDENTITY was specified during the build, and that nested code is signed before the bundle containing it.",
+            ));
+        }
+
+        // Gatekeeper assessment additionally covers sealed resources and certificate validity,
+        // but is only available on macOS, and only applies to bundles.
+        if cfg!(target_os = "macos")
+            && input_path.is_dir()
+            && !passes_spctl(&["-t", "exec"], input_path)?
+        {
+            return Ok(Status::fail_with(
                 "Bundle is not signed with a Developer ID certificate or it includes unsigned binaries.",
                 "Make sure CODE_SIGN_IDENTITY was specified during the build.",
-                None
-            ))
+                None,
+            ));
         }
+
+        Ok(Status::Pass)
     }
 }
 
 pub(super) struct HardenedRuntimeCheck;
 
 impl super::Precheck for HardenedRuntimeCheck {
+    fn id(&self) -> &'static str {
+        "hardened_runtime"
+    }
+
     fn display(&self) -> &'static str {
         "Hardened runtime"
     }
 
     fn run(&self, input_path: &PathBuf) -> Result<Status, Box<dyn Error>> {
-        let output = Command::new("/usr/bin/codesign")
-            .args(&["--display", "--verbose"])
-            .arg(input_path.as_os_str())
-            .output()?;
-
-        // unfortunately, codesign sends output to stderr.
-        let stderr = String::from_utf8(output.stderr).unwrap();
-        if !output.status.success() {
-            return Err(OperationError::new(&stderr).into());
-        }
+        let failing = failing_code(&bundle_code(input_path)?, |signature| {
+            signature
+                .and_then(|signature| signature.code_directory.as_ref())
+                .is_some_and(|cd| cd.has_hardened_runtime())
+        });
 
-        let code_directory_line = stderr
-            .lines()
-            .find(|s: &&str| s.starts_with("CodeDirectory"))
-            .unwrap()
-            .to_string();
-        let flags_text = code_directory_line
-            .split_ascii_whitespace()
-            .into_iter()
-            .find(|s: &&str| s.starts_with("flags"))
-            .unwrap();
-
-        if flags_text.contains("runtime") {
+        if failing.is_empty() {
             Ok(Status::Pass)
         } else {
-            Ok(Status::fail_with(
-                "Bundle does not have hardened runtime enabled.",
+            Ok(fail_listing(
+                "Bundle includes code without hardened runtime enabled:",
+                &failing,
                 r#"codesign using --options runtime flag, or pass OTHER_CODE_SIGN_FLAGS=--runtime to xcodebuild. You can also enable the "Hardened Runtime" capability in Xcode's target settings > "Signing and Capabilities""#,
-                None,
             ))
         }
     }
@@ -71,64 +177,75 @@ impl super::Precheck for HardenedRuntimeCheck {
 pub(super) struct NoGetTaskAllowCheck;
 
 impl super::Precheck for NoGetTaskAllowCheck {
+    fn id(&self) -> &'static str {
+        "get_task_allow"
+    }
+
     fn display(&self) -> &'static str {
         "No get-task-allow entitlement"
     }
 
     fn run(&self, input_path: &PathBuf) -> Result<Status, Box<dyn Error>> {
-        let output = Command::new("/usr/bin/codesign")
-            .args(&["-d", "--entitlements", ":-"])
-            .arg(input_path.as_os_str())
-            .output()?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8(output.stderr).unwrap();
-            return Err(OperationError::new(&stderr).into());
+        let items = bundle_code(input_path)?;
+        let entitlements = |signature: Option<&CodeSignature>| {
+            signature
+                .and_then(|signature| signature.entitlements.as_ref())
+                .map(|entitlements| crate::util::plist::bundle_entitlemens(entitlements))
+        };
+
+        let unreadable = failing_code(&items, |signature| {
+            !matches!(entitlements(signature), Some(Err(_)))
+        });
+        if !unreadable.is_empty() {
+            return Ok(fail_listing(
+                "Entitlements could not be read for:",
+                &unreadable,
+                "Check that the entitlements passed to codesign are a valid property list.",
+            ));
         }
 
-        if !output.stdout.is_empty() {
-            if let Some(true) =
-                crate::util::plist::bundle_entitlemens(&output.stdout).get_task_allow
-            {
-                return Ok(Status::fail_with(
-                    "Bundle includes get-task-allow entitlement.",
-                    "Specify CODE_SIGN_INJECT_BASE_ENTITLEMENTS=NO when running xcodebuild.",
-                    None,
-                ));
-            }
-        }
+        let failing = failing_code(&items, |signature| match entitlements(signature) {
+            Some(Ok(entitlements)) => entitlements.get_task_allow != Some(true),
+            _ => true,
+        });
 
-        Ok(Status::Pass)
+        if failing.is_empty() {
+            Ok(Status::Pass)
+        } else {
+            Ok(fail_listing(
+                "Bundle includes code with the get-task-allow entitlement:",
+                &failing,
+                "Specify CODE_SIGN_INJECT_BASE_ENTITLEMENTS=NO when running xcodebuild.",
+            ))
+        }
     }
 }
 
 pub(super) struct SecureTimestampCheck;
 
 impl super::Precheck for SecureTimestampCheck {
+    fn id(&self) -> &'static str {
+        "secure_timestamp"
+    }
+
     fn display(&self) -> &'static str {
         "Secure timestamp"
     }
 
     fn run(&self, input_path: &PathBuf) -> Result<Status, Box<dyn Error>> {
-        let output = Command::new("/usr/bin/codesign")
-            .arg("-dvv")
-            .arg(input_path.as_os_str())
-            .output()?;
-
-        // unfortunately, codesign sends successful output to stderr.
-        let stderr = String::from_utf8(output.stderr).unwrap();
-        if !output.status.success() {
-            return Err(OperationError::new(&stderr).into());
-        }
+        let failing = failing_code(&bundle_code(input_path)?, |signature| {
+            signature
+                .and_then(|signature| signature.signed_data.as_ref())
+                .is_some_and(|signed_data| signed_data.has_timestamp)
+        });
 
-        // Note: Presence of "Signed Time" would also indicate that a secure timestamp is missing
-        if stderr.contains("Timestamp=") {
+        if failing.is_empty() {
             Ok(Status::Pass)
         } else {
-            Ok(Status::fail_with(
-                r#"The bundle is missing a secure timestamp."#,
+            Ok(fail_listing(
+                "Bundle includes code missing a secure timestamp:",
+                &failing,
                 "codesign using --timestamp flag, or pass OTHER_CODE_SIGN_FLAGS=--timestamp to xcodebuild.",
-             None
             ))
         }
     }
@@ -4,27 +4,72 @@ use std::error::Error;
 use std::path::PathBuf;
 
 use super::util::passes_spctl;
+use crate::archive::udif;
+use crate::codesign::CodeSignature;
 
 pub(super) struct DeveloperIdCheck;
 
 impl super::Precheck for DeveloperIdCheck {
+    fn id(&self) -> &'static str {
+        "developer_id"
+    }
+
     fn display(&self) -> &'static str {
         "Developer ID signing"
     }
 
     fn run(&self, input_path: &PathBuf) -> Result<Status, Box<dyn Error>> {
+        let solution = r#"Run codesign -s "Developer ID Application: <team>" <dmg_path> to sign."#;
+
+        let signature = match udif::code_signature(input_path)? {
+            Some(signature) => Some(CodeSignature::parse(&signature)?),
+            None => None,
+        };
+        let certificate = signature
+            .as_ref()
+            .and_then(|signature| signature.signed_data.as_ref())
+            .and_then(|signed_data| signed_data.signing_certificate());
+
+        match certificate {
+            Some(certificate) if certificate.is_developer_id_application() => {}
+            Some(certificate) => {
+                return Ok(Status::fail_with(
+                    &format!(
+                        r#"Disk image is not signed with a Developer ID certificate, but with "{}"."#,
+                        certificate
+                            .common_name
+                            .as_deref()
+                            .unwrap_or("unknown certificate")
+                    ),
+                    solution,
+                    None,
+                ))
+            }
+            None => {
+                return Ok(Status::fail_with(
+                    "Disk image is not signed with a Developer ID certificate.",
+                    solution,
+                    None,
+                ))
+            }
+        }
+
+        // Gatekeeper assessment additionally covers certificate validity, but is only
+        // available on macOS.
         // https://developer.apple.com/library/archive/technotes/tn2206/_index.html#//apple_ref/doc/uid/DTS40007919-CH1-TNTAG18
-        if passes_spctl(
-            &vec!["-t", "open", "--context", "context:primary-signature"],
-            input_path,
-        )? {
-            Ok(Status::Pass)
-        } else {
-            Ok(Status::fail_with(
+        if cfg!(target_os = "macos")
+            && !passes_spctl(
+                &["-t", "open", "--context", "context:primary-signature"],
+                input_path,
+            )?
+        {
+            return Ok(Status::fail_with(
                 "Disk image is not signed with a Developer ID certificate.",
-                r#"Run codesign -s "Developer ID Application: <team>" <dmg_path> to sign."#,
+                solution,
                 None,
-            ))
+            ));
         }
+
+        Ok(Status::Pass)
     }
 }
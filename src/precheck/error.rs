@@ -2,9 +2,12 @@ use console::Style;
 use std::error::Error;
 use std::fmt;
 
+use crate::output;
+
 static DEFAULT_HELP_URL:&str = "https://developer.apple.com/documentation/xcode/notarizing_macos_software_before_distribution/resolving_common_notarization_issues";
 
-pub(crate) enum Status {
+/// Result of a single check.
+pub enum Status {
     Pass,
     Fail {
         message: String,
@@ -38,13 +41,32 @@ impl Status {
     }
 }
 
+/// A failed check, describing the issue and how to resolve it.
 #[derive(Debug, Clone)]
-pub(crate) struct PrecheckError {
+pub struct PrecheckError {
     message: String,
     solution: String,
     see_also: Option<String>,
 }
 
+impl PrecheckError {
+    /// Description of the issue.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// How to resolve the issue.
+    pub fn solution(&self) -> &str {
+        &self.solution
+    }
+
+    /// Names the bundle the failure applies to, for input that may contain several bundles.
+    pub(super) fn in_bundle(mut self, bundle: &str) -> Self {
+        self.message = format!("{}: {}", bundle, self.message);
+        self
+    }
+}
+
 impl std::fmt::Display for PrecheckError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let heading_style = Style::new().white().bold();
@@ -73,3 +95,133 @@ impl std::fmt::Display for PrecheckError {
 }
 
 impl Error for PrecheckError {}
+
+/// Outcome of a single check, as listed by [`PrecheckReport::results`].
+#[derive(Debug, Clone)]
+pub enum CheckOutcome {
+    Pass,
+    Fail(PrecheckError),
+    /// Not applicable to the input, e.g. bundle-only checks for a .pkg.
+    Skip,
+}
+
+/// Outcome of a check of the input, or of one of the bundles it contains.
+#[derive(Debug, Clone)]
+pub struct CheckResult {
+    /// Display name of the check.
+    pub check: &'static str,
+    /// Name of the bundle checked, for input that may contain several.
+    pub bundle: Option<String>,
+    pub outcome: CheckOutcome,
+}
+
+/// Results of the checks performed, up to the first failure when stopping at it.
+#[derive(Debug)]
+pub struct PrecheckReport {
+    results: Vec<CheckResult>,
+    fail_fast: bool,
+}
+
+impl PrecheckReport {
+    pub(super) fn new(fail_fast: bool) -> Self {
+        PrecheckReport {
+            results: vec![],
+            fail_fast,
+        }
+    }
+
+    /// Adds the outcome of a check of `bundle`, if given, printing it unless stopping at the
+    /// first failure, whose progress is shown instead.
+    pub(super) fn add(&mut self, check: &'static str, bundle: Option<&str>, outcome: CheckOutcome) {
+        let result = CheckResult {
+            check,
+            bundle: bundle.map(String::from),
+            outcome,
+        };
+        if self.fail_fast && !matches!(result.outcome, CheckOutcome::Skip) {
+            self.results.push(result);
+            return;
+        }
+
+        let (mark, style) = match result.outcome {
+            CheckOutcome::Pass => ("✔", Style::new().green()),
+            CheckOutcome::Fail(_) => ("✘", Style::new().red().bold()),
+            CheckOutcome::Skip => ("-", Style::new().dim()),
+        };
+        let bundle = match bundle {
+            Some(bundle) => format!(" ({})", bundle),
+            None => String::new(),
+        };
+        let suffix = match result.outcome {
+            CheckOutcome::Skip => " (skipped)",
+            _ => "",
+        };
+        if output::is_text() {
+            println!("{} {}{}{}", style.apply_to(mark), check, bundle, suffix);
+        }
+
+        self.results.push(result);
+    }
+
+    fn count(&self, matches: fn(&CheckOutcome) -> bool) -> usize {
+        self.results
+            .iter()
+            .filter(|result| matches(&result.outcome))
+            .count()
+    }
+
+    /// Outcome of each check, in the order they were performed.
+    pub fn results(&self) -> &[CheckResult] {
+        &self.results
+    }
+
+    pub fn has_failures(&self) -> bool {
+        self.count(|outcome| matches!(outcome, CheckOutcome::Fail(_))) > 0
+    }
+
+    /// Fails if any check failed: with the first failure when stopping at it, otherwise with
+    /// the report of all failures.
+    pub fn into_result(self) -> Result<(), Box<dyn Error>> {
+        if !self.fail_fast {
+            return match self.has_failures() {
+                true => Err(self.into()),
+                false => Ok(()),
+            };
+        }
+
+        let first = self
+            .results
+            .into_iter()
+            .find_map(|result| match result.outcome {
+                CheckOutcome::Fail(error) => Some(error),
+                _ => None,
+            });
+        match first {
+            Some(error) => Err(error.into()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::fmt::Display for PrecheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let error_style = Style::new().red().bold();
+
+        for result in &self.results {
+            if let CheckOutcome::Fail(error) = &result.outcome {
+                write!(f, "{}\n\n", error)?;
+            }
+        }
+
+        write!(
+            f,
+            "{} {} passed, {} failed, {} skipped",
+            error_style.apply_to("Pre-notarization checks:"),
+            self.count(|outcome| matches!(outcome, CheckOutcome::Pass)),
+            self.count(|outcome| matches!(outcome, CheckOutcome::Fail(_))),
+            self.count(|outcome| matches!(outcome, CheckOutcome::Skip)),
+        )
+    }
+}
+
+impl Error for PrecheckReport {}
@@ -1,61 +1,220 @@
 mod bundle;
-mod package;
-mod util;
-use console::Style;
 mod dmg;
 mod error;
+mod package;
+mod util;
 
 #[cfg(test)]
-mod tests;
+pub(crate) mod tests;
 
 use std::error::Error;
 use std::path::PathBuf;
 
+use crate::archive;
+use crate::output;
 use crate::util::display::progress_bar;
 use crate::util::input_path::PathType;
+use crate::util::OperationError;
 
-pub(self) use error::Status;
+pub use error::{CheckOutcome, CheckResult, PrecheckError, PrecheckReport, Status};
 
-pub(crate) trait Precheck {
+/// Identifiers of the checks, accepted by `--skip-check`.
+pub const CHECK_IDS: &[&str] = &[
+    "developer_id",
+    "hardened_runtime",
+    "get_task_allow",
+    "secure_timestamp",
+];
+
+/// A check of the input's code signature, catching issues the notarization service would reject.
+pub trait Precheck {
+    /// One of `CHECK_IDS`.
+    fn id(&self) -> &'static str;
     fn display(&self) -> &'static str;
     fn run(&self, input_path: &PathBuf) -> Result<Status, Box<dyn Error>>;
 }
 
-pub(crate) fn run(
+/// Whether `err` reports failed checks, rather than a failure to perform them.
+pub fn is_check_failure(err: &(dyn Error + 'static)) -> bool {
+    err.is::<PrecheckError>() || err.is::<PrecheckReport>()
+}
+
+/// Checks applicable to an application bundle, in the order they are run.
+pub fn bundle_checks() -> Vec<Box<dyn Precheck>> {
+    vec![
+        Box::new(bundle::DeveloperIdCheck),
+        Box::new(bundle::HardenedRuntimeCheck),
+        Box::new(bundle::NoGetTaskAllowCheck),
+        Box::new(bundle::SecureTimestampCheck),
+    ]
+}
+
+/// Runs the checks applicable to the input, except those identified in `skip`, and returns
+/// their results. With `fail_fast`, stops at the first failure. Fails only if the checks could
+/// not be performed; use [`PrecheckReport::into_result`] to fail if a check failed.
+pub fn run(
     path: &PathBuf,
     path_type: &PathType,
-    show_message: bool,
-) -> Result<(), Box<dyn Error>> {
-    let checks: Vec<Box<dyn Precheck>> = match path_type {
-        PathType::AppBundle => vec![
-            Box::new(bundle::DeveloperIdCheck),
-            Box::new(bundle::HardenedRuntimeCheck),
-            Box::new(bundle::NoGetTaskAllowCheck),
-            Box::new(bundle::SecureTimestampCheck),
-        ],
-        PathType::DiskImage => vec![Box::new(dmg::DeveloperIdCheck)],
-        PathType::InstallerPackage => vec![Box::new(package::DeveloperIdCheck)],
-    };
+    fail_fast: bool,
+    skip: &[String],
+) -> Result<PrecheckReport, Box<dyn Error>> {
+    let mut report = PrecheckReport::new(fail_fast);
 
-    for check in checks {
-        let pb = progress_bar(&format!("Perform check: {}", check.display()));
+    match path_type {
+        PathType::AppBundle => {
+            run_checks(bundle_checks(), path, None, fail_fast, skip, &mut report)?
+        }
+        PathType::ZipArchive => {
+            let pb = progress_bar("Extracting archive");
+            let extracted = archive::zip::extract_bundles(path, None)?;
+            pb.finish_and_clear();
+
+            for bundle in &extracted.bundles {
+                let name = bundle.file_name().unwrap().to_string_lossy();
+                run_checks(
+                    bundle_checks(),
+                    bundle,
+                    Some(&name),
+                    fail_fast,
+                    skip,
+                    &mut report,
+                )?;
+            }
+        }
+        PathType::DiskImage => {
+            run_checks(
+                vec![Box::new(dmg::DeveloperIdCheck)],
+                path,
+                None,
+                fail_fast,
+                skip,
+                &mut report,
+            )?;
+            if fail_fast && report.has_failures() {
+                return Ok(report);
+            }
+
+            let pb = progress_bar("Extracting disk image");
+            let extracted = archive::disk_image::extract_bundles(path);
+            pb.finish_and_clear();
 
-        if let Some(error) = check.run(path)?.to_err() {
-            return Err(error.into());
+            // Images the reader does not support, e.g. encrypted ones, are only checked for
+            // their signature.
+            let bundles = match &extracted {
+                Ok(extracted) => extracted.bundles.as_slice(),
+                Err(err) => {
+                    output::warn(&OperationError::detail(
+                        "Could not read disk image contents. Skipping bundle checks.",
+                        &err.to_string(),
+                    ));
+                    &[]
+                }
+            };
+
+            if bundles.is_empty() && !fail_fast {
+                // Only the Developer ID check applies without an app bundle to check.
+                for check in bundle_checks().iter().skip(1) {
+                    report.add(check.display(), None, CheckOutcome::Skip);
+                }
+            }
+
+            for bundle in bundles {
+                let name = bundle.file_name().unwrap().to_string_lossy();
+                run_checks(
+                    bundle_checks(),
+                    bundle,
+                    Some(&name),
+                    fail_fast,
+                    skip,
+                    &mut report,
+                )?;
+            }
         }
+        PathType::InstallerPackage => {
+            run_checks(
+                vec![Box::new(package::DeveloperIdCheck)],
+                path,
+                None,
+                fail_fast,
+                skip,
+                &mut report,
+            )?;
+            if fail_fast && report.has_failures() {
+                return Ok(report);
+            }
+
+            let pb = progress_bar("Extracting package");
+            let code = package::package_code(path)?;
+            pb.finish_and_clear();
+
+            if code.items.is_empty() {
+                output::warn(&OperationError::detail(
+                    "Could not find code in package contents. Skipping bundle checks.",
+                    "No app bundle or binary was found in the package's components, e.g. as they are not included in the package.",
+                ));
+                if !fail_fast {
+                    for check in bundle_checks().iter().skip(1) {
+                        report.add(check.display(), None, CheckOutcome::Skip);
+                    }
+                }
+            }
 
-        pb.finish();
+            for (label, code_path) in &code.items {
+                run_checks(
+                    bundle_checks(),
+                    code_path,
+                    Some(label),
+                    fail_fast,
+                    skip,
+                    &mut report,
+                )?;
+            }
+        }
     }
 
-    if show_message {
-        if let PathType::InstallerPackage = path_type {
-            let emphasized = Style::new().yellow().bold();
-            println!();
-            println!(
-                r#"{}Because .pkg input was specified, not all checks were performed. Run "precheck" on a bundle for additional checks."#,
-                emphasized.apply_to("Note: ")
-            );
+    Ok(report)
+}
+
+/// Runs `checks` against `path`, adding their outcomes to `report`. `bundle` names the bundle
+/// being checked when the input contains several. Checks identified in `skip` are reported as
+/// skipped. With `fail_fast`, does nothing once a check has failed.
+fn run_checks(
+    checks: Vec<Box<dyn Precheck>>,
+    path: &PathBuf,
+    bundle: Option<&str>,
+    fail_fast: bool,
+    skip: &[String],
+    report: &mut PrecheckReport,
+) -> Result<(), Box<dyn Error>> {
+    for check in checks {
+        if fail_fast && report.has_failures() {
+            break;
+        }
+
+        if skip.iter().any(|id| id == check.id()) {
+            report.add(check.display(), bundle, CheckOutcome::Skip);
+            continue;
+        }
+
+        let title = match bundle {
+            Some(bundle) => format!("Perform check: {} ({})", check.display(), bundle),
+            None => format!("Perform check: {}", check.display()),
+        };
+        let pb = progress_bar(&title);
+        let outcome = match check.run(path)?.to_err() {
+            Some(error) => match bundle {
+                Some(bundle) => CheckOutcome::Fail(error.in_bundle(bundle)),
+                None => CheckOutcome::Fail(error),
+            },
+            None => CheckOutcome::Pass,
+        };
+
+        if !fail_fast {
+            pb.finish_and_clear();
+        } else if let CheckOutcome::Pass = outcome {
+            pb.finish();
         }
+        report.add(check.display(), bundle, outcome);
     }
 
     Ok(())
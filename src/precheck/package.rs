@@ -2,25 +2,133 @@ use super::util::passes_spctl;
 use super::Status;
 
 use std::error::Error;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use crate::archive::package::{self, ExtractedPackage};
+use crate::codesign::{is_macho, Certificate};
+use crate::util::OperationError;
 
 pub(super) struct DeveloperIdCheck;
 
 impl super::Precheck for DeveloperIdCheck {
+    fn id(&self) -> &'static str {
+        "developer_id"
+    }
+
     fn display(&self) -> &'static str {
         "Developer ID signing"
     }
 
     fn run(&self, input_path: &PathBuf) -> Result<Status, Box<dyn Error>> {
-        // Note: may also use "/usr/sbin/pkgutil --check-signature"
-        if passes_spctl(&vec!["-t", "install"], input_path)? {
-            Ok(Status::Pass)
-        } else {
-            Ok(Status::fail_with(
+        let certificate = match package::signing_certificates(input_path)?.first() {
+            Some(der) => Some(Certificate::from_der(der)?),
+            None => None,
+        };
+
+        let solution = "Make sure to provide the --sign <installer identity> argument to pkgbuild.";
+
+        match certificate {
+            Some(certificate) if certificate.is_developer_id_installer() => {}
+            Some(certificate) => {
+                return Ok(Status::fail_with(
+                    &format!(
+                        r#"Package is not signed with a Developer ID certificate, but with "{}"."#,
+                        certificate
+                            .common_name
+                            .as_deref()
+                            .unwrap_or("unknown certificate")
+                    ),
+                    solution,
+                    None,
+                ))
+            }
+            None => {
+                return Ok(Status::fail_with(
+                    "Package is not signed with a Developer ID certificate.",
+                    solution,
+                    None,
+                ))
+            }
+        }
+
+        // Gatekeeper assessment additionally covers certificate validity, but is only
+        // available on macOS. Note: may also use "/usr/sbin/pkgutil --check-signature"
+        if cfg!(target_os = "macos") && !passes_spctl(&["-t", "install"], input_path)? {
+            return Ok(Status::fail_with(
                 "Package is not signed with a Developer ID certificate.",
-                "Make sure to provide the --sign <installer identity> argument to pkgbuild.",
+                solution,
                 None,
-            ))
+            ));
+        }
+
+        Ok(Status::Pass)
+    }
+}
+
+/// Code within an extracted package, removed on drop.
+pub(super) struct PackageCode {
+    _extracted: ExtractedPackage,
+    /// Label and path of each app bundle or binary.
+    pub(super) items: Vec<(String, PathBuf)>,
+}
+
+/// Extracts the package and lists the code to run bundle checks on: app bundles, and Mach-O
+/// binaries outside of them. Each is named by its path relative to the install location,
+/// followed by the component identifier if the package has several components.
+pub(super) fn package_code(input_path: &Path) -> Result<PackageCode, Box<dyn Error>> {
+    let extracted = package::extract(input_path)?;
+
+    let mut items = vec![];
+    for component in &extracted.components {
+        let mut paths = vec![];
+        find_code(&component.root, &mut paths)?;
+        paths.sort();
+
+        for path in paths {
+            let relative = path
+                .strip_prefix(&component.root)
+                .unwrap_or(&path)
+                .display();
+            let label = match &component.identifier {
+                Some(identifier) if extracted.components.len() > 1 => {
+                    format!("{} ({})", relative, identifier)
+                }
+                _ => relative.to_string(),
+            };
+            items.push((label, path));
+        }
+    }
+
+    Ok(PackageCode {
+        _extracted: extracted,
+        items,
+    })
+}
+
+fn find_code(dir: &Path, found: &mut Vec<PathBuf>) -> Result<(), Box<dyn Error>> {
+    let entries = std::fs::read_dir(dir).map_err(|err| {
+        OperationError::detail(
+            &format!("Could not read {}", dir.display()),
+            &err.to_string(),
+        )
+    })?;
+
+    for entry in entries {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let path = entry.path();
+
+        if file_type.is_dir() {
+            // Bundle checks cover everything within an app.
+            if path.extension().is_some_and(|ext| ext == "app") {
+                found.push(path);
+            } else {
+                find_code(&path, found)?;
+            }
+        } else if file_type.is_file() && is_macho(&path) {
+            found.push(path);
         }
     }
+
+    Ok(())
 }
@@ -56,6 +56,17 @@ pub fn test_precheck_dmg() {
         .is_pass());
 }
 
+#[test]
+pub fn test_precheck_dmg_contents() {
+    let artifact = test_utils::artifact(PathType::DiskImage, "signed_with_correctly_signed_app");
+    let extracted = crate::archive::disk_image::extract_bundles(&artifact.path).unwrap();
+    assert_eq!(extracted.bundles.len(), 1);
+
+    for check in super::bundle_checks() {
+        assert!(check.run(&extracted.bundles[0]).unwrap().is_pass());
+    }
+}
+
 #[test]
 pub fn test_precheck_bundle() {
     let artifact = test_utils::artifact(PathType::AppBundle, "correctly_signed");
@@ -82,11 +93,16 @@ pub fn test_precheck_bundle() {
         .unwrap()
         .is_fail());
 
+    // The main executable is signed with Developer ID, but the bundle also includes an
+    // ad-hoc signed binary in Resources.
     let artifact = test_utils::artifact(PathType::AppBundle, "manually_signed");
-    assert!(super::bundle::DeveloperIdCheck
-        .run(&artifact.path)
-        .unwrap()
-        .is_pass());
+    match super::bundle::DeveloperIdCheck.run(&artifact.path).unwrap() {
+        super::Status::Fail { message, .. } => {
+            assert!(message.contains("Contents/Resources/embedded_binary"));
+            assert!(!message.contains("Contents/MacOS"));
+        }
+        super::Status::Pass => panic!("Expected ad-hoc signed nested code to fail the check"),
+    }
 
     let artifact = test_utils::artifact(PathType::AppBundle, "no_secure_timestamp");
     assert!(super::bundle::SecureTimestampCheck
@@ -107,7 +123,143 @@ pub fn test_precheck_bundle() {
         .is_fail());
 }
 
-pub(super) mod test_utils {
+#[test]
+pub fn test_precheck_nested_code() {
+    let artifact = test_utils::artifact(PathType::AppBundle, "correctly_signed");
+    let unsigned = test_utils::artifact(PathType::AppBundle, "no_hardened_runtime");
+
+    let frameworks = artifact.path.join("Contents/Frameworks");
+    std::fs::create_dir(&frameworks).unwrap();
+    std::fs::copy(
+        unsigned.path.join("Contents/MacOS/no_hardened_runtime"),
+        frameworks.join("libhelper.dylib"),
+    )
+    .unwrap();
+
+    match super::bundle::HardenedRuntimeCheck
+        .run(&artifact.path)
+        .unwrap()
+    {
+        super::Status::Fail { message, .. } => {
+            assert!(message.contains("Contents/Frameworks/libhelper.dylib"));
+            assert!(!message.contains("Contents/MacOS"));
+        }
+        super::Status::Pass => panic!("Expected nested code to fail the check"),
+    }
+
+    assert!(super::bundle::SecureTimestampCheck
+        .run(&artifact.path)
+        .unwrap()
+        .is_fail());
+    assert!(super::bundle::NoGetTaskAllowCheck
+        .run(&artifact.path)
+        .unwrap()
+        .is_pass());
+}
+
+#[test]
+pub fn test_precheck_report() {
+    console::set_colors_enabled(false);
+
+    let artifact = test_utils::artifact(PathType::AppBundle, "no_hardened_runtime");
+    let report = super::run(&artifact.path, &PathType::AppBundle, false, &[])
+        .unwrap()
+        .into_result()
+        .unwrap_err()
+        .to_string();
+
+    assert!(report.contains("missing a secure timestamp"));
+    assert!(report.contains("without hardened runtime enabled"));
+    assert!(report.ends_with("2 passed, 2 failed, 0 skipped"));
+
+    let report = super::run(&artifact.path, &PathType::AppBundle, true, &[]).unwrap();
+    assert_eq!(report.results().len(), 2);
+    let error = report.into_result().unwrap_err().to_string();
+    assert!(!error.contains("passed"));
+}
+
+#[test]
+pub fn test_precheck_skip() {
+    console::set_colors_enabled(false);
+
+    let artifact = test_utils::artifact(PathType::AppBundle, "no_hardened_runtime");
+    let skip = ["hardened_runtime".to_string()];
+    let report = super::run(&artifact.path, &PathType::AppBundle, false, &skip)
+        .unwrap()
+        .into_result()
+        .unwrap_err()
+        .to_string();
+    assert!(!report.contains("without hardened runtime enabled"));
+    assert!(report.ends_with("2 passed, 1 failed, 1 skipped"));
+
+    let skip = [
+        "hardened_runtime".to_string(),
+        "secure_timestamp".to_string(),
+    ];
+    assert!(
+        !super::run(&artifact.path, &PathType::AppBundle, true, &skip)
+            .unwrap()
+            .has_failures()
+    );
+}
+
+#[test]
+pub fn test_precheck_zip() {
+    let artifact = test_utils::artifact(PathType::ZipArchive, "correctly_signed");
+    assert!(
+        !super::run(&artifact.path, &PathType::ZipArchive, false, &[])
+            .unwrap()
+            .has_failures()
+    );
+
+    let artifact = test_utils::artifact(PathType::ZipArchive, "no_hardened_runtime");
+    let report = super::run(&artifact.path, &PathType::ZipArchive, false, &[])
+        .unwrap()
+        .into_result()
+        .unwrap_err()
+        .to_string();
+    assert!(
+        report.contains("no_hardened_runtime.app: Bundle includes code without hardened runtime")
+    );
+    assert!(report.ends_with("2 passed, 2 failed, 0 skipped"));
+
+    let report = super::run(&artifact.path, &PathType::ZipArchive, false, &[]).unwrap();
+    assert!(report
+        .results()
+        .iter()
+        .all(|result| result.bundle.as_deref() == Some("no_hardened_runtime.app")));
+}
+
+#[test]
+pub fn test_precheck_package_contents() {
+    console::set_colors_enabled(false);
+
+    let artifact = test_utils::artifact(
+        PathType::InstallerPackage,
+        "signed_with_correctly_signed_app",
+    );
+    assert!(
+        !super::run(&artifact.path, &PathType::InstallerPackage, false, &[])
+            .unwrap()
+            .has_failures()
+    );
+
+    let artifact = test_utils::artifact(
+        PathType::InstallerPackage,
+        "signed_with_no_secure_timestamp_app",
+    );
+    let report = super::run(&artifact.path, &PathType::InstallerPackage, false, &[])
+        .unwrap()
+        .into_result()
+        .unwrap_err()
+        .to_string();
+    assert!(
+        report.contains("no_secure_timestamp.app: Bundle includes code missing a secure timestamp")
+    );
+    assert!(report.ends_with("4 passed, 1 failed, 0 skipped"));
+}
+
+pub(crate) mod test_utils {
     use crate::util::input_path::PathType;
     use std::path::PathBuf;
     use std::process::Command;
@@ -122,10 +274,18 @@ pub(super) mod test_utils {
         let temp_dir = TempFileBuilder::new().tempdir().unwrap();
         let temp_dir_path = temp_dir.path().to_str().unwrap();
 
-        let status = Command::new("/usr/bin/ditto")
-            .args(&["-xk", zipped_path, &temp_dir_path])
-            .status()
-            .unwrap();
+        // ditto is only available on macOS. Signatures of the fixture binaries are embedded,
+        // so extracting with unzip elsewhere does not lose them.
+        let status = if cfg!(target_os = "macos") {
+            Command::new("/usr/bin/ditto")
+                .args(["-xk", zipped_path, temp_dir_path])
+                .status()
+        } else {
+            Command::new("unzip")
+                .args(["-q", zipped_path, "-d", temp_dir_path])
+                .status()
+        }
+        .unwrap();
 
         if !status.success() {
             panic!();
@@ -153,13 +313,23 @@ pub(super) mod test_utils {
 
                 bundle_artifact(path.to_str().unwrap(), name)
             }
+            PathType::ZipArchive => {
+                path.push("app");
+                path.push(name);
+                path.set_extension("zip");
+
+                Artifact {
+                    path,
+                    _temp_dir: None,
+                }
+            }
             PathType::DiskImage => {
                 path.push("dmg");
                 path.push(name);
                 path.set_extension("dmg");
 
                 Artifact {
-                    path: path,
+                    path,
                     _temp_dir: None,
                 }
             }
@@ -169,7 +339,7 @@ pub(super) mod test_utils {
                 path.set_extension("pkg");
 
                 Artifact {
-                    path: path,
+                    path,
                     _temp_dir: None,
                 }
             }
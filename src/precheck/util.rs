@@ -12,7 +12,7 @@ pub(super) fn passes_spctl(args: &[&str], input_path: &PathBuf) -> Result<bool,
         .arg(input_path.as_os_str())
         .output()?;
 
-    let stderr = String::from_utf8(output.stderr).unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
 
     return match output.status.code() {
         // spctl exits zero on success, or one if an operation has failed.
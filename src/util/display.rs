@@ -1,6 +1,10 @@
 use indicatif::{ProgressBar, ProgressStyle};
 
 pub(crate) fn progress_bar(message: &str) -> ProgressBar {
+    if !crate::output::is_text() {
+        return ProgressBar::hidden();
+    }
+
     let pb = ProgressBar::new_spinner();
     pb.enable_steady_tick(120);
     pb.set_style(
@@ -2,19 +2,119 @@ use console::Style;
 use std::error::Error;
 use std::fmt;
 
+/// Cause of a failure, determining the exit status of the process. Exit statuses are part of
+/// the command line interface and must not change.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorKind {
+    /// Any other failure, e.g. invalid arguments or unreadable input.
+    Other,
+    /// The input failed a pre-notarization check.
+    PrecheckFailed,
+    /// Missing or invalid developer account or API key credentials.
+    Credentials,
+    /// The notarization service rejected the submission.
+    Rejected,
+    /// Network failure or service outage, likely to succeed when retried.
+    Network,
+    /// Gave up waiting for the submission to complete.
+    TimedOut,
+    /// The submission was accepted, but the ticket could not be stapled.
+    StapleFailed,
+    /// The notarization service or a command line tool returned unexpected output.
+    MalformedResponse,
+    /// No ticket is stapled to the input, or it does not cover the input's current code.
+    InvalidTicket,
+    /// The operation was cancelled through a `CancellationToken`. Not produced by the command
+    /// line tool.
+    Cancelled,
+}
+
+impl ErrorKind {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::TimedOut => 2,
+            ErrorKind::PrecheckFailed => 3,
+            ErrorKind::Credentials => 4,
+            ErrorKind::Rejected => 5,
+            ErrorKind::Network => 6,
+            ErrorKind::StapleFailed => 7,
+            ErrorKind::MalformedResponse => 8,
+            ErrorKind::InvalidTicket => 9,
+            ErrorKind::Cancelled => 10,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
-pub(crate) enum OperationError {
-    Text(String),
-    Detailed(String, String), // message: String,
+pub struct OperationError {
+    kind: ErrorKind,
+    heading: Option<String>,
+    message: String,
+    request_id: Option<String>,
 }
 
 impl OperationError {
-    pub(crate) fn new(message: &str) -> Self {
-        OperationError::Text(message.into())
+    pub fn new(message: &str) -> Self {
+        OperationError {
+            kind: ErrorKind::Other,
+            heading: None,
+            message: message.into(),
+            request_id: None,
+        }
+    }
+
+    /// Error with a heading describing the failed operation, followed by details.
+    pub fn detail(heading: &str, message: &str) -> Self {
+        OperationError {
+            kind: ErrorKind::Other,
+            heading: Some(heading.into()),
+            message: message.into(),
+            request_id: None,
+        }
+    }
+
+    /// Failure likely to succeed when retried, e.g. a network error or a service outage.
+    pub(crate) fn transient(heading: &str, message: &str) -> Self {
+        Self::detail(heading, message).with_kind(ErrorKind::Network)
     }
 
-    pub(crate) fn detail(heading: &str, message: &str) -> Self {
-        OperationError::Detailed(heading.into(), message.into())
+    /// Gave up waiting for the request with the given ID to complete.
+    pub(crate) fn timed_out(request_id: &str) -> Self {
+        Self::new(&format!(
+            "Timed out waiting for notarization. Request UUID:\n{}\nRun `xcnotary wait` with this UUID to resume.",
+            request_id
+        ))
+        .with_kind(ErrorKind::TimedOut)
+        .with_request_id(request_id)
+    }
+
+    pub(crate) fn cancelled() -> Self {
+        Self::new("Operation cancelled").with_kind(ErrorKind::Cancelled)
+    }
+
+    pub fn with_kind(mut self, kind: ErrorKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Names the submission the failure occurred in.
+    pub(crate) fn with_request_id(mut self, request_id: &str) -> Self {
+        self.request_id = Some(request_id.into());
+        self
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Request ID of the submission, if the input was submitted before the operation failed.
+    pub fn request_id(&self) -> Option<&str> {
+        self.request_id.as_deref()
+    }
+
+    pub fn is_transient(&self) -> bool {
+        self.kind == ErrorKind::Network
     }
 }
 
@@ -22,11 +122,9 @@ impl fmt::Display for OperationError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let error_color = Style::new().red().bold();
 
-        match self {
-            OperationError::Text(text) => write!(f, "{}{}", error_color.apply_to("Error: "), text),
-            OperationError::Detailed(heading, text) => {
-                write!(f, "{}:\n{}", error_color.apply_to(heading), text)
-            }
+        match &self.heading {
+            None => write!(f, "{}{}", error_color.apply_to("Error: "), self.message),
+            Some(heading) => write!(f, "{}:\n{}", error_color.apply_to(heading), self.message),
         }
     }
 }
@@ -2,15 +2,15 @@ use super::OperationError;
 use crate::util::plist::{bundle_info_from_file, structs::BundleInfo};
 use std::path::{Path, PathBuf};
 
-pub(crate) fn path_info<P: AsRef<Path>>(
-    input_path: P,
-) -> Result<(PathType, String), OperationError> {
+/// Identifies the input at `input_path`, returning its type and bundle ID. Input other than app
+/// bundles is identified by a pseudo-bundle ID derived from its file name.
+pub fn path_info<P: AsRef<Path>>(input_path: P) -> Result<(PathType, String), OperationError> {
     let path_type = identify_path_type(&input_path)?;
     let bundle_id = match &path_type {
         PathType::AppBundle => read_bundle_info(&input_path)?.id,
         // Generate a pseudo-bundle ID. This value is used for informational purposes
         // e.g. to notify of notarization status.
-        PathType::DiskImage | PathType::InstallerPackage => input_path
+        PathType::ZipArchive | PathType::DiskImage | PathType::InstallerPackage => input_path
             .as_ref()
             .file_name()
             .unwrap()
@@ -25,12 +25,13 @@ pub(crate) fn path_info<P: AsRef<Path>>(
     Ok((path_type, bundle_id))
 }
 
-pub(crate) fn identify_path_type<P: AsRef<Path>>(
-    bundle_path: P,
-) -> Result<PathType, OperationError> {
+/// Identifies the type of input at `bundle_path` by its extension.
+pub fn identify_path_type<P: AsRef<Path>>(bundle_path: P) -> Result<PathType, OperationError> {
     if let Some(val) = bundle_path.as_ref().extension() {
         if val == "app" {
             return Ok(PathType::AppBundle);
+        } else if val == "zip" {
+            return Ok(PathType::ZipArchive);
         } else if val == "dmg" {
             return Ok(PathType::DiskImage);
         } else if val == "pkg" {
@@ -39,15 +40,16 @@ pub(crate) fn identify_path_type<P: AsRef<Path>>(
     }
 
     Err(OperationError::new(&format!(
-        "Expected an application bundle, disk image, or installer package at {}",
+        "Expected an application bundle, zip archive, disk image, or installer package at {}",
         bundle_path.as_ref().display()
-    ))
-    .into())
+    )))
 }
 
-#[derive(Debug, PartialEq)]
-pub(crate) enum PathType {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathType {
     AppBundle,
+    /// Zip archive containing one or more application bundles, submitted as-is.
+    ZipArchive,
     DiskImage,
     InstallerPackage,
 }
@@ -56,9 +58,10 @@ fn read_bundle_info<P: AsRef<Path>>(bundle_path: P) -> Result<BundleInfo, Operat
     let bundle_path = bundle_path.as_ref();
 
     if !bundle_path.exists() {
-        return Err(
-            OperationError::new(&format!("Path not found: {}", bundle_path.display())).into(),
-        );
+        return Err(OperationError::new(&format!(
+            "Path not found: {}",
+            bundle_path.display()
+        )));
     }
 
     let mut info_plist_path = PathBuf::from(bundle_path);
@@ -68,11 +71,10 @@ fn read_bundle_info<P: AsRef<Path>>(bundle_path: P) -> Result<BundleInfo, Operat
         return Err(OperationError::new(&format!(
             "Info.plist was not found at {}",
             info_plist_path.display()
-        ))
-        .into());
+        )));
     }
 
-    Ok(bundle_info_from_file(info_plist_path))
+    bundle_info_from_file(info_plist_path)
 }
 
 #[cfg(test)]
@@ -87,6 +89,10 @@ mod tests {
             Some(PathType::AppBundle),
             identify_path_type(PathBuf::from("Foo.app")).ok()
         );
+        assert_eq!(
+            Some(PathType::ZipArchive),
+            identify_path_type(PathBuf::from("Foo.zip")).ok()
+        );
         assert_eq!(
             Some(PathType::DiskImage),
             identify_path_type(PathBuf::from("Foo.dmg")).ok()
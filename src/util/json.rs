@@ -0,0 +1,142 @@
+use serde::de::DeserializeOwned;
+
+use super::plist::structs;
+use super::{ErrorKind, OperationError};
+
+pub(crate) fn notarytool_submit_response(
+    bytes: &[u8],
+) -> Result<structs::NotarytoolSubmission, OperationError> {
+    from_bytes(bytes)
+}
+
+pub(crate) fn notarytool_info_response(
+    bytes: &[u8],
+) -> Result<structs::NotarytoolInfo, OperationError> {
+    from_bytes(bytes)
+}
+
+pub(crate) fn notarytool_history_response(
+    bytes: &[u8],
+) -> Result<structs::NotarytoolHistory, OperationError> {
+    from_bytes(bytes)
+}
+
+pub(crate) fn notary_api_new_submission_response(
+    bytes: &[u8],
+) -> Result<structs::NotaryApiNewSubmission, OperationError> {
+    from_bytes(bytes)
+}
+
+pub(crate) fn notary_api_submission_response(
+    bytes: &[u8],
+) -> Result<structs::NotaryApiSubmission, OperationError> {
+    from_bytes(bytes)
+}
+
+pub(crate) fn notary_api_submission_list_response(
+    bytes: &[u8],
+) -> Result<structs::NotaryApiSubmissionList, OperationError> {
+    from_bytes(bytes)
+}
+
+pub(crate) fn notary_api_submission_log_response(
+    bytes: &[u8],
+) -> Result<structs::NotaryApiSubmissionLog, OperationError> {
+    from_bytes(bytes)
+}
+
+pub(crate) fn ticket_lookup_response(
+    bytes: &[u8],
+) -> Result<structs::TicketLookup, OperationError> {
+    from_bytes(bytes)
+}
+
+pub(crate) fn notarization_log(bytes: &[u8]) -> Result<structs::NotarizationLog, OperationError> {
+    from_bytes(bytes)
+}
+
+fn from_bytes<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, OperationError> {
+    serde_json::from_slice(bytes).map_err(|err| {
+        OperationError::detail(
+            "Unexpected response from notarization service",
+            &format!("{}\n{}", err, String::from_utf8_lossy(bytes)),
+        )
+        .with_kind(ErrorKind::MalformedResponse)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::plist::structs::NotarizationStatus;
+
+    static REQUEST_UUID: &str = "c0dec0de-1234-5678-1234-b4d961a1d182";
+
+    static SUBMIT_RESPONSE: &str = r#"{
+        "id": "c0dec0de-1234-5678-1234-b4d961a1d182",
+        "message": "Successfully uploaded file",
+        "path": "/tmp/foo.zip"
+    }"#;
+
+    static IN_PROGRESS_INFO_RESPONSE: &str = r#"{
+        "createdDate": "2021-06-08T20:57:18.434Z",
+        "id": "c0dec0de-1234-5678-1234-b4d961a1d182",
+        "message": "Successfully received submission info",
+        "name": "foo.zip",
+        "status": "In Progress"
+    }"#;
+
+    static INVALID_INFO_RESPONSE: &str = r#"{
+        "createdDate": "2021-06-08T20:57:18.434Z",
+        "id": "c0dec0de-1234-5678-1234-b4d961a1d182",
+        "message": "Successfully received submission info",
+        "name": "foo.zip",
+        "status": "Invalid"
+    }"#;
+
+    static HISTORY_RESPONSE: &str = r#"{
+        "history": [
+            {
+                "createdDate": "2021-06-08T20:57:18.434Z",
+                "id": "c0dec0de-1234-5678-1234-b4d961a1d182",
+                "name": "foo.zip",
+                "status": "Accepted"
+            },
+            {
+                "createdDate": "2021-06-07T10:00:00.000Z",
+                "id": "c0dec0de-0000-5678-1234-b4d961a1d182",
+                "name": "foo.dmg",
+                "status": "Rejected"
+            }
+        ],
+        "message": "Successfully received submission history."
+    }"#;
+
+    #[test]
+    fn test_deserialize_submit() {
+        let result = super::notarytool_submit_response(SUBMIT_RESPONSE.as_bytes()).unwrap();
+        assert_eq!(result.id, REQUEST_UUID);
+    }
+
+    #[test]
+    fn test_deserialize_info() {
+        let result = super::notarytool_info_response(IN_PROGRESS_INFO_RESPONSE.as_bytes()).unwrap();
+        assert_eq!(result.id, REQUEST_UUID);
+        assert_eq!(result.status, NotarizationStatus::InProgress);
+
+        let result = super::notarytool_info_response(INVALID_INFO_RESPONSE.as_bytes()).unwrap();
+        assert_eq!(result.status, NotarizationStatus::Invalid);
+    }
+
+    #[test]
+    fn test_deserialize_history() {
+        let result = super::notarytool_history_response(HISTORY_RESPONSE.as_bytes()).unwrap();
+        assert_eq!(result.history.len(), 2);
+        assert_eq!(result.history[0].status, NotarizationStatus::Success);
+        assert_eq!(result.history[1].status, NotarizationStatus::Invalid);
+    }
+
+    #[test]
+    fn test_malformed_response() {
+        assert!(super::notarytool_info_response(b"Error: HTTP status code: 401").is_err());
+    }
+}
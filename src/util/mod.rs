@@ -1,7 +1,9 @@
-pub(crate) mod cli;
 pub(crate) mod display;
 mod error;
+pub(crate) mod hash;
 pub(crate) mod input_path;
+pub(crate) mod json;
 pub(crate) mod plist;
+pub(crate) mod xml;
 
-pub(crate) use error::OperationError;
+pub use error::{ErrorKind, OperationError};
@@ -1,21 +1,55 @@
+use serde::de::DeserializeOwned;
 use std::path::Path;
 
+use super::{ErrorKind, OperationError};
+
 pub mod structs;
 
-pub(crate) fn bundle_info_from_file<P: AsRef<Path>>(path: P) -> structs::BundleInfo {
-    plist::from_file(path).unwrap()
+pub(crate) fn bundle_info_from_file<P: AsRef<Path>>(
+    path: P,
+) -> Result<structs::BundleInfo, OperationError> {
+    let path = path.as_ref();
+    plist::from_file(path).map_err(|err| {
+        OperationError::detail(
+            &format!("Could not read {}", path.display()),
+            &err.to_string(),
+        )
+    })
+}
+
+pub(crate) fn notarization_upload_response(
+    bytes: &[u8],
+) -> Result<structs::NotarizationUpload, OperationError> {
+    response_from_bytes(bytes)
+}
+
+pub(crate) fn notarization_status_response(
+    bytes: &[u8],
+) -> Result<structs::NotarizationInfo, OperationError> {
+    response_from_bytes(bytes)
 }
 
-pub(crate) fn notarization_upload_response(bytes: &[u8]) -> structs::NotarizationUpload {
-    plist::from_bytes(bytes).unwrap()
+pub(crate) fn notarization_history_response(
+    bytes: &[u8],
+) -> Result<structs::NotarizationHistory, OperationError> {
+    response_from_bytes(bytes)
 }
 
-pub(crate) fn notarization_status_response(bytes: &[u8]) -> structs::NotarizationInfo {
-    plist::from_bytes(bytes).unwrap()
+pub(crate) fn bundle_entitlemens(
+    bytes: &[u8],
+) -> Result<structs::BundleEntitlements, OperationError> {
+    plist::from_bytes(bytes)
+        .map_err(|err| OperationError::detail("Could not parse entitlements", &err.to_string()))
 }
 
-pub(crate) fn bundle_entitlemens(bytes: &[u8]) -> structs::BundleEntitlements {
-    plist::from_bytes(bytes).unwrap()
+fn response_from_bytes<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, OperationError> {
+    plist::from_bytes(bytes).map_err(|err| {
+        OperationError::detail(
+            "Unexpected response from notarization service",
+            &format!("{}\n{}", err, String::from_utf8_lossy(bytes)),
+        )
+        .with_kind(ErrorKind::MalformedResponse)
+    })
 }
 
 #[cfg(test)]
@@ -122,6 +156,38 @@ mod tests {
 	<string>4.00.1181</string>
 </dict>
 </plist>
+"#;
+
+    static VALID_NOTARIZATION_HISTORY_PLIST: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>notarization-history</key>
+	<dict>
+		<key>items</key>
+		<array>
+			<dict>
+				<key>Date</key>
+				<date>2020-03-01T00:00:00Z</date>
+				<key>RequestUUID</key>
+				<string>c0dec0de-1234-5678-1234-b4d961a1d182</string>
+				<key>Status</key>
+				<string>success</string>
+				<key>Status Code</key>
+				<integer>0</integer>
+				<key>Status Message</key>
+				<string>Package Approved</string>
+			</dict>
+		</array>
+		<key>next-page</key>
+		<integer>1</integer>
+	</dict>
+	<key>os-version</key>
+	<string>10.15.3</string>
+	<key>success-message</key>
+	<string>No errors getting notarization history.</string>
+</dict>
+</plist>
 "#;
 
     static ENTITLEMENTS_OUTPUT: &str = r#"
@@ -138,15 +204,15 @@ mod tests {
     #[test]
     fn test_deserialize_notarization_success_info() {
         let result =
-            super::notarization_status_response(VALID_SUCCESS_NOTARIZATION_INFO_PLIST.as_bytes());
+            super::notarization_status_response(VALID_SUCCESS_NOTARIZATION_INFO_PLIST.as_bytes())
+                .unwrap();
 
-        assert_eq!(result.details.request_uuid, REQUEST_UUID);
-        assert!(if let NotarizationStatus::Success = result.details.status {
-            true
-        } else {
-            false
-        });
+        assert!(matches!(result.details.status, NotarizationStatus::Success));
 
+        assert_eq!(
+            result.details.hash.as_deref(),
+            Some("3caffa321f3adb01d1e0eebabfd8bdb5dbfcfc467522b903ec7b64fdad24ada8")
+        );
         assert!(result.details.logfile_url.is_some());
         assert!(result.details.logfile_url.unwrap().starts_with("https"));
 
@@ -157,26 +223,39 @@ mod tests {
     fn test_deserialize_notarization_in_progress_info() {
         let result = super::notarization_status_response(
             VALID_IN_PROGRESS_NOTARIZATION_INFO_PLIST.as_bytes(),
-        );
+        )
+        .unwrap();
 
-        assert_eq!(result.details.request_uuid, REQUEST_UUID);
-        assert!(
-            if let NotarizationStatus::InProgress = result.details.status {
-                true
-            } else {
-                false
-            }
-        );
+        assert!(matches!(
+            result.details.status,
+            NotarizationStatus::InProgress
+        ));
 
         assert!(result.details.logfile_url.is_none());
         assert!(result.success_message.starts_with("No errors"));
     }
 
+    #[test]
+    fn test_malformed_response() {
+        let err = super::notarization_status_response(b"Error: not a plist").unwrap_err();
+        assert_eq!(err.kind(), crate::util::ErrorKind::MalformedResponse);
+    }
+
     #[test]
     fn test_deserialize_notarization_upload() {
-        let result = super::notarization_upload_response(VALID_SUCCESS_UPLOAD_PLIST.as_bytes());
+        let result =
+            super::notarization_upload_response(VALID_SUCCESS_UPLOAD_PLIST.as_bytes()).unwrap();
         assert_eq!(result.details.request_uuid, REQUEST_UUID);
-        assert!(result.success_message.starts_with("No errors"));
+    }
+
+    #[test]
+    fn test_deserialize_notarization_history() {
+        let result =
+            super::notarization_history_response(VALID_NOTARIZATION_HISTORY_PLIST.as_bytes())
+                .unwrap();
+        assert_eq!(result.details.items.len(), 1);
+        assert_eq!(result.details.items[0].request_uuid, REQUEST_UUID);
+        assert_eq!(result.details.items[0].status, NotarizationStatus::Success);
     }
 
     #[test]
@@ -184,14 +263,14 @@ mod tests {
         let temp_file = NamedTempFile::new().unwrap();
         std::fs::write(&temp_file.path().as_os_str(), VALID_INFO_PLIST).unwrap();
 
-        let result = super::bundle_info_from_file(temp_file);
+        let result = super::bundle_info_from_file(temp_file).unwrap();
 
         assert_eq!(result.id, "com.example.helloworld");
     }
 
     #[test]
     fn test_parse_entitlements() {
-        let result = super::bundle_entitlemens(ENTITLEMENTS_OUTPUT.as_bytes());
+        let result = super::bundle_entitlemens(ENTITLEMENTS_OUTPUT.as_bytes()).unwrap();
 
         assert!(result.get_task_allow.is_some());
         assert!(result.get_task_allow.unwrap());
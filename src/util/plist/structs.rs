@@ -8,10 +8,8 @@ use std::fmt;
 pub(crate) struct BundleInfo {
     #[serde(rename = "CFBundleIdentifier")]
     pub(crate) id: String,
-    #[serde(rename = "CFBundleVersion")]
-    pub(crate) version: String,
-    #[serde(rename = "CFBundleShortVersionString")]
-    pub(crate) short_version_string: String,
+    #[serde(rename = "CFBundleExecutable")]
+    pub(crate) executable: Option<String>,
 }
 
 /// Response from altool --notarization-info
@@ -27,15 +25,18 @@ pub(crate) struct NotarizationInfo {
 pub(crate) struct NotarizationInfoDetails {
     #[serde(rename = "LogFileURL")]
     pub(crate) logfile_url: Option<String>,
-    #[serde(rename = "RequestUUID")]
-    pub(crate) request_uuid: String,
+    /// Hex-encoded SHA-256 digest of the uploaded file.
+    #[serde(rename = "Hash")]
+    pub(crate) hash: Option<String>,
     #[serde(rename = "Status")]
     #[serde(deserialize_with = "notarization_status_from_string")]
     pub(crate) status: NotarizationStatus,
+    #[serde(rename = "Status Message")]
+    pub(crate) status_message: Option<String>,
 }
 
-#[derive(Debug)]
-pub(crate) enum NotarizationStatus {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NotarizationStatus {
     InProgress,
     Success,
     Invalid,
@@ -54,7 +55,6 @@ pub(crate) struct BundleEntitlements {
 pub(crate) struct NotarizationUpload {
     #[serde(rename = "notarization-upload")]
     pub(crate) details: NotarizationUploadDetails,
-    pub(crate) success_message: String,
 }
 
 #[derive(Deserialize, Debug)]
@@ -63,6 +63,213 @@ pub(crate) struct NotarizationUploadDetails {
     pub(crate) request_uuid: String,
 }
 
+impl NotarizationStatus {
+    /// Identifier of the status in machine-readable output, e.g. "in_progress".
+    pub fn id(self) -> &'static str {
+        match self {
+            NotarizationStatus::InProgress => "in_progress",
+            NotarizationStatus::Success => "success",
+            NotarizationStatus::Invalid => "invalid",
+        }
+    }
+}
+
+impl fmt::Display for NotarizationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NotarizationStatus::InProgress => write!(f, "in progress"),
+            NotarizationStatus::Success => write!(f, "success"),
+            NotarizationStatus::Invalid => write!(f, "invalid"),
+        }
+    }
+}
+
+/// Response from altool --notarization-history
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct NotarizationHistory {
+    #[serde(rename = "notarization-history")]
+    pub(crate) details: NotarizationHistoryDetails,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct NotarizationHistoryDetails {
+    #[serde(default)]
+    pub(crate) items: Vec<NotarizationHistoryItem>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct NotarizationHistoryItem {
+    #[serde(rename = "Date")]
+    pub(crate) date: plist::Date,
+    #[serde(rename = "RequestUUID")]
+    pub(crate) request_uuid: String,
+    #[serde(rename = "Status")]
+    #[serde(deserialize_with = "notarization_status_from_string")]
+    pub(crate) status: NotarizationStatus,
+}
+
+/// Response from notarytool submit
+#[derive(Deserialize, Debug)]
+pub(crate) struct NotarytoolSubmission {
+    pub(crate) id: String,
+}
+
+/// Response from notarytool info, also used for notarytool history entries
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NotarytoolInfo {
+    pub(crate) id: String,
+    #[serde(deserialize_with = "notarization_status_from_string")]
+    pub(crate) status: NotarizationStatus,
+    pub(crate) message: Option<String>,
+    pub(crate) name: Option<String>,
+    pub(crate) created_date: String,
+}
+
+/// Response from notarytool history
+#[derive(Deserialize, Debug)]
+pub(crate) struct NotarytoolHistory {
+    pub(crate) history: Vec<NotarytoolInfo>,
+}
+
+/// Response from Notary API POST /submissions
+#[derive(Deserialize, Debug)]
+pub(crate) struct NotaryApiNewSubmission {
+    pub(crate) data: NotaryApiNewSubmissionData,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct NotaryApiNewSubmissionData {
+    pub(crate) id: String,
+    pub(crate) attributes: NotaryApiUploadCredentials,
+}
+
+/// Temporary credentials for uploading the submission to S3
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NotaryApiUploadCredentials {
+    pub(crate) aws_access_key_id: String,
+    pub(crate) aws_secret_access_key: String,
+    pub(crate) aws_session_token: String,
+    pub(crate) bucket: String,
+    pub(crate) object: String,
+}
+
+/// Response from Notary API GET /submissions/{id}
+#[derive(Deserialize, Debug)]
+pub(crate) struct NotaryApiSubmission {
+    pub(crate) data: NotaryApiSubmissionData,
+}
+
+/// Response from Notary API GET /submissions
+#[derive(Deserialize, Debug)]
+pub(crate) struct NotaryApiSubmissionList {
+    pub(crate) data: Vec<NotaryApiSubmissionData>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct NotaryApiSubmissionData {
+    pub(crate) id: String,
+    pub(crate) attributes: NotaryApiSubmissionAttributes,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NotaryApiSubmissionAttributes {
+    #[serde(deserialize_with = "notarization_status_from_string")]
+    pub(crate) status: NotarizationStatus,
+    pub(crate) name: Option<String>,
+    pub(crate) created_date: String,
+}
+
+/// Response from Notary API GET /submissions/{id}/logs
+#[derive(Deserialize, Debug)]
+pub(crate) struct NotaryApiSubmissionLog {
+    pub(crate) data: NotaryApiSubmissionLogData,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct NotaryApiSubmissionLogData {
+    pub(crate) attributes: NotaryApiSubmissionLogAttributes,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NotaryApiSubmissionLogAttributes {
+    pub(crate) developer_log_url: String,
+}
+
+/// Response from the ticket lookup service, POST /records/lookup
+#[derive(Deserialize, Debug)]
+pub(crate) struct TicketLookup {
+    pub(crate) records: Vec<TicketRecord>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TicketRecord {
+    pub(crate) record_name: String,
+    pub(crate) fields: Option<TicketFields>,
+    /// Present instead of `fields` if the lookup failed, e.g. `NOT_FOUND`.
+    pub(crate) server_error_code: Option<String>,
+    pub(crate) reason: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TicketFields {
+    pub(crate) signed_ticket: TicketValue,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct TicketValue {
+    /// Base64-encoded ticket.
+    pub(crate) value: String,
+}
+
+/// Notarization service log, retrieved once a submission completes
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NotarizationLog {
+    #[serde(deserialize_with = "notarization_status_from_string")]
+    pub(crate) status: NotarizationStatus,
+    pub(crate) status_summary: Option<String>,
+    pub(crate) archive_filename: Option<String>,
+    pub(crate) sha256: Option<String>,
+    pub(crate) ticket_contents: Option<Vec<NotarizationTicketContent>>,
+    pub(crate) issues: Option<Vec<NotarizationIssue>>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NotarizationTicketContent {
+    pub(crate) path: String,
+    pub(crate) cdhash: String,
+    pub(crate) arch: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+/// Error or warning listed in the service log.
+pub struct NotarizationIssue {
+    pub severity: NotarizationIssueSeverity,
+    /// File the issue applies to, within the submitted archive.
+    pub path: Option<String>,
+    pub message: String,
+    pub doc_url: Option<String>,
+    pub architecture: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum NotarizationIssueSeverity {
+    Error,
+    Warning,
+    #[serde(other)]
+    Other,
+}
+
 struct NotarizationStatusString;
 
 impl<'de> Visitor<'de> for NotarizationStatusString {
@@ -77,9 +284,14 @@ impl<'de> Visitor<'de> for NotarizationStatusString {
         E: de::Error,
     {
         match s {
+            // altool
             "in progress" => Ok(NotarizationStatus::InProgress),
             "success" => Ok(NotarizationStatus::Success),
             "invalid" => Ok(NotarizationStatus::Invalid),
+            // notarytool and Notary API
+            "In Progress" => Ok(NotarizationStatus::InProgress),
+            "Accepted" => Ok(NotarizationStatus::Success),
+            "Invalid" | "Rejected" => Ok(NotarizationStatus::Invalid),
             _ => Err(de::Error::invalid_value(Unexpected::Str(s), &self)),
         }
     }
@@ -0,0 +1,126 @@
+//! Minimal element tree for the XML documents found in installer packages, e.g. the XAR
+//! table of contents, `Distribution` and `PackageInfo`.
+
+use xml::reader::{EventReader, XmlEvent};
+
+use super::OperationError;
+
+#[derive(Debug, Default)]
+pub(crate) struct Element {
+    pub(crate) name: String,
+    attributes: Vec<(String, String)>,
+    pub(crate) children: Vec<Element>,
+    /// Concatenated text content, excluding that of children.
+    pub(crate) text: String,
+}
+
+impl Element {
+    pub(crate) fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub(crate) fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    pub(crate) fn children_named<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> impl Iterator<Item = &'a Element> + 'a {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    /// Text content of the named child, trimmed.
+    pub(crate) fn child_text(&self, name: &str) -> Option<&str> {
+        self.child(name).map(|child| child.text.trim())
+    }
+
+    /// Elements named `name` anywhere below this one, in document order.
+    pub(crate) fn descendants_named<'a>(&'a self, name: &str, found: &mut Vec<&'a Element>) {
+        for child in &self.children {
+            if child.name == name {
+                found.push(child);
+            }
+            child.descendants_named(name, found);
+        }
+    }
+}
+
+/// Parses `data`, returning the root element. Namespaces are ignored.
+pub(crate) fn parse(data: &[u8]) -> Result<Element, OperationError> {
+    // The parser rejects whitespace before the XML declaration.
+    let start = data
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(data.len());
+
+    let mut stack = vec![Element::default()];
+
+    for event in EventReader::new(&data[start..]) {
+        let event =
+            event.map_err(|err| OperationError::detail("Malformed XML", &err.to_string()))?;
+
+        match event {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => stack.push(Element {
+                name: name.local_name,
+                attributes: attributes
+                    .into_iter()
+                    .map(|attribute| (attribute.name.local_name, attribute.value))
+                    .collect(),
+                ..Element::default()
+            }),
+            XmlEvent::EndElement { .. } => {
+                let element = stack.pop().unwrap();
+                stack.last_mut().unwrap().children.push(element);
+            }
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                stack.last_mut().unwrap().text.push_str(&text)
+            }
+            _ => {}
+        }
+    }
+
+    stack
+        .pop()
+        .and_then(|document| document.children.into_iter().next())
+        .ok_or_else(|| OperationError::new("Malformed XML: no root element"))
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_parse() {
+        let root = super::parse(
+            br#"
+            <?xml version="1.0" encoding="utf-8"?>
+            <pkg-info identifier="com.example" version="1.0">
+                <payload numberOfFiles="10"/>
+                <bundle path="./Foo.app"><![CDATA[x]]></bundle>
+                <bundle path="./Bar.app"/>
+            </pkg-info>"#,
+        )
+        .unwrap();
+
+        assert_eq!(root.name, "pkg-info");
+        assert_eq!(root.attribute("identifier"), Some("com.example"));
+        assert_eq!(root.attribute("missing"), None);
+        assert_eq!(
+            root.children_named("bundle")
+                .filter_map(|bundle| bundle.attribute("path"))
+                .collect::<Vec<_>>(),
+            vec!["./Foo.app", "./Bar.app"]
+        );
+        assert_eq!(root.child_text("bundle"), Some("x"));
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert!(super::parse(b"<a><b></a>").is_err());
+        assert!(super::parse(b"").is_err());
+    }
+}
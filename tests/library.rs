@@ -0,0 +1,54 @@
+use std::path::PathBuf;
+use xcnotary::notarize::{Backend, NotarizeOp};
+use xcnotary::precheck::CheckOutcome;
+use xcnotary::{path_info, precheck, ErrorKind, PathType};
+
+#[test]
+pub fn test_precheck_package() {
+    let path = package_artifacts_path("signed_with_correctly_signed_app");
+    let (path_type, bundle_id) = path_info(&path).unwrap();
+    assert_eq!(path_type, PathType::InstallerPackage);
+    assert_eq!(bundle_id, "signed.with.correctly.signed.app.pkg");
+
+    let report = precheck::run(&path, &path_type, false, &[]).unwrap();
+    assert!(!report.has_failures());
+    assert!(report
+        .results()
+        .iter()
+        .any(|result| matches!(result.outcome, CheckOutcome::Pass)));
+
+    let report = precheck::run(&package_artifacts_path("unsigned"), &path_type, true, &[]).unwrap();
+    assert!(report.has_failures());
+    let err = report.into_result().unwrap_err();
+    assert!(precheck::is_check_failure(&*err));
+}
+
+#[test]
+pub fn test_notarize_missing_credentials() {
+    let err = NotarizeOp::builder(package_artifacts_path("unsigned"))
+        .backend(Backend::Altool)
+        .build()
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), ErrorKind::Credentials);
+}
+
+#[test]
+pub fn test_notarize_unsupported_input() {
+    let err = NotarizeOp::builder("Foo.txt")
+        .apple_id("dev@example.com", "AC_PASSWORD")
+        .build()
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), ErrorKind::Other);
+}
+
+fn package_artifacts_path(name: &str) -> PathBuf {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("tests");
+    path.push("generated_artifacts");
+    path.push("pkg");
+    path.push(name);
+    path.set_extension("pkg");
+    path
+}
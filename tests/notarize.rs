@@ -0,0 +1,171 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+use std::path::PathBuf;
+
+static REQUEST_UUID: &str = "c0dec0de-1234-5678-1234-b4d961a1d182";
+
+#[test]
+pub fn test_status_missing_credentials() {
+    Command::cargo_bin("xcnotary")
+        .unwrap()
+        .args(["status", REQUEST_UUID, "--backend", "altool"])
+        .assert()
+        .code(4)
+        .stderr(predicate::str::contains("--developer-account"));
+}
+
+#[test]
+pub fn test_log_missing_api_key() {
+    Command::cargo_bin("xcnotary")
+        .unwrap()
+        .args(["log", REQUEST_UUID, "--backend", "api"])
+        .env_remove("XCNOTARY_API_TOKEN")
+        .assert()
+        .code(4)
+        .stderr(predicate::str::contains("--api-key"));
+}
+
+#[test]
+pub fn test_status_json_error() {
+    let output = Command::cargo_bin("xcnotary")
+        .unwrap()
+        .args([
+            "status",
+            REQUEST_UUID,
+            "--backend",
+            "altool",
+            "--output",
+            "json",
+        ])
+        .assert()
+        .code(4)
+        .get_output()
+        .stdout
+        .clone();
+
+    let document: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    assert!(document["error"]
+        .as_str()
+        .unwrap()
+        .contains("--developer-account"));
+    assert!(document["request_id"].is_null());
+    assert_eq!(document["prechecks"], serde_json::json!([]));
+}
+
+#[test]
+pub fn test_notarize_prechecks_every_input() {
+    let pattern = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("generated_artifacts")
+        .join("pkg")
+        .join("signed_with_*.pkg");
+
+    let state_dir = tempfile::tempdir().unwrap();
+
+    // The input failing its pre-checks is not submitted, while the other one is.
+    let output = Command::cargo_bin("xcnotary")
+        .unwrap()
+        .args(["notarize", "--backend", "altool", "-d", "dev@example.com"])
+        .args(["-k", "AC_PASSWORD", "--output", "json", "--state-dir"])
+        .arg(state_dir.path())
+        .arg(pattern.as_os_str())
+        .assert()
+        .failure()
+        .get_output()
+        .stdout
+        .clone();
+
+    let document: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    assert!(document.get("prechecks").is_none());
+    let inputs = document["inputs"].as_array().unwrap();
+    assert_eq!(inputs.len(), 2);
+
+    assert!(inputs[0]["path"]
+        .as_str()
+        .unwrap()
+        .ends_with("signed_with_correctly_signed_app.pkg"));
+    let prechecks = inputs[0]["prechecks"].as_array().unwrap();
+    assert!(!prechecks.is_empty());
+    assert!(prechecks.iter().all(|check| check["result"] != "fail"));
+
+    assert!(inputs[1]["path"]
+        .as_str()
+        .unwrap()
+        .ends_with("signed_with_no_secure_timestamp_app.pkg"));
+    assert!(inputs[1]["prechecks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|check| check["result"] == "fail"));
+    assert!(inputs[1]["error"]
+        .as_str()
+        .unwrap()
+        .contains("missing a secure timestamp"));
+    assert!(inputs[1]["request_id"].is_null());
+}
+
+#[test]
+pub fn test_notarize_unmatched_pattern() {
+    Command::cargo_bin("xcnotary")
+        .unwrap()
+        .args(["notarize", "--backend", "altool", "-d", "dev@example.com"])
+        .args(["-k", "AC_PASSWORD", "Missing-*.pkg"])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "No input found matching Missing-*.pkg",
+        ));
+}
+
+#[test]
+pub fn test_history_from_journal() {
+    let state_dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        state_dir.path().join("journal.jsonl"),
+        format!(
+            concat!(
+                r#"{{"request_id":"{0}","artifact_path":"/tmp/Foo.dmg","sha256":null,"bundle_id":"Foo.dmg","submitted_at":"2021-06-08T20:57:18Z","completed_at":null,"status":null,"log_path":null}}"#,
+                "\n",
+                r#"{{"request_id":"{0}","artifact_path":"/tmp/Foo.dmg","sha256":null,"bundle_id":"Foo.dmg","submitted_at":"2021-06-08T20:57:18Z","completed_at":"2021-06-08T21:03:02Z","status":"success","log_path":null}}"#,
+                "\n"
+            ),
+            REQUEST_UUID
+        ),
+    )
+    .unwrap();
+
+    Command::cargo_bin("xcnotary")
+        .unwrap()
+        .args(["history", "--state-dir"])
+        .arg(state_dir.path())
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::is_match(format!(
+                r"{}\s+2021-06-08T20:57:18Z\s+success\s+/tmp/Foo.dmg",
+                REQUEST_UUID
+            ))
+            .unwrap(),
+        );
+}
+
+#[test]
+pub fn test_resume_without_unfinished_submissions() {
+    let state_dir = tempfile::tempdir().unwrap();
+
+    Command::cargo_bin("xcnotary")
+        .unwrap()
+        .args([
+            "wait",
+            "--resume",
+            "--backend",
+            "altool",
+            "-d",
+            "dev@example.com",
+        ])
+        .args(["-k", "AC_PASSWORD", "--state-dir"])
+        .arg(state_dir.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No unfinished submissions"));
+}
@@ -11,7 +11,8 @@ pub fn test_precheck_package_pass() {
         .arg(package_artifacts_path("signed_with_correctly_signed_app").as_os_str())
         .assert()
         .success()
-        .stdout(predicate::str::contains("not all checks were performed"));
+        .stdout(predicate::str::contains("Secure timestamp"))
+        .stdout(predicate::str::contains("not all checks were performed").not());
 }
 
 #[test]
@@ -21,10 +22,38 @@ pub fn test_precheck_package_fail() {
         .arg("precheck")
         .arg(package_artifacts_path("unsigned").as_os_str())
         .assert()
-        .failure()
+        .code(3)
         .stderr(predicate::str::contains("Package is not signed"));
 }
 
+#[test]
+pub fn test_precheck_profile() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        temp_dir.path().join("xcnotary.toml"),
+        "[profile.default]\nskip-check = [\"developer_id\"]\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("xcnotary")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .arg("precheck")
+        .arg(package_artifacts_path("unsigned").as_os_str())
+        .assert()
+        .stdout(predicate::str::contains("Developer ID signing (skipped)"))
+        .stderr(predicate::str::contains("Package is not signed").not());
+
+    Command::cargo_bin("xcnotary")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["precheck", "--profile", "release"])
+        .arg(package_artifacts_path("unsigned").as_os_str())
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("No profile named release"));
+}
+
 fn package_artifacts_path(name: &str) -> PathBuf {
     let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
     path.push("tests");